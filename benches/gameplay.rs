@@ -0,0 +1,172 @@
+//! Benchmarks of the gameplay systems, run headless on a bare `App`.
+
+use bevy::asset::AssetPlugin;
+use bevy::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use unfair_advantage_lib::simulation::SimulationClock;
+use unfair_advantage_lib::snake_grid::{Arena, Direction, Occupancy, Position, Snake};
+use unfair_advantage_lib::{
+    occupy_new_snakes, position_translation, snake_movement, snake_split, spawn_snake_entities,
+    LastTailPosition, PreviousPosition, SmoothMotion, SnakeSeveredEvent, SnakeSplitEvent,
+    SnakeTimer,
+};
+
+criterion_group!(
+    benches,
+    snake_movement_by_length,
+    position_translation_by_entities,
+    snake_splitting,
+    pathfinding_by_arena_size
+);
+criterion_main!(benches);
+
+fn configure(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>) {
+    group.warm_up_time(std::time::Duration::from_millis(500));
+    group.measurement_time(std::time::Duration::from_secs(4));
+}
+
+/// A headless app with the snake resources in place and snakes moving on every update.
+fn snake_app(arena: Arena) -> App {
+    let clock = SimulationClock::default();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Image>()
+        .insert_resource(arena)
+        .insert_resource(Occupancy::<Entity>::new(arena))
+        .insert_resource(SnakeTimer(Timer::new(clock.step(), true)))
+        .insert_resource(clock)
+        .init_resource::<LastTailPosition>()
+        .add_event::<SnakeSplitEvent>()
+        .add_event::<SnakeSeveredEvent>();
+    app
+}
+
+/// Spawns a straight snake of `len` cells along the bottom row, returning its head.
+fn spawn_straight_snake(app: &mut App, len: usize) -> Entity {
+    let arena = *app.world.get_resource::<Arena>().unwrap();
+    let snake = Snake::straight(
+        Position::new(len as i32 - 1, 0),
+        Direction::Right,
+        len,
+        &arena,
+    );
+    let asset_server = app.world.get_resource::<AssetServer>().unwrap().clone();
+    let mut command_queue = bevy::ecs::system::CommandQueue::default();
+    let head = {
+        let mut commands = Commands::new(&mut command_queue, &app.world);
+        spawn_snake_entities(&mut commands, &asset_server, snake)
+    };
+    command_queue.apply(&mut app.world);
+    head
+}
+
+fn snake_movement_by_length(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("snake_movement");
+    configure(&mut group);
+
+    for len in [10, 100, 1_000, 10_000] {
+        // Wide enough that the snake never catches up with its own tail
+        let mut app = snake_app(Arena::new(len as u32 * 2, 16));
+        app.add_system(occupy_new_snakes.before("movement"))
+            .add_system(snake_movement.label("movement"));
+        spawn_straight_snake(&mut app, len);
+        app.update();
+
+        group.bench_function(format!("{}_segments", len), |bencher| {
+            bencher.iter(|| app.update());
+        });
+    }
+
+    group.finish();
+}
+
+fn position_translation_by_entities(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("position_translation");
+    configure(&mut group);
+
+    for entity_count in [1_000, 10_000, 100_000] {
+        let mut world = World::default();
+        world.insert_resource(Arena::new(28, 14));
+        world.insert_resource(SnakeTimer::new());
+        world.insert_resource(SimulationClock::default());
+        world.insert_resource(SmoothMotion(true));
+        world.spawn_batch((0..entity_count).map(|index| {
+            let position = Position::new(index % 28, index / 28 % 14);
+            (
+                position,
+                PreviousPosition(Arena::new(28, 14).neighbour(position, Direction::Left)),
+                Transform::default(),
+            )
+        }));
+        let mut stage = SystemStage::single(position_translation);
+
+        group.bench_function(format!("{}_entities", entity_count), |bencher| {
+            bencher.iter(|| stage.run(&mut world));
+        });
+    }
+
+    group.finish();
+}
+
+fn snake_splitting(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("snake_splitting");
+    configure(&mut group);
+
+    for len in [10, 1_000, 10_000] {
+        group.bench_function(format!("{}_segments_in_half", len), |bencher| {
+            bencher.iter_batched_ref(
+                || {
+                    let mut app = snake_app(Arena::new(len as u32 * 2, 16));
+                    app.add_system(snake_split);
+                    let head = spawn_straight_snake(&mut app, len);
+                    app.world
+                        .get_resource_mut::<Events<SnakeSplitEvent>>()
+                        .unwrap()
+                        .send(SnakeSplitEvent {
+                            snake: head,
+                            index: len / 2,
+                        });
+                    app
+                },
+                |app| app.update(),
+                BatchSize::PerIteration,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+/// An arena crossed by a wall of snakes every few columns.
+fn cluttered_arena(size: u32) -> Occupancy<u32> {
+    let arena = Arena::new(size, size);
+    let mut occupancy = Occupancy::new(arena);
+    for (snake, x) in (2..size as i32 - 2).step_by(4).enumerate() {
+        // Leave gaps at alternating heights so paths have to weave between the snakes
+        let gap = if snake % 2 == 0 { 0 } else { size as i32 / 2 };
+        for y in (0..size as i32).filter(|y| *y != gap) {
+            occupancy.insert(Position::new(x, y), snake as u32);
+        }
+    }
+    occupancy
+}
+
+fn pathfinding_by_arena_size(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("pathfinding");
+    configure(&mut group);
+
+    for size in [32, 128, 512] {
+        let occupancy = cluttered_arena(size);
+        // Half way across is as far as you can get on a wrapping arena
+        let from = Position::new(0, size as i32 / 4);
+        let to = Position::new(size as i32 / 2, size as i32 / 4);
+        assert!(occupancy.find_path(from, to).is_some());
+
+        group.bench_function(format!("{}x{}_arena", size, size), |bencher| {
+            bencher.iter(|| occupancy.find_path(black_box(from), black_box(to)));
+        });
+    }
+
+    group.finish();
+}
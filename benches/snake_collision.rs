@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use unfair_advantage_lib::snake_grid::{Arena, Direction, Occupancy, Position, Snake};
+
+criterion_group!(benches, snake_collision);
+criterion_main!(benches);
+
+const ARENA: Arena = Arena {
+    width: 256,
+    height: 256,
+};
+
+/// A snake of `len` cells coiled back and forth across the bottom of the arena,
+/// with its head about to move into free space.
+fn coiled_snake(len: usize) -> Snake {
+    let width = ARENA.width as usize;
+    let mut body: Vec<Position> = (0..len)
+        .map(|index| {
+            let row = index / width;
+            let column = index % width;
+            let x = if row % 2 == 0 {
+                column
+            } else {
+                width - 1 - column
+            };
+            Position::new(x as i32, row as i32)
+        })
+        .collect();
+    body.reverse();
+    let head = body[0];
+    let neck = body[1];
+    let direction = ARENA.direction_between(neck, head).unwrap_or(Direction::Up);
+    Snake::new(body, direction)
+}
+
+fn snake_collision(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("snake_collision");
+    group.warm_up_time(std::time::Duration::from_millis(500));
+    group.measurement_time(std::time::Duration::from_secs(4));
+
+    for len in [1_000, 4_000, 16_000] {
+        let snake = coiled_snake(len);
+        let mut occupancy = Occupancy::new(ARENA);
+        occupancy.occupy_snake(&snake, 0u32);
+        let next = ARENA.neighbour(snake.head(), snake.direction());
+
+        group.bench_function(format!("linear_contains_{}_segments", len), |bencher| {
+            bencher.iter(|| snake.body().contains(black_box(&next)));
+        });
+        group.bench_function(format!("occupancy_lookup_{}_segments", len), |bencher| {
+            bencher.iter(|| occupancy.get(black_box(next)));
+        });
+        group.bench_function(format!("advance_{}_segments", len), |bencher| {
+            bencher.iter_batched_ref(
+                || (snake.clone(), occupancy.clone()),
+                |(snake, occupancy)| occupancy.advance_snake(snake, 0),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
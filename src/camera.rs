@@ -0,0 +1,153 @@
+//! Fits the game world to whatever window it is shown in.
+//!
+//! The world is laid out for a fixed [`VIEW_WIDTH`] × [`VIEW_HEIGHT`] view, centred on the origin.
+//! The game camera zooms so that the whole view fits inside the window, and whatever is left
+//! over on the sides or at the top and bottom is covered by black letterbox bars.
+//! Grid cells therefore land on the same world coordinates at any window size.
+
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+
+/// The width of the world area that is always visible, in world units.
+pub const VIEW_WIDTH: f32 = 1920.0;
+/// The height of the world area that is always visible, in world units.
+pub const VIEW_HEIGHT: f32 = 1080.0;
+
+/// How far the letterbox bars reach out from the edges of the view.
+const BAR_SIZE: f32 = 10_000.0;
+
+/// The camera that looks at the game world.
+#[derive(Component)]
+pub struct GameCamera;
+
+/// Covers the part of the window outside the view.
+#[derive(Component)]
+struct LetterboxBar;
+
+/// Keeps the game camera fitted to the window and handles fullscreen toggling.
+pub struct GameCameraPlugin;
+
+impl Plugin for GameCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(fit_view_to_window)
+            .add_system(toggle_fullscreen);
+    }
+}
+
+/// Spawns the game camera along with its letterbox bars.
+pub fn spawn_game_camera(commands: &mut Commands) -> Entity {
+    // Left, right, top and bottom, as (offset, size) pairs
+    let bars = [
+        (
+            Vec2::new(-(VIEW_WIDTH + BAR_SIZE) / 2.0, 0.0),
+            Vec2::new(BAR_SIZE, BAR_SIZE * 2.0),
+        ),
+        (
+            Vec2::new((VIEW_WIDTH + BAR_SIZE) / 2.0, 0.0),
+            Vec2::new(BAR_SIZE, BAR_SIZE * 2.0),
+        ),
+        (
+            Vec2::new(0.0, (VIEW_HEIGHT + BAR_SIZE) / 2.0),
+            Vec2::new(BAR_SIZE * 2.0, BAR_SIZE),
+        ),
+        (
+            Vec2::new(0.0, -(VIEW_HEIGHT + BAR_SIZE) / 2.0),
+            Vec2::new(BAR_SIZE * 2.0, BAR_SIZE),
+        ),
+    ];
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(GameCamera)
+        .with_children(|camera| {
+            for (offset, size) in bars {
+                camera
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::BLACK,
+                            custom_size: Some(size),
+                            ..Default::default()
+                        },
+                        // Just in front of the camera, so they cover everything else
+                        transform: Transform::from_translation(offset.extend(-1.0)),
+                        ..Default::default()
+                    })
+                    .insert(LetterboxBar);
+            }
+        })
+        .id()
+}
+
+/// How many world units one logical pixel covers when the view is fitted to a window of this size.
+pub fn view_scale(window_width: f32, window_height: f32) -> f32 {
+    if window_width <= 0.0 || window_height <= 0.0 {
+        return 1.0;
+    }
+    (VIEW_WIDTH / window_width).max(VIEW_HEIGHT / window_height)
+}
+
+/// Where a point in the window, such as the cursor, is in the game world.
+pub fn window_to_world(window: &Window, point: Vec2) -> Vec2 {
+    let size = Vec2::new(window.width(), window.height());
+    (point - size / 2.0) * view_scale(size.x, size.y)
+}
+
+fn fit_view_to_window(
+    windows: Option<Res<Windows>>,
+    mut cameras: Query<&mut OrthographicProjection, With<GameCamera>>,
+) {
+    let window = match windows.as_ref().and_then(|windows| windows.get_primary()) {
+        Some(window) => window,
+        None => return,
+    };
+    let scale = view_scale(window.width(), window.height());
+    for mut projection in cameras.iter_mut() {
+        // Only touch the projection when needed, since changing it recomputes the camera
+        if (projection.scale - scale).abs() > f32::EPSILON {
+            projection.scale = scale;
+        }
+    }
+}
+
+fn toggle_fullscreen(keyboard_input: Res<Input<KeyCode>>, windows: Option<ResMut<Windows>>) {
+    let alt_enter = keyboard_input.just_pressed(KeyCode::Return)
+        && (keyboard_input.pressed(KeyCode::LAlt) || keyboard_input.pressed(KeyCode::RAlt));
+    if !keyboard_input.just_pressed(KeyCode::F11) && !alt_enter {
+        return;
+    }
+    let mut windows = match windows {
+        Some(windows) => windows,
+        None => return,
+    };
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = match window.mode() {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        };
+        window.set_mode(mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_whole_view_always_fits() {
+        for (width, height) in [
+            (1920.0, 1080.0),
+            (1280.0, 1024.0),
+            (3440.0, 1440.0),
+            (800.0, 600.0),
+        ] {
+            let scale = view_scale(width, height);
+            assert!(width * scale >= VIEW_WIDTH - 0.01);
+            assert!(height * scale >= VIEW_HEIGHT - 0.01);
+            // One of the two sides fits exactly, the other is letterboxed
+            assert!(
+                (width * scale - VIEW_WIDTH).abs() < 0.01
+                    || (height * scale - VIEW_HEIGHT).abs() < 0.01
+            );
+        }
+        assert_eq!(view_scale(960.0, 540.0), 2.0);
+    }
+}
@@ -0,0 +1,147 @@
+//! Co-op matches: two slayers side by side against a snake that steers itself.
+//!
+//! Each slayer keeps their own lives and score. A slayer scores for every sword hit that
+//! cuts the snake, and the snake only wins once both slayers are out of lives. The lives
+//! and scores of both players are shown on a HUD for the whole match.
+
+use bevy::prelude::*;
+
+use crate::handicap::ExtraLives;
+use crate::simulation::{in_state, SimulationStage, SimulationSystem};
+use crate::{AppState, MatchConfig, MatchMode, SlayerPlayer, SnakeAction, SwordHitEvent};
+
+/// How many lives each slayer gets on top of their first in a co-op match.
+pub const CO_OP_EXTRA_LIVES: u8 = 2;
+/// What a sword hit that cuts the snake is worth.
+pub const SEVER_POINTS: u32 = 10;
+
+/// The score of each slayer in a co-op match, in player order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SlayerScores(pub [u32; 2]);
+
+#[derive(Component)]
+struct OnCoOpHud;
+
+/// The HUD line for one player.
+#[derive(Component)]
+struct PlayerStatus(usize);
+
+/// Keeps score and shows how both slayers are doing in co-op matches.
+pub struct CoOpPlugin;
+
+impl Plugin for CoOpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SlayerScores>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(reset_scores)
+                    .with_system(setup_co_op_hud),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(update_co_op_hud),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_co_op_hud),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(score_sword_hits.after(SnakeAction::Movement)),
+            );
+    }
+}
+
+fn reset_scores(mut scores: ResMut<SlayerScores>) {
+    *scores = SlayerScores::default();
+}
+
+fn score_sword_hits(
+    mut hits: EventReader<SwordHitEvent>,
+    mut scores: ResMut<SlayerScores>,
+    slayers: Query<&SlayerPlayer>,
+) {
+    for hit in hits.iter() {
+        // Hitting the head does not cut anything off
+        if hit.index == 0 {
+            continue;
+        }
+        if let Ok(player) = slayers.get(hit.slayer) {
+            if let Some(score) = scores.0.get_mut(player.0) {
+                *score += SEVER_POINTS;
+            }
+        }
+    }
+}
+
+fn setup_co_op_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<MatchConfig>,
+) {
+    if config.mode != MatchMode::CoOp {
+        return;
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexStart,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(OnCoOpHud)
+        .with_children(|parent| {
+            for player in 0..config.mode.slayers() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            "",
+                            TextStyle {
+                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(PlayerStatus(player));
+            }
+        });
+}
+
+fn update_co_op_hud(
+    scores: Res<SlayerScores>,
+    slayers: Query<(&SlayerPlayer, &ExtraLives)>,
+    mut lines: Query<(&PlayerStatus, &mut Text)>,
+) {
+    for (status, mut text) in lines.iter_mut() {
+        let player = status.0;
+        let lives = slayers
+            .iter()
+            .find(|(slayer, _)| slayer.0 == player)
+            .map(|(_, extra)| extra.0 + 1);
+        let score = scores.0.get(player).copied().unwrap_or_default();
+        text.sections[0].value = match lives {
+            Some(lives) => format!("P{} lives {} score {}", player + 1, lives, score),
+            None => format!("P{} out score {}", player + 1, score),
+        };
+    }
+}
+
+fn cleanup_co_op_hud(mut commands: Commands, hud: Query<Entity, With<OnCoOpHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,529 @@
+//! A drop-down developer console for setting up test scenarios by hand.
+//!
+//! Only built with the `dev_console` cargo feature. Press the backtick key to open or close it,
+//! then type `help` for the list of commands. While the console is open it swallows the keyboard,
+//! so typing does not steer the slayer or pause the game.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+use heron::prelude::*;
+
+use crate::snake_grid::{Arena, Direction, Position, Snake};
+use crate::{
+    cell_to_world, spawn_snake_entities, AppState, Invulnerable, Slayer, SnakeSplitEvent,
+    SnakeTimer,
+};
+
+/// How many lines of output stay visible.
+const VISIBLE_LINES: usize = 14;
+
+const HELP: &str = "\
+spawn <length> [x y]   spawn a snake heading right, with its head at x y
+snakes                 list the snakes, for use with split
+speed <seconds>        how long snakes take to move one cell
+god [on|off]           stop snakes from killing the slayer
+tp <x> <y>             teleport the slayer to a cell
+gravity <x> <y>        set the gravity, in world units per second squared
+split <index> [snake]  cut a snake in front of the segment at index
+state <main|pause|one|two|editor|handicaps|intermission>  switch to another screen
+clear                  clear the console";
+
+/// A command typed into the console.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleCommand {
+    Help,
+    Clear,
+    Spawn {
+        length: usize,
+        head: Option<Position>,
+    },
+    Snakes,
+    Speed(f32),
+    /// Turn god mode on or off, or toggle it if `None`.
+    God(Option<bool>),
+    Teleport(Position),
+    Gravity(Vec2),
+    Split {
+        index: usize,
+        /// The position of the snake in the `snakes` listing.
+        snake: usize,
+    },
+    State(AppState),
+}
+
+fn parse_arg<T: FromStr>(arg: Option<&str>, name: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("missing {}", name))?;
+    arg.parse()
+        .map_err(|_| format!("{} is not a valid {}", arg, name))
+}
+
+fn parse_position<'a>(args: &mut impl Iterator<Item = &'a str>) -> Result<Position, String> {
+    Ok(Position::new(
+        parse_arg(args.next(), "x")?,
+        parse_arg(args.next(), "y")?,
+    ))
+}
+
+impl FromStr for ConsoleCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut args = line.split_whitespace();
+        let name = args
+            .next()
+            .ok_or_else(|| "type help for a list of commands".to_string())?;
+        let command = match name {
+            "help" => Self::Help,
+            "clear" => Self::Clear,
+            "spawn" => {
+                let length = parse_arg(args.next(), "length")?;
+                if length == 0 {
+                    return Err("a snake needs at least a head".to_string());
+                }
+                let head = match args.next() {
+                    Some(x) => Some(Position::new(
+                        parse_arg(Some(x), "x")?,
+                        parse_arg(args.next(), "y")?,
+                    )),
+                    None => None,
+                };
+                Self::Spawn { length, head }
+            }
+            "snakes" => Self::Snakes,
+            "speed" => {
+                let seconds: f32 = parse_arg(args.next(), "number of seconds")?;
+                if !seconds.is_finite() || seconds <= 0.0 {
+                    return Err("snakes need some time to move".to_string());
+                }
+                Self::Speed(seconds)
+            }
+            "god" => Self::God(match args.next() {
+                None => None,
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                Some(other) => return Err(format!("{} is not on or off", other)),
+            }),
+            "tp" | "teleport" => Self::Teleport(parse_position(&mut args)?),
+            "gravity" => Self::Gravity(Vec2::new(
+                parse_arg(args.next(), "x")?,
+                parse_arg(args.next(), "y")?,
+            )),
+            "split" => Self::Split {
+                index: parse_arg(args.next(), "index")?,
+                snake: match args.next() {
+                    Some(snake) => parse_arg(Some(snake), "snake")?,
+                    None => 0,
+                },
+            },
+            "state" => Self::State(match args.next() {
+                Some("main") => AppState::MainMenu,
+                Some("pause") => AppState::PauseMenu,
+                Some("one") => AppState::InOnePlayerGame,
+                Some("two") => AppState::InTwoPlayerGame,
+                Some("editor") => AppState::LevelEditor,
+                Some("handicaps") => AppState::HandicapMenu,
+                Some("intermission") => AppState::WaveIntermission,
+                Some(other) => return Err(format!("there is no {} state", other)),
+                None => return Err("missing state".to_string()),
+            }),
+            other => return Err(format!("unknown command {}", other)),
+        };
+        match args.next() {
+            Some(extra) => Err(format!("unexpected {}", extra)),
+            None => Ok(command),
+        }
+    }
+}
+
+/// What has been typed into the console, and what it printed back.
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: Vec<String>,
+    /// Lines entered since the last time commands were run.
+    submitted: Vec<String>,
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+    }
+
+    /// Queues `line` to be run as if it had been typed in.
+    pub fn submit(&mut self, line: impl Into<String>) {
+        self.submitted.push(line.into());
+    }
+}
+
+/// Marks the console's camera and the root of its text.
+#[derive(Component)]
+struct ConsoleUi;
+
+#[derive(Component)]
+struct ConsoleCamera;
+
+#[derive(Component)]
+struct ConsoleText;
+
+/// Adds the developer console.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_system_to_stage(CoreStage::PreUpdate, console_input.after(InputSystem))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                run_console_commands.exclusive_system().at_end(),
+            )
+            .add_system(show_console);
+    }
+}
+
+/// Types into the console, and hides the keyboard from the rest of the game while it is open.
+fn console_input(
+    mut console: ResMut<Console>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+) {
+    // Characters typed while the console was closed are not for it, but still need reading
+    let typed: Vec<char> = characters.iter().map(|character| character.char).collect();
+    if keyboard_input.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+    } else if console.open {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            console.open = false;
+        } else if keyboard_input.just_pressed(KeyCode::Return) {
+            let line = std::mem::take(&mut console.input);
+            console.submit(line);
+        } else if keyboard_input.just_pressed(KeyCode::Back) {
+            console.input.pop();
+        }
+        let typed = typed
+            .into_iter()
+            .filter(|character| *character != '`' && !character.is_control());
+        console.input.extend(typed);
+    }
+
+    if console.open || keyboard_input.just_pressed(KeyCode::Grave) {
+        let pressed: Vec<KeyCode> = keyboard_input.get_pressed().copied().collect();
+        for key in pressed {
+            keyboard_input.reset(key);
+        }
+        keyboard_input.clear();
+    }
+}
+
+fn run_console_commands(world: &mut World) {
+    let submitted = std::mem::take(&mut world.get_resource_mut::<Console>().unwrap().submitted);
+    for line in submitted {
+        world
+            .get_resource_mut::<Console>()
+            .unwrap()
+            .print(format!("> {}", line));
+        let reply = match line.parse::<ConsoleCommand>() {
+            Ok(command) => run_command(world, command),
+            Err(error) => error,
+        };
+        if !reply.is_empty() {
+            world.get_resource_mut::<Console>().unwrap().print(reply);
+        }
+    }
+}
+
+/// The snakes, in the order they are listed by the `snakes` command.
+fn list_snakes(world: &mut World) -> Vec<(Entity, Snake)> {
+    let mut snakes: Vec<(Entity, Snake)> = world
+        .query::<(Entity, &Snake)>()
+        .iter(world)
+        .map(|(head, snake)| (head, snake.clone()))
+        .collect();
+    snakes.sort_by_key(|(head, _)| *head);
+    snakes
+}
+
+fn in_match(world: &mut World) -> bool {
+    world
+        .query_filtered::<(), With<Slayer>>()
+        .iter(world)
+        .next()
+        .is_some()
+        || world.query::<&Snake>().iter(world).next().is_some()
+}
+
+/// Runs `command`, returning what to print in reply.
+fn run_command(world: &mut World, command: ConsoleCommand) -> String {
+    let arena = *world.get_resource::<Arena>().unwrap();
+    match command {
+        ConsoleCommand::Help => HELP.to_string(),
+        ConsoleCommand::Clear => {
+            world.get_resource_mut::<Console>().unwrap().output.clear();
+            String::new()
+        }
+        ConsoleCommand::Spawn { length, head } => {
+            if !in_match(world) {
+                return "start a game first".to_string();
+            }
+            let head = head
+                .unwrap_or_else(|| Position::new(arena.width as i32 / 2, arena.height as i32 / 2));
+            if !arena.contains(head) {
+                return format!("{},{} is outside the arena", head.x, head.y);
+            }
+            let asset_server = world.get_resource::<AssetServer>().unwrap().clone();
+            let mut queue = CommandQueue::default();
+            let mut commands = Commands::new(&mut queue, world);
+            let snake = Snake::straight(head, Direction::Right, length, &arena);
+            spawn_snake_entities(&mut commands, &asset_server, snake);
+            queue.apply(world);
+            format!("spawned a snake of length {}", length)
+        }
+        ConsoleCommand::Snakes => {
+            let snakes = list_snakes(world);
+            if snakes.is_empty() {
+                return "no snakes".to_string();
+            }
+            snakes
+                .iter()
+                .enumerate()
+                .map(|(number, (_, snake))| {
+                    let head = snake.head();
+                    format!(
+                        "{}: head at {},{}, length {}",
+                        number,
+                        head.x,
+                        head.y,
+                        snake.len()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        ConsoleCommand::Speed(seconds) => {
+            let mut timer = world.get_resource_mut::<SnakeTimer>().unwrap();
+            timer.0.set_duration(Duration::from_secs_f32(seconds));
+            format!("snakes move every {} seconds", seconds)
+        }
+        ConsoleCommand::God(enable) => {
+            let slayers: Vec<(Entity, bool)> = world
+                .query_filtered::<(Entity, Option<&Invulnerable>), With<Slayer>>()
+                .iter(world)
+                .map(|(slayer, invulnerable)| (slayer, invulnerable.is_some()))
+                .collect();
+            if slayers.is_empty() {
+                return "there is no slayer".to_string();
+            }
+            let enable = enable.unwrap_or(!slayers[0].1);
+            for (slayer, _) in slayers {
+                if enable {
+                    world.entity_mut(slayer).insert(Invulnerable);
+                } else {
+                    world.entity_mut(slayer).remove::<Invulnerable>();
+                }
+            }
+            format!("god mode {}", if enable { "on" } else { "off" })
+        }
+        ConsoleCommand::Teleport(cell) => {
+            if !arena.contains(cell) {
+                return format!("{},{} is outside the arena", cell.x, cell.y);
+            }
+            let to = cell_to_world(cell.x as f32, cell.y as f32);
+            let mut slayers =
+                world.query_filtered::<(&mut Transform, Option<&mut Velocity>), With<Slayer>>();
+            let mut moved = false;
+            for (mut transform, velocity) in slayers.iter_mut(world) {
+                transform.translation.x = to.x;
+                transform.translation.y = to.y;
+                if let Some(mut velocity) = velocity {
+                    *velocity = Velocity::default();
+                }
+                moved = true;
+            }
+            if moved {
+                format!("teleported to {},{}", cell.x, cell.y)
+            } else {
+                "there is no slayer".to_string()
+            }
+        }
+        ConsoleCommand::Gravity(gravity) => {
+            world.insert_resource(Gravity::from(gravity.extend(0.0)));
+            format!("gravity is now {},{}", gravity.x, gravity.y)
+        }
+        ConsoleCommand::Split { index, snake } => {
+            let snakes = list_snakes(world);
+            let (head, body) = match snakes.get(snake) {
+                Some(snake) => snake,
+                None => return format!("there is no snake {}", snake),
+            };
+            if index == 0 || index >= body.len() {
+                return format!("index must be between 1 and {}", body.len() - 1);
+            }
+            world
+                .get_resource_mut::<Events<SnakeSplitEvent>>()
+                .unwrap()
+                .send(SnakeSplitEvent {
+                    snake: *head,
+                    index,
+                });
+            format!("split snake {} at {}", snake, index)
+        }
+        ConsoleCommand::State(state) => {
+            let mut current = world.get_resource_mut::<State<AppState>>().unwrap();
+            let result = if state == AppState::PauseMenu {
+                current.push(state.clone())
+            } else {
+                current.replace(state.clone())
+            };
+            match result {
+                Ok(()) => format!("switching to {:?}", state),
+                Err(error) => format!("cannot switch to {:?}: {:?}", state, error),
+            }
+        }
+    }
+}
+
+/// Shows or hides the console, and keeps its text up to date.
+fn show_console(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    console: Res<Console>,
+    ui: Query<Entity, With<ConsoleUi>>,
+    cameras: Query<(), With<ConsoleCamera>>,
+    mut texts: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.open {
+        for entity in ui.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if cameras.is_empty() {
+        commands
+            .spawn_bundle(UiCameraBundle::default())
+            .insert(ConsoleCamera)
+            .insert(ConsoleUi);
+    }
+
+    let first = console.output.len().saturating_sub(VISIBLE_LINES);
+    let mut output = console.output[first..].join("\n");
+    output.push('\n');
+    let input = format!("> {}_", console.input);
+
+    // Leaving a game despawns every loose entity, so the text may need to be put back
+    if texts.is_empty() {
+        let style = TextStyle {
+            font: asset_server.load("fonts/GoMono-Bold.ttf"),
+            font_size: 20.0,
+            color: Color::rgb(0.8, 1.0, 0.8),
+        };
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Px(0.0),
+                        top: Val::Px(0.0),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Percent(100.0), Val::Percent(45.0)),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    justify_content: JustifyContent::FlexEnd,
+                    padding: Rect::all(Val::Px(8.0)),
+                    ..Default::default()
+                },
+                color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                ..Default::default()
+            })
+            .insert(ConsoleUi)
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text {
+                            sections: vec![
+                                TextSection {
+                                    value: output,
+                                    style: style.clone(),
+                                },
+                                TextSection {
+                                    value: input,
+                                    style,
+                                },
+                            ],
+                            alignment: Default::default(),
+                        },
+                        ..Default::default()
+                    })
+                    .insert(ConsoleText);
+            });
+    } else if console.is_changed() {
+        for mut text in texts.iter_mut() {
+            text.sections[0].value = output.clone();
+            text.sections[1].value = input.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            "spawn 12 3 4".parse(),
+            Ok(ConsoleCommand::Spawn {
+                length: 12,
+                head: Some(Position::new(3, 4))
+            })
+        );
+        assert_eq!(
+            "spawn 5".parse(),
+            Ok(ConsoleCommand::Spawn {
+                length: 5,
+                head: None
+            })
+        );
+        assert_eq!("god".parse(), Ok(ConsoleCommand::God(None)));
+        assert_eq!("god off".parse(), Ok(ConsoleCommand::God(Some(false))));
+        assert_eq!(
+            "  tp 3   7 ".parse(),
+            Ok(ConsoleCommand::Teleport(Position::new(3, 7)))
+        );
+        assert_eq!(
+            "gravity 0 -9.5".parse(),
+            Ok(ConsoleCommand::Gravity(Vec2::new(0.0, -9.5)))
+        );
+        assert_eq!(
+            "split 3".parse(),
+            Ok(ConsoleCommand::Split { index: 3, snake: 0 })
+        );
+        assert_eq!(
+            "state main".parse(),
+            Ok(ConsoleCommand::State(AppState::MainMenu))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        for line in [
+            "",
+            "fly",
+            "spawn",
+            "spawn 0",
+            "spawn 4 2",
+            "speed -1",
+            "speed fast",
+            "god maybe",
+            "tp 1",
+            "state lost",
+            "split 2 0 extra",
+        ] {
+            assert!(line.parse::<ConsoleCommand>().is_err(), "{:?} parsed", line);
+        }
+    }
+}
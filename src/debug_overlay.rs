@@ -0,0 +1,323 @@
+//! A debug overlay showing where the grid and colliders really are.
+//!
+//! Only built with the `debug_overlay` cargo feature. Press F3 to toggle it. While it is on,
+//! it draws the arena grid, every heron collision shape coloured by its [`Layer`](crate::Layer),
+//! the [`Position`] of every entity on the grid, and a readout of the current [`AppState`],
+//! the [`SnakeTimer`] progress, the frame rate and entity counts.
+//!
+//! Overlay entities are never attached to the things they describe; each one remembers its
+//! target and is tidied away once the target is gone.
+
+use std::collections::HashSet;
+
+use bevy::diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::camera::{VIEW_HEIGHT, VIEW_WIDTH};
+use crate::snake_grid::{Position, Snake};
+use crate::{cell_to_world, AppState, Layer, SnakeTimer, ARENA_HEIGHT, ARENA_WIDTH};
+
+const GRID_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.15);
+const SLAYER_SHAPE_COLOR: Color = Color::rgba(0.2, 0.5, 1.0, 0.35);
+const SNAKE_HEAD_SHAPE_COLOR: Color = Color::rgba(1.0, 0.2, 0.2, 0.35);
+const OTHER_SHAPE_COLOR: Color = Color::rgba(0.3, 1.0, 0.3, 0.25);
+const TEXT_COLOR: Color = Color::rgb(1.0, 1.0, 0.6);
+
+/// Is the overlay showing?
+#[derive(Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+}
+
+/// Marks every entity that belongs to the overlay.
+#[derive(Component)]
+struct OverlayPart;
+
+#[derive(Component)]
+struct GridLine;
+
+/// Outlines the collision shape of `0`.
+#[derive(Component)]
+struct ShapeOverlay(Entity);
+
+/// Shows the grid position of `0`.
+#[derive(Component)]
+struct PositionLabel(Entity);
+
+#[derive(Component)]
+struct OverlayReadout;
+
+/// Adds the F3 debug overlay.
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlay>()
+            .add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_plugin(EntityCountDiagnosticsPlugin::default())
+            .add_system(toggle_debug_overlay.label(OverlaySystem::Toggle))
+            .add_system_set(
+                SystemSet::new()
+                    .after(OverlaySystem::Toggle)
+                    .with_system(draw_grid)
+                    .with_system(draw_collision_shapes)
+                    .with_system(draw_position_labels)
+                    .with_system(spawn_readout)
+                    .with_system(update_readout),
+            );
+    }
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum OverlaySystem {
+    Toggle,
+}
+
+fn toggle_debug_overlay(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    parts: Query<Entity, With<OverlayPart>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.enabled = !overlay.enabled;
+        if !overlay.enabled {
+            for part in parts.iter() {
+                commands.entity(part).despawn();
+            }
+        }
+    }
+}
+
+/// Spawns the grid lines, and respawns them if a state change tidied them away.
+fn draw_grid(overlay: Res<DebugOverlay>, mut commands: Commands, lines: Query<(), With<GridLine>>) {
+    if !overlay.enabled || !lines.is_empty() {
+        return;
+    }
+    let corner = cell_to_world(-0.5, -0.5);
+    let size = cell_to_world(ARENA_WIDTH as f32 - 0.5, ARENA_HEIGHT as f32 - 0.5) - corner;
+    let vertical = (0..=ARENA_WIDTH).map(|x| {
+        let x = cell_to_world(x as f32 - 0.5, 0.0).x;
+        (
+            Vec2::new(x, corner.y + size.y / 2.0),
+            Vec2::new(2.0, size.y),
+        )
+    });
+    let horizontal = (0..=ARENA_HEIGHT).map(|y| {
+        let y = cell_to_world(0.0, y as f32 - 0.5).y;
+        (
+            Vec2::new(corner.x + size.x / 2.0, y),
+            Vec2::new(size.x, 2.0),
+        )
+    });
+    for (centre, size) in vertical.chain(horizontal) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: GRID_COLOR,
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(centre.extend(900.0)),
+                ..Default::default()
+            })
+            .insert(GridLine)
+            .insert(OverlayPart);
+    }
+}
+
+fn shape_color(layers: Option<&CollisionLayers>) -> Color {
+    match layers {
+        Some(layers) if layers.contains_group(Layer::Slayer) => SLAYER_SHAPE_COLOR,
+        Some(layers) if layers.contains_group(Layer::SnakeHead) => SNAKE_HEAD_SHAPE_COLOR,
+        _ => OTHER_SHAPE_COLOR,
+    }
+}
+
+/// The size of the box drawn for `shape`, if it is a shape the overlay knows how to draw.
+fn shape_size(shape: &CollisionShape) -> Option<Vec2> {
+    match shape {
+        CollisionShape::Cuboid { half_extends, .. } => Some(half_extends.truncate() * 2.0),
+        CollisionShape::Sphere { radius } => Some(Vec2::splat(radius * 2.0)),
+        CollisionShape::Capsule {
+            half_segment,
+            radius,
+        } => Some(Vec2::new(radius * 2.0, (half_segment + radius) * 2.0)),
+        _ => None,
+    }
+}
+
+fn draw_collision_shapes(
+    overlay: Res<DebugOverlay>,
+    mut commands: Commands,
+    shapes: Query<(
+        Entity,
+        &CollisionShape,
+        Option<&CollisionLayers>,
+        &GlobalTransform,
+    )>,
+    mut outlines: Query<(Entity, &ShapeOverlay, &mut Transform, &mut Sprite)>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    let mut outlined = HashSet::new();
+    for (outline, ShapeOverlay(target), mut transform, mut sprite) in outlines.iter_mut() {
+        match shapes.get(*target) {
+            Ok((_, _, layers, global)) => {
+                transform.translation = global.translation.truncate().extend(910.0);
+                transform.rotation = global.rotation;
+                sprite.color = shape_color(layers);
+                outlined.insert(*target);
+            }
+            Err(_) => commands.entity(outline).despawn(),
+        }
+    }
+    for (entity, shape, layers, global) in shapes.iter() {
+        if outlined.contains(&entity) {
+            continue;
+        }
+        if let Some(size) = shape_size(shape) {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: shape_color(layers),
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: global.translation.truncate().extend(910.0),
+                        rotation: global.rotation,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(ShapeOverlay(entity))
+                .insert(OverlayPart);
+        }
+    }
+}
+
+fn text_style(asset_server: &AssetServer, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size,
+        color: TEXT_COLOR,
+    }
+}
+
+fn draw_position_labels(
+    overlay: Res<DebugOverlay>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    positions: Query<(Entity, &Position, &GlobalTransform)>,
+    mut labels: Query<(Entity, &PositionLabel, &mut Transform, &mut Text)>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    let mut labelled = HashSet::new();
+    for (label, PositionLabel(target), mut transform, mut text) in labels.iter_mut() {
+        match positions.get(*target) {
+            Ok((_, position, global)) => {
+                transform.translation = global.translation.truncate().extend(920.0);
+                let value = format!("{},{}", position.x, position.y);
+                if text.sections[0].value != value {
+                    text.sections[0].value = value;
+                }
+                labelled.insert(*target);
+            }
+            Err(_) => commands.entity(label).despawn(),
+        }
+    }
+    for (entity, position, global) in positions.iter() {
+        if labelled.contains(&entity) {
+            continue;
+        }
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::with_section(
+                    format!("{},{}", position.x, position.y),
+                    text_style(&asset_server, 14.0),
+                    TextAlignment {
+                        vertical: VerticalAlign::Center,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                transform: Transform::from_translation(global.translation.truncate().extend(920.0)),
+                ..Default::default()
+            })
+            .insert(PositionLabel(entity))
+            .insert(OverlayPart);
+    }
+}
+
+/// Spawns the readout in the top left corner of the view.
+fn spawn_readout(
+    overlay: Res<DebugOverlay>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    readouts: Query<(), With<OverlayReadout>>,
+) {
+    if !overlay.enabled || !readouts.is_empty() {
+        return;
+    }
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                "",
+                text_style(&asset_server, 24.0),
+                TextAlignment {
+                    vertical: VerticalAlign::Top,
+                    horizontal: HorizontalAlign::Left,
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(
+                -VIEW_WIDTH / 2.0 + 16.0,
+                VIEW_HEIGHT / 2.0 - 16.0,
+                930.0,
+            )),
+            ..Default::default()
+        })
+        .insert(OverlayReadout)
+        .insert(OverlayPart);
+}
+
+fn update_readout(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    state: Res<State<AppState>>,
+    snake_timer: Res<SnakeTimer>,
+    (snakes, cells, bodies): (
+        Query<(), With<Snake>>,
+        Query<(), With<Position>>,
+        Query<(), With<RigidBody>>,
+    ),
+    mut readouts: Query<&mut Text, With<OverlayReadout>>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.average())
+        .unwrap_or_default();
+    let entities = diagnostics
+        .get(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        .and_then(|count| count.value())
+        .unwrap_or_default();
+    let value = format!(
+        "FPS {:.1}\nState {:?}\nSnake timer {:.0}%\nEntities {:.0}  snakes {}  cells {}  bodies {}",
+        fps,
+        state.current(),
+        snake_timer.0.percent() * 100.0,
+        entities,
+        snakes.iter().count(),
+        cells.iter().count(),
+        bodies.iter().count(),
+    );
+    for mut text in readouts.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,532 @@
+//! The level editor, opened from the main menu.
+//!
+//! Pick a tool with the number keys, then edit with the mouse: the left button places
+//! and the right button removes. The arrow keys turn the snake. Ctrl+S saves the level
+//! as [`EDITOR_LEVEL`], Ctrl+L reloads it, and F5 saves it and starts a match on it;
+//! F5 during that match comes straight back to the editor.
+
+use bevy::prelude::*;
+
+use crate::camera::{spawn_game_camera, window_to_world, VIEW_HEIGHT, VIEW_WIDTH};
+use crate::level::{Level, LevelDirectory, Platform, BACKGROUNDS};
+use crate::snake_den::{DEN_SIZE, DEN_TEXTURE};
+use crate::snake_grid::{Arena, Direction, Position, Snake};
+use crate::{
+    cell_to_world, head_rotation, world_to_cell, AppState, MatchConfig, ARENA_HEIGHT, ARENA_WIDTH,
+    GROUND_COLOR, OBSTACLE_COLOR, TILE_SIZE,
+};
+
+/// The name levels made in the editor are saved under.
+pub const EDITOR_LEVEL: &str = "custom";
+
+/// Platform corners snap to this many world units.
+const PLATFORM_SNAP: f32 = TILE_SIZE / 2.0;
+
+const GRID_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.1);
+const DRAG_COLOR: Color = Color::rgba(0.3, 0.8, 0.3, 0.5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tool {
+    Platform,
+    Obstacle,
+    SlayerSpawn,
+    SnakePath,
+    Background,
+    Den,
+}
+
+impl Tool {
+    const ALL: [(KeyCode, Tool); 6] = [
+        (KeyCode::Key1, Tool::Platform),
+        (KeyCode::Key2, Tool::Obstacle),
+        (KeyCode::Key3, Tool::SlayerSpawn),
+        (KeyCode::Key4, Tool::SnakePath),
+        (KeyCode::Key5, Tool::Background),
+        (KeyCode::Key6, Tool::Den),
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Platform => "Platforms: drag to place",
+            Tool::Obstacle => "Obstacles: paint cells",
+            Tool::SlayerSpawn => "Slayer spawn: click a cell",
+            Tool::SnakePath => "Snake: draw from the head back",
+            Tool::Background => "Background: click to change",
+            Tool::Den => "Snake dens: paint cells",
+        }
+    }
+}
+
+/// What the editor is doing.
+pub struct LevelEditor {
+    tool: Tool,
+    /// Where the platform being dragged out was started.
+    drag_start: Option<Vec2>,
+    /// Is a match running to try out the level?
+    pub test_playing: bool,
+    /// The outcome of the last save or load.
+    status: String,
+}
+
+impl Default for LevelEditor {
+    fn default() -> Self {
+        Self {
+            tool: Tool::Platform,
+            drag_start: None,
+            test_playing: false,
+            status: String::new(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct OnLevelEditorScreen;
+
+/// Draws part of the level being edited; all of these are redrawn whenever it changes.
+#[derive(Component)]
+struct LevelPreview;
+
+#[derive(Component)]
+struct DragPreview;
+
+#[derive(Component)]
+struct EditorHud;
+
+/// Adds the level editor state.
+pub struct LevelEditorPlugin;
+
+impl Plugin for LevelEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelEditor>()
+            .add_system_set(SystemSet::on_enter(AppState::LevelEditor).with_system(setup_editor))
+            .add_system_set(
+                SystemSet::on_update(AppState::LevelEditor)
+                    .with_system(editor_keys)
+                    .with_system(edit_with_mouse)
+                    .with_system(draw_level_preview)
+                    .with_system(update_editor_hud),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::LevelEditor).with_system(cleanup_editor))
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(end_test_play),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(forget_test_play));
+    }
+}
+
+fn setup_editor(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut editor: ResMut<LevelEditor>,
+    directory: Res<LevelDirectory>,
+    mut level: ResMut<Level>,
+) {
+    let camera = spawn_game_camera(&mut commands);
+    commands.entity(camera).insert(OnLevelEditorScreen);
+
+    if editor.test_playing {
+        // The match loaded the level from where it was just saved
+        editor.test_playing = false;
+    } else {
+        let path = directory.path(EDITOR_LEVEL);
+        *level = match Level::load(&path) {
+            Ok(level) => {
+                editor.status = format!("Loaded {}", path.display());
+                level
+            }
+            Err(_) => {
+                editor.status = "New level".to_string();
+                Level::default()
+            }
+        };
+    }
+
+    let corner = cell_to_world(-0.5, -0.5);
+    let size = Vec2::new(
+        ARENA_WIDTH as f32 * TILE_SIZE,
+        ARENA_HEIGHT as f32 * TILE_SIZE,
+    );
+    let vertical = (0..=ARENA_WIDTH).map(|x| {
+        (
+            Vec2::new(corner.x + x as f32 * TILE_SIZE, corner.y + size.y / 2.0),
+            Vec2::new(1.0, size.y),
+        )
+    });
+    let horizontal = (0..=ARENA_HEIGHT).map(|y| {
+        (
+            Vec2::new(corner.x + size.x / 2.0, corner.y + y as f32 * TILE_SIZE),
+            Vec2::new(size.x, 1.0),
+        )
+    });
+    for (centre, size) in vertical.chain(horizontal) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: GRID_COLOR,
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(centre.extend(3.0)),
+                ..Default::default()
+            })
+            .insert(OnLevelEditorScreen);
+    }
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: DRAG_COLOR,
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 4.0)),
+            ..Default::default()
+        })
+        .insert(DragPreview)
+        .insert(OnLevelEditorScreen);
+
+    commands
+        .spawn_bundle(Text2dBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/GoMono-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Top,
+                    horizontal: HorizontalAlign::Left,
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(
+                -VIEW_WIDTH / 2.0 + 16.0,
+                VIEW_HEIGHT / 2.0 - 16.0,
+                5.0,
+            )),
+            ..Default::default()
+        })
+        .insert(EditorHud)
+        .insert(OnLevelEditorScreen);
+}
+
+fn cleanup_editor(
+    mut commands: Commands,
+    to_despawn: Query<Entity, Or<(With<OnLevelEditorScreen>, With<LevelPreview>)>>,
+) {
+    for entity in to_despawn.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn editor_keys(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut editor: ResMut<LevelEditor>,
+    (directory, arena): (Res<LevelDirectory>, Res<Arena>),
+    mut level: ResMut<Level>,
+    mut config: ResMut<MatchConfig>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for (key, tool) in Tool::ALL {
+        if keyboard_input.just_pressed(key) {
+            editor.tool = tool;
+            editor.drag_start = None;
+        }
+    }
+
+    for (key, direction) in [
+        (KeyCode::Up, Direction::Up),
+        (KeyCode::Down, Direction::Down),
+        (KeyCode::Left, Direction::Left),
+        (KeyCode::Right, Direction::Right),
+    ] {
+        if !keyboard_input.just_pressed(key) || level.snake.direction() == direction {
+            continue;
+        }
+        let body: Vec<Position> = level.snake.body().iter().copied().collect();
+        if body.get(1) == Some(&arena.neighbour(body[0], direction)) {
+            editor.status = "The snake cannot face back into its own neck".to_string();
+        } else {
+            level.snake = Snake::new(body, direction);
+        }
+    }
+
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let path = directory.path(EDITOR_LEVEL);
+    if ctrl && keyboard_input.just_pressed(KeyCode::S) {
+        editor.status = match level.save(&path) {
+            Ok(()) => format!("Saved {}", path.display()),
+            Err(error) => format!("Could not save {}: {}", path.display(), error),
+        };
+    } else if ctrl && keyboard_input.just_pressed(KeyCode::L) {
+        editor.status = match Level::load(&path) {
+            Ok(loaded) => {
+                *level = loaded;
+                format!("Loaded {}", path.display())
+            }
+            Err(error) => format!("Could not load {}: {}", path.display(), error),
+        };
+    } else if keyboard_input.just_pressed(KeyCode::F5) {
+        match level.save(&path) {
+            Ok(()) => {
+                *config = MatchConfig {
+                    level: EDITOR_LEVEL.to_string(),
+                    ..Default::default()
+                };
+                editor.test_playing = true;
+                // The match starts this frame, and should not see the key and come straight back
+                keyboard_input.reset(KeyCode::F5);
+                state.set(AppState::InOnePlayerGame).unwrap();
+            }
+            Err(error) => editor.status = format!("Could not save {}: {}", path.display(), error),
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        state.set(AppState::MainMenu).unwrap();
+    }
+}
+
+/// Snaps a world point to the nearest platform corner.
+fn snap(point: Vec2) -> Vec2 {
+    (point / PLATFORM_SNAP).round() * PLATFORM_SNAP
+}
+
+fn edit_with_mouse(
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Option<Res<Windows>>,
+    arena: Res<Arena>,
+    mut editor: ResMut<LevelEditor>,
+    mut level: ResMut<Level>,
+    mut drag_previews: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<DragPreview>>,
+) {
+    let cursor = match windows.as_ref().and_then(|windows| windows.get_primary()) {
+        Some(window) => match window.cursor_position() {
+            Some(cursor) => window_to_world(window, cursor),
+            None => return,
+        },
+        None => return,
+    };
+    let cell = world_to_cell(cursor);
+    let left = mouse_input.pressed(MouseButton::Left);
+    let right = mouse_input.pressed(MouseButton::Right);
+
+    match editor.tool {
+        Tool::Platform => {
+            if mouse_input.just_pressed(MouseButton::Left) {
+                editor.drag_start = Some(snap(cursor));
+            }
+            if let Some(start) = editor.drag_start {
+                let platform = Platform::from_corners(start, snap(cursor));
+                if mouse_input.just_released(MouseButton::Left) {
+                    editor.drag_start = None;
+                    if platform.size.x > 0.0 && platform.size.y > 0.0 {
+                        level.platforms.push(platform);
+                    }
+                }
+                for (mut transform, mut sprite, mut visibility) in drag_previews.iter_mut() {
+                    transform.translation.x = platform.centre.x;
+                    transform.translation.y = platform.centre.y;
+                    sprite.custom_size = Some(platform.size);
+                    visibility.is_visible = editor.drag_start.is_some();
+                }
+            }
+            if mouse_input.just_pressed(MouseButton::Right) {
+                // The platform drawn last is on top
+                if let Some(index) = level.platforms.iter().rposition(|p| p.contains(cursor)) {
+                    level.platforms.remove(index);
+                }
+            }
+        }
+        // Obstacles, dens and the snake each need a cell to themselves
+        Tool::Obstacle if arena.contains(cell) => {
+            let painted = level.obstacles.contains(&cell);
+            if left && !level.is_taken(cell) {
+                level.obstacles.push(cell);
+            } else if right && painted {
+                level.obstacles.retain(|obstacle| *obstacle != cell);
+            }
+        }
+        Tool::Den if arena.contains(cell) => {
+            let painted = level.dens.contains(&cell);
+            if left && !level.is_taken(cell) {
+                level.dens.push(cell);
+            } else if right && painted {
+                level.dens.retain(|den| *den != cell);
+            }
+        }
+        Tool::SlayerSpawn if arena.contains(cell) => {
+            if mouse_input.just_pressed(MouseButton::Left) {
+                level.slayer_spawn = cell_to_world(cell.x as f32, cell.y as f32);
+            }
+        }
+        Tool::SnakePath if arena.contains(cell) => {
+            let body = level.snake.body();
+            let tail = body[body.len() - 1];
+            let direction = level.snake.direction();
+            if left && !level.is_taken(cell) {
+                if arena.direction_between(tail, cell).is_some() {
+                    // Dragging on from the tail makes the snake longer
+                    let mut body: Vec<Position> = body.iter().copied().collect();
+                    body.push(cell);
+                    level.snake = Snake::new(body, direction);
+                } else if mouse_input.just_pressed(MouseButton::Left) {
+                    // Anywhere else starts a new snake with its head there
+                    level.snake = Snake::new(vec![cell], direction);
+                }
+            } else if mouse_input.just_pressed(MouseButton::Right) && body.len() > 1 {
+                let mut body: Vec<Position> = body.iter().copied().collect();
+                body.pop();
+                level.snake = Snake::new(body, direction);
+            }
+        }
+        Tool::Background => {
+            if mouse_input.just_pressed(MouseButton::Left) {
+                // Cycle through the backgrounds, then no background at all
+                let current = level
+                    .background
+                    .as_deref()
+                    .and_then(|current| BACKGROUNDS.iter().position(|b| *b == current));
+                level.background = match current {
+                    Some(index) if index + 1 < BACKGROUNDS.len() => {
+                        Some(BACKGROUNDS[index + 1].to_string())
+                    }
+                    Some(_) => None,
+                    None => Some(BACKGROUNDS[0].to_string()),
+                };
+            }
+        }
+        _ => {}
+    }
+}
+
+fn draw_level_preview(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    level: Res<Level>,
+    previews: Query<Entity, With<LevelPreview>>,
+) {
+    if !level.is_changed() && !previews.is_empty() {
+        return;
+    }
+    for entity in previews.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let mut preview = |bundle: SpriteBundle| {
+        commands.spawn_bundle(bundle).insert(LevelPreview);
+    };
+    if let Some(background) = &level.background {
+        preview(SpriteBundle {
+            texture: asset_server.load(background.as_str()),
+            ..Default::default()
+        });
+    }
+    for platform in &level.platforms {
+        preview(SpriteBundle {
+            sprite: Sprite {
+                color: GROUND_COLOR,
+                custom_size: Some(platform.size),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(platform.centre.extend(0.5)),
+            ..Default::default()
+        });
+    }
+    for obstacle in &level.obstacles {
+        preview(SpriteBundle {
+            sprite: Sprite {
+                color: OBSTACLE_COLOR,
+                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(
+                cell_to_world(obstacle.x as f32, obstacle.y as f32).extend(0.5),
+            ),
+            ..Default::default()
+        });
+    }
+    for den in &level.dens {
+        preview(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(DEN_SIZE)),
+                ..Default::default()
+            },
+            texture: asset_server.load(DEN_TEXTURE),
+            transform: Transform::from_translation(
+                cell_to_world(den.x as f32, den.y as f32).extend(0.6),
+            ),
+            ..Default::default()
+        });
+    }
+    let last = level.snake.len() - 1;
+    for (index, cell) in level.snake.body().iter().enumerate() {
+        let texture = if index == 0 {
+            "snake_head.png"
+        } else if index == last {
+            "snake_tail.png"
+        } else {
+            "snake_section.png"
+        };
+        let mut transform =
+            Transform::from_translation(cell_to_world(cell.x as f32, cell.y as f32).extend(2.0));
+        if index == 0 {
+            transform.rotation = head_rotation(level.snake.direction());
+        }
+        preview(SpriteBundle {
+            texture: asset_server.load(texture),
+            transform,
+            ..Default::default()
+        });
+    }
+    preview(SpriteBundle {
+        texture: asset_server.load("slayer.png"),
+        transform: Transform::from_translation(level.slayer_spawn.extend(2.0)),
+        ..Default::default()
+    });
+}
+
+fn update_editor_hud(
+    editor: Res<LevelEditor>,
+    level: Res<Level>,
+    mut huds: Query<&mut Text, With<EditorHud>>,
+) {
+    if !editor.is_changed() && !level.is_changed() {
+        return;
+    }
+    let tools: Vec<String> = Tool::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, (_, tool))| {
+            let marker = if *tool == editor.tool { ">" } else { " " };
+            format!("{} {} {}", marker, index + 1, tool.name())
+        })
+        .collect();
+    let value = format!(
+        "{}\nArrows turn the snake ({:?}), background {}\n\
+         Ctrl+S save, Ctrl+L load, F5 test play, Esc main menu\n{}",
+        tools.join("\n"),
+        level.snake.direction(),
+        level.background.as_deref().unwrap_or("none"),
+        editor.status,
+    );
+    for mut text in huds.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+/// Goes back to the editor from a test play.
+fn end_test_play(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    editor: Res<LevelEditor>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if editor.test_playing && keyboard_input.just_pressed(KeyCode::F5) {
+        keyboard_input.reset(KeyCode::F5);
+        state.set(AppState::LevelEditor).unwrap();
+    }
+}
+
+/// A test play that was quit from the pause menu does not come back to the editor.
+fn forget_test_play(mut editor: ResMut<LevelEditor>) {
+    editor.test_playing = false;
+}
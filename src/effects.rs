@@ -0,0 +1,251 @@
+//! Screen effects that give hits their weight: camera shake, hit-stop and flashes.
+//!
+//! Effects are triggered by gameplay events once the simulation has run, and only ever touch
+//! the game camera and the pacing of the [`SimulationClock`], never the game state itself.
+//! Hit-stop only holds back when ticks run: physics and collisions are stepped with the
+//! ticks rather than with the frames, so turning effects down in [`ScreenEffects`] cannot
+//! change how a match plays out.
+
+use bevy::core::Time;
+use bevy::prelude::*;
+
+use crate::camera::{GameCamera, VIEW_HEIGHT, VIEW_WIDTH};
+use crate::simulation::SimulationClock;
+use crate::{SlayerDeathEvent, SnakeSeveredEvent, SwordHitEvent};
+
+/// How much trauma drains away per second.
+const TRAUMA_DECAY: f32 = 1.5;
+/// How far the camera moves at full trauma, in world units.
+const MAX_SHAKE_OFFSET: f32 = 40.0;
+/// How far the camera rolls at full trauma, in radians.
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+/// How quickly the shake wobbles back and forth.
+const SHAKE_FREQUENCY: f32 = 25.0;
+/// How long a flash takes to fade out, in seconds.
+const FLASH_DURATION: f32 = 0.2;
+
+/// How strongly hits are felt, as chosen by the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenEffects {
+    Full,
+    /// Gentler shaking, and no hit-stop or flashes.
+    Reduced,
+    Off,
+}
+
+impl Default for ScreenEffects {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+impl ScreenEffects {
+    /// The setting that follows this one in the pause menu.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Full => Self::Reduced,
+            Self::Reduced => Self::Off,
+            Self::Off => Self::Full,
+        }
+    }
+
+    /// The text of the pause menu button for this setting.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Full => "Screen Effects: Full",
+            Self::Reduced => "Screen Effects: Low",
+            Self::Off => "Screen Effects: Off",
+        }
+    }
+
+    fn shake_scale(self) -> f32 {
+        match self {
+            Self::Full => 1.0,
+            Self::Reduced => 0.4,
+            Self::Off => 0.0,
+        }
+    }
+
+    fn hit_stop(self) -> bool {
+        self == Self::Full
+    }
+
+    fn flashes(self) -> bool {
+        self == Self::Full
+    }
+}
+
+/// Marks the text showing the current [`ScreenEffects`] setting.
+#[derive(Component)]
+pub struct ScreenEffectsLabel;
+
+/// How the screen reacts to one kind of gameplay event.
+struct Impact {
+    /// Added to the [`CameraShake`] trauma.
+    trauma: f32,
+    hit_stop_frames: u32,
+    flash: Color,
+}
+
+const SWORD_HIT: Impact = Impact {
+    trauma: 0.25,
+    hit_stop_frames: 3,
+    flash: Color::rgba(1.0, 1.0, 1.0, 0.15),
+};
+
+const SEVER: Impact = Impact {
+    trauma: 0.45,
+    hit_stop_frames: 5,
+    flash: Color::rgba(1.0, 1.0, 1.0, 0.3),
+};
+
+const SLAYER_DEATH: Impact = Impact {
+    trauma: 0.9,
+    hit_stop_frames: 10,
+    flash: Color::rgba(0.8, 0.1, 0.1, 0.5),
+};
+
+/// Trauma-based screen shake.
+///
+/// Trauma goes from 0 to 1 and drains away over time. The camera shakes by the square of
+/// the trauma, so small knocks barely register while big ones stack up into a heavy shake.
+#[derive(Default)]
+pub struct CameraShake {
+    trauma: f32,
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+}
+
+/// A full-view overlay that briefly tints the screen.
+#[derive(Component)]
+struct Flash {
+    color: Color,
+    timer: Timer,
+}
+
+/// Reacts to the gameplay events of the last simulation ticks.
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+struct TriggerImpacts;
+
+/// Shakes, freezes and flashes the screen in response to hits.
+pub struct ScreenEffectsPlugin;
+
+impl Plugin for ScreenEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScreenEffects>()
+            .init_resource::<CameraShake>()
+            .add_system(trigger_impacts.label(TriggerImpacts))
+            .add_system(shake_camera.after(TriggerImpacts))
+            .add_system(fade_flashes.after(TriggerImpacts))
+            .add_system(attach_flash)
+            .add_system(update_screen_effects_label);
+    }
+}
+
+fn trigger_impacts(
+    screen_effects: Res<ScreenEffects>,
+    mut sword_hits: EventReader<SwordHitEvent>,
+    mut severs: EventReader<SnakeSeveredEvent>,
+    mut deaths: EventReader<SlayerDeathEvent>,
+    mut shake: ResMut<CameraShake>,
+    mut clock: ResMut<SimulationClock>,
+    mut flashes: Query<&mut Flash>,
+) {
+    let impacts = sword_hits
+        .iter()
+        .map(|_| &SWORD_HIT)
+        .chain(severs.iter().map(|_| &SEVER))
+        .chain(deaths.iter().map(|_| &SLAYER_DEATH));
+    for impact in impacts {
+        shake.add_trauma(impact.trauma * screen_effects.shake_scale());
+        if screen_effects.hit_stop() {
+            clock.hit_stop(impact.hit_stop_frames);
+        }
+        if screen_effects.flashes() {
+            for mut flash in flashes.iter_mut() {
+                flash.color = impact.flash;
+                flash.timer.reset();
+            }
+        }
+    }
+}
+
+/// Smooth pseudo-random wobble between -1 and 1, different for each `seed`.
+fn wobble(time: f32, seed: f32) -> f32 {
+    let t = time * SHAKE_FREQUENCY + seed;
+    t.sin() * 0.5 + (t * 2.3).sin() * 0.3 + (t * 5.7).sin() * 0.2
+}
+
+/// Offsets the game camera from the origin, where it otherwise always sits.
+fn shake_camera(
+    time: Res<Time>,
+    mut shake: ResMut<CameraShake>,
+    mut cameras: Query<&mut Transform, With<GameCamera>>,
+) {
+    let shake = &mut *shake;
+    shake.time += time.delta_seconds();
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.0);
+
+    let amount = shake.trauma * shake.trauma;
+    let offset =
+        Vec2::new(wobble(shake.time, 0.0), wobble(shake.time, 10.0)) * MAX_SHAKE_OFFSET * amount;
+    let angle = wobble(shake.time, 20.0) * MAX_SHAKE_ANGLE * amount;
+    for mut transform in cameras.iter_mut() {
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+        transform.rotation = Quat::from_rotation_z(angle);
+    }
+}
+
+fn attach_flash(mut commands: Commands, cameras: Query<Entity, Added<GameCamera>>) {
+    for camera in cameras.iter() {
+        commands.entity(camera).with_children(|camera| {
+            let mut timer = Timer::from_seconds(FLASH_DURATION, false);
+            timer.tick(timer.duration());
+            camera
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::NONE,
+                        custom_size: Some(Vec2::new(VIEW_WIDTH, VIEW_HEIGHT)),
+                        ..Default::default()
+                    },
+                    // Behind the letterbox bars, in front of everything else
+                    transform: Transform::from_translation(Vec3::new(0.0, 0.0, -2.0)),
+                    ..Default::default()
+                })
+                .insert(Flash {
+                    color: Color::NONE,
+                    timer,
+                });
+        });
+    }
+}
+
+fn fade_flashes(time: Res<Time>, mut flashes: Query<(&mut Flash, &mut Sprite)>) {
+    for (mut flash, mut sprite) in flashes.iter_mut() {
+        flash.timer.tick(time.delta());
+        let mut color = flash.color;
+        color.set_a(flash.color.a() * flash.timer.percent_left());
+        sprite.color = color;
+    }
+}
+
+fn update_screen_effects_label(
+    screen_effects: Res<ScreenEffects>,
+    mut labels: Query<&mut Text, With<ScreenEffectsLabel>>,
+) {
+    if screen_effects.is_changed() {
+        for mut text in labels.iter_mut() {
+            text.sections[0].value = screen_effects.label().to_string();
+        }
+    }
+}
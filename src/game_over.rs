@@ -0,0 +1,118 @@
+//! The screen shown once a match has been won.
+//!
+//! It names the winner and the seed the match was played with, so that a run can be tried again
+//! with `--seed`.
+
+use bevy::prelude::*;
+
+use crate::handicap::MatchOverEvent;
+use crate::netplay::Side;
+use crate::time_attack::{format_ticks, TimeAttackRun};
+use crate::{AppState, MatchConfig, MatchMode};
+
+#[derive(Component)]
+struct OnGameOverScreen;
+
+/// Shows the game over screen when the match is over, and leaves the match from it.
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::InOnePlayerGame)
+                .with_system(show_game_over_screen)
+                .with_system(leave_game_over_screen),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_game_over_screen),
+        );
+    }
+}
+
+fn show_game_over_screen(
+    mut commands: Commands,
+    mut match_over: EventReader<MatchOverEvent>,
+    asset_server: Res<AssetServer>,
+    (config, run): (Res<MatchConfig>, Res<TimeAttackRun>),
+    screen: Query<(), With<OnGameOverScreen>>,
+) {
+    let winner = match match_over.iter().last() {
+        Some(event) => event.winner,
+        None => return,
+    };
+    if screen.iter().next().is_some() {
+        return;
+    }
+    let mut lines = vec![match (config.mode, winner) {
+        (MatchMode::CoOp, Side::Slayer) => "The slayers win!".to_string(),
+        (MatchMode::Survival, _) => format!("The snakes got you on wave {}", config.wave),
+        (MatchMode::TimeAttack, Side::Slayer) => {
+            format!(
+                "Cleared in {}",
+                format_ticks(run.finished.unwrap_or_default())
+            )
+        }
+        (MatchMode::Tutorial, _) => "Tutorial complete!".to_string(),
+        _ => format!("The {} wins!", winner.name()),
+    }];
+    if config.mode == MatchMode::TimeAttack && winner == Side::Slayer {
+        if run.new_best {
+            lines.push("New best time!".to_string());
+        } else if let Some(best) = &run.best {
+            lines.push(format!("Best time: {}", format_ticks(best.ticks)));
+        }
+    }
+    // Neither time attack runs nor the tutorial depend on the seed
+    if !matches!(config.mode, MatchMode::TimeAttack | MatchMode::Tutorial) {
+        lines.push(format!("Seed: {}", config.seed));
+    }
+    lines.push("Press Enter for the main menu".to_string());
+
+    let style = TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size: 40.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OnGameOverScreen);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                margin: Rect::all(Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(30.0)),
+                ..Default::default()
+            },
+            color: Color::TEAL.into(),
+            ..Default::default()
+        })
+        .insert(OnGameOverScreen)
+        .with_children(|parent| {
+            for line in lines {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(line, style.clone(), Default::default()),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn leave_game_over_screen(
+    mut input: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    screen: Query<(), With<OnGameOverScreen>>,
+) {
+    if screen.iter().next().is_some() && input.just_pressed(KeyCode::Return) {
+        // The main menu would otherwise see the same press
+        input.reset(KeyCode::Return);
+        state.replace(AppState::MainMenu).unwrap();
+    }
+}
+
+fn cleanup_game_over_screen(mut commands: Commands, screen: Query<Entity, With<OnGameOverScreen>>) {
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
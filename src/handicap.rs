@@ -0,0 +1,896 @@
+//! Handicaps that even out versus matches between players of different skill.
+//!
+//! Before a versus match each player picks modifiers for their own side: the slayer can
+//! take extra lives and a heavier sword, and the snake player picks how fast their snake
+//! moves. The players also enter their names there, and the picks start out at a suggestion
+//! based on how their recent matches against each other went, which are kept in a
+//! [`WinHistory`] file.
+//!
+//! A match is won by the snake once the slayer runs out of lives, and by the slayer once
+//! the snake player's snake has been cut all the way down to its head. Co-op matches end
+//! the same way, but are not kept in the history.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+use crate::netplay::Side;
+use crate::replay::ReplayPlayback;
+use crate::simulation::{in_state, SimulationClock, SimulationStage, SimulationSystem};
+use crate::snake_ai::SnakeAi;
+use crate::snake_den::DenSnake;
+use crate::snake_grid::Snake;
+use crate::{
+    menu_button_dynamic_colors, menu_panel, menu_text_style, spawn_menu_button, AppState,
+    MatchConfig, MatchMode, PlayerSnake, Slayer, SlayerDeathEvent, SnakeAction, SnakeSeveredEvent,
+};
+
+/// Where the results of versus matches are kept by default.
+pub const WIN_HISTORY_PATH: &str = "saves/win_history.txt";
+
+/// The most lives the slayer can be given on top of their first.
+pub const MAX_EXTRA_LIVES: u8 = 3;
+/// The heaviest sword the slayer can pick.
+pub const MAX_SWORD_DAMAGE: u8 = 3;
+
+/// The longest name a player can enter.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+/// How many of the latest matches are looked at when suggesting handicaps.
+const SUGGESTION_WINDOW: usize = 5;
+/// How many more matches one side has to have won before the suggestion leans the other way.
+const SUGGESTION_MARGIN: i32 = 2;
+
+/// How fast the snake player's snake moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnakePace {
+    Slower,
+    Normal,
+    Faster,
+}
+
+impl SnakePace {
+    /// How long the [`SnakeTimer`](crate::SnakeTimer) waits between moves at this pace.
+    pub fn interval(self) -> Duration {
+        Duration::from_millis(match self {
+            SnakePace::Slower => 500,
+            SnakePace::Normal => 400,
+            SnakePace::Faster => 300,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SnakePace::Slower => "Slower",
+            SnakePace::Normal => "Normal",
+            SnakePace::Faster => "Faster",
+        }
+    }
+
+    /// The pace after this one on the handicap screen, going round.
+    fn next(self) -> Self {
+        match self {
+            SnakePace::Slower => SnakePace::Normal,
+            SnakePace::Normal => SnakePace::Faster,
+            SnakePace::Faster => SnakePace::Slower,
+        }
+    }
+
+    fn encode(self) -> u8 {
+        self as u8
+    }
+
+    fn decode(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(SnakePace::Slower),
+            1 => Some(SnakePace::Normal),
+            2 => Some(SnakePace::Faster),
+            _ => None,
+        }
+    }
+}
+
+/// The modifiers picked for a match. The default is an even match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handicaps {
+    /// Lives the slayer has on top of their first.
+    pub extra_lives: u8,
+    /// How far towards the head a sword hit cuts the snake, counting the segment hit as 1.
+    pub sword_damage: u8,
+    pub snake_pace: SnakePace,
+}
+
+impl Default for Handicaps {
+    fn default() -> Self {
+        Self {
+            extra_lives: 0,
+            sword_damage: 1,
+            snake_pace: SnakePace::Normal,
+        }
+    }
+}
+
+impl Handicaps {
+    /// Where a sword hit on the segment at `index` cuts the snake.
+    ///
+    /// A heavier sword cuts closer to the head, but never through the head itself.
+    pub fn severed_at(&self, index: usize) -> usize {
+        let extra = usize::from(self.sword_damage.saturating_sub(1));
+        index.saturating_sub(extra).max(1)
+    }
+
+    /// These handicaps moved one step in favour of `side`.
+    ///
+    /// Help that the other side was getting is taken away before any more is handed out.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::handicap::{Handicaps, SnakePace};
+    /// # use unfair_advantage_lib::netplay::Side;
+    /// let faster = Handicaps {
+    ///     snake_pace: SnakePace::Faster,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(faster.favor(Side::Slayer), Handicaps::default());
+    /// assert_eq!(Handicaps::default().favor(Side::Slayer).extra_lives, 1);
+    /// ```
+    pub fn favor(self, side: Side) -> Self {
+        let mut favored = self;
+        match side {
+            Side::Slayer => {
+                if self.snake_pace == SnakePace::Faster {
+                    favored.snake_pace = SnakePace::Normal;
+                } else if self.extra_lives < MAX_EXTRA_LIVES {
+                    favored.extra_lives += 1;
+                } else if self.sword_damage < MAX_SWORD_DAMAGE {
+                    favored.sword_damage += 1;
+                } else {
+                    favored.snake_pace = SnakePace::Slower;
+                }
+            }
+            Side::Snake => {
+                if self.extra_lives > 0 {
+                    favored.extra_lives -= 1;
+                } else if self.sword_damage > 1 {
+                    favored.sword_damage -= 1;
+                } else if self.snake_pace == SnakePace::Slower {
+                    favored.snake_pace = SnakePace::Normal;
+                } else {
+                    favored.snake_pace = SnakePace::Faster;
+                }
+            }
+        }
+        favored
+    }
+
+    /// Packs the handicaps into the three bytes used by saves, replays and netplay.
+    pub fn to_bytes(self) -> [u8; 3] {
+        [
+            self.extra_lives,
+            self.sword_damage,
+            self.snake_pace.encode(),
+        ]
+    }
+
+    /// Unpacks handicaps written by [`Handicaps::to_bytes`], or `None` if they are out of range.
+    pub fn from_bytes([extra_lives, sword_damage, snake_pace]: [u8; 3]) -> Option<Self> {
+        if extra_lives > MAX_EXTRA_LIVES || !(1..=MAX_SWORD_DAMAGE).contains(&sword_damage) {
+            return None;
+        }
+        Some(Self {
+            extra_lives,
+            sword_damage,
+            snake_pace: SnakePace::decode(snake_pace)?,
+        })
+    }
+}
+
+/// How many more times the slayer can be chomped before the match is lost.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtraLives(pub u8);
+
+/// The match has been decided in favour of `winner`.
+pub struct MatchOverEvent {
+    pub winner: Side,
+}
+
+/// Who has won the current match, once that is decided.
+#[derive(Default)]
+pub struct MatchOutcome(pub Option<Side>);
+
+/// The names of the two people playing versus matches, as entered on the handicap screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Players {
+    pub slayer: String,
+    pub snake: String,
+}
+
+impl Players {
+    /// The name of whoever plays `side`.
+    pub fn name(&self, side: Side) -> &str {
+        match side {
+            Side::Slayer => &self.slayer,
+            Side::Snake => &self.snake,
+        }
+    }
+
+    fn name_mut(&mut self, side: Side) -> &mut String {
+        match side {
+            Side::Slayer => &mut self.slayer,
+            Side::Snake => &mut self.snake,
+        }
+    }
+}
+
+/// How a finished versus match went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+    pub players: Players,
+    pub winner: Side,
+    pub handicaps: Handicaps,
+}
+
+/// The results of past versus matches, oldest first.
+///
+/// On disk this is a text file with one match per line, giving the winner, the slayer's
+/// extra lives, the sword damage and the snake's pace, then after a tab each the names of
+/// the slayer and the snake player, like `snake 1 2 normal\tana\tbo`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WinHistory {
+    pub results: Vec<MatchResult>,
+}
+
+impl WinHistory {
+    /// Reads the history stored at `path`, which is empty if there is no file yet.
+    ///
+    /// Lines that cannot be read are skipped, so that one bad line does not lose the rest.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(text.parse().unwrap()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the history to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    pub fn record(&mut self, players: &Players, winner: Side, handicaps: Handicaps) {
+        self.results.push(MatchResult {
+            players: players.clone(),
+            winner,
+            handicaps,
+        });
+    }
+
+    /// The matches `players` played on the same sides as now, oldest first.
+    fn between<'a>(
+        &'a self,
+        players: &'a Players,
+    ) -> impl DoubleEndedIterator<Item = &'a MatchResult> {
+        self.results
+            .iter()
+            .filter(move |result| result.players == *players)
+    }
+
+    /// The handicaps to offer for the next match between `players`.
+    ///
+    /// These are the handicaps of their last match, moved a step towards whichever side has
+    /// been losing them clearly. Only matches played with those same handicaps count, so
+    /// that a new setting gets a fair chance before it is changed again.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::handicap::{Handicaps, Players, WinHistory};
+    /// # use unfair_advantage_lib::netplay::Side;
+    /// let players = Players {
+    ///     slayer: "Ana".to_string(),
+    ///     snake: "Bo".to_string(),
+    /// };
+    /// let mut history = WinHistory::default();
+    /// history.record(&players, Side::Snake, Handicaps::default());
+    /// assert_eq!(history.suggest(&players), Handicaps::default());
+    /// history.record(&players, Side::Snake, Handicaps::default());
+    /// assert_eq!(history.suggest(&players).extra_lives, 1);
+    /// // Other players start from an even match
+    /// assert_eq!(history.suggest(&Players::default()), Handicaps::default());
+    /// ```
+    pub fn suggest(&self, players: &Players) -> Handicaps {
+        let last = match self.between(players).last() {
+            Some(result) => result.handicaps,
+            None => return Handicaps::default(),
+        };
+        let margin: i32 = self
+            .between(players)
+            .rev()
+            .take(SUGGESTION_WINDOW)
+            .take_while(|result| result.handicaps == last)
+            .map(|result| match result.winner {
+                Side::Slayer => 1,
+                Side::Snake => -1,
+            })
+            .sum();
+        if margin >= SUGGESTION_MARGIN {
+            last.favor(Side::Snake)
+        } else if margin <= -SUGGESTION_MARGIN {
+            last.favor(Side::Slayer)
+        } else {
+            last
+        }
+    }
+
+    /// A line telling how the latest matches between `players` went.
+    pub fn summary(&self, players: &Players) -> String {
+        let mut recent: Vec<&MatchResult> = self
+            .between(players)
+            .rev()
+            .take(SUGGESTION_WINDOW)
+            .collect();
+        recent.reverse();
+        if recent.is_empty() {
+            return "No versus matches between these players yet".to_string();
+        }
+        let slayer_wins = recent
+            .iter()
+            .filter(|result| result.winner == Side::Slayer)
+            .count();
+        format!(
+            "Last {} matches: slayer {}, snake {}",
+            recent.len(),
+            slayer_wins,
+            recent.len() - slayer_wins
+        )
+    }
+}
+
+impl fmt::Display for WinHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let handicaps = result.handicaps;
+            writeln!(
+                f,
+                "{} {} {} {}\t{}\t{}",
+                result.winner.name(),
+                handicaps.extra_lives,
+                handicaps.sword_damage,
+                handicaps.snake_pace.name().to_lowercase(),
+                result.players.slayer,
+                result.players.snake
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for WinHistory {
+    type Err = std::convert::Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let results = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                let result = parse_result(line);
+                if result.is_none() {
+                    warn!("Skipping unreadable win history line {:?}", line);
+                }
+                result
+            })
+            .collect();
+        Ok(Self { results })
+    }
+}
+
+fn parse_result(line: &str) -> Option<MatchResult> {
+    let mut fields = line.split('\t');
+    let mut words = fields.next()?.split_whitespace();
+    let winner = match words.next()? {
+        "slayer" => Side::Slayer,
+        "snake" => Side::Snake,
+        _ => return None,
+    };
+    let extra_lives = words.next()?.parse().ok()?;
+    let sword_damage = words.next()?.parse().ok()?;
+    let snake_pace = match words.next()? {
+        "slower" => SnakePace::Slower,
+        "normal" => SnakePace::Normal,
+        "faster" => SnakePace::Faster,
+        _ => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    let handicaps = Handicaps::from_bytes([extra_lives, sword_damage, snake_pace.encode()])?;
+    let players = Players {
+        slayer: fields.next()?.to_string(),
+        snake: fields.next()?.to_string(),
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(MatchResult {
+        players,
+        winner,
+        handicaps,
+    })
+}
+
+/// Where versus results are kept; `None` stops them being recorded.
+pub struct WinHistoryPath(pub Option<PathBuf>);
+
+impl Default for WinHistoryPath {
+    fn default() -> Self {
+        Self(Some(PathBuf::from(WIN_HISTORY_PATH)))
+    }
+}
+
+#[derive(Component)]
+struct OnHandicapScreen;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum HandicapButton {
+    /// The name of whoever plays a side, which is typed in once the button is clicked.
+    Name(Side),
+    ExtraLives,
+    SwordDamage,
+    SnakePace,
+    StartMatch,
+    Back,
+}
+
+impl HandicapButton {
+    fn label(self, handicaps: &Handicaps, players: &Players, editing: Option<Side>) -> String {
+        match self {
+            HandicapButton::Name(side) => {
+                let title = match side {
+                    Side::Slayer => "Slayer",
+                    Side::Snake => "Snake",
+                };
+                let cursor = if editing == Some(side) { "_" } else { "" };
+                format!("{} player: {}{}", title, players.name(side), cursor)
+            }
+            HandicapButton::ExtraLives => format!("Slayer lives: {}", handicaps.extra_lives + 1),
+            HandicapButton::SwordDamage => format!("Sword damage: {}", handicaps.sword_damage),
+            HandicapButton::SnakePace => format!("Snake speed: {}", handicaps.snake_pace.name()),
+            HandicapButton::StartMatch => "Start Match".to_string(),
+            HandicapButton::Back => "Back".to_string(),
+        }
+    }
+}
+
+/// The text of a [`HandicapButton`], which changes as the handicaps are picked.
+#[derive(Component)]
+struct HandicapLabel(HandicapButton);
+
+/// The line telling how the players' recent matches went.
+#[derive(Component)]
+struct SummaryLabel;
+
+/// The side whose player's name is being typed in, if any.
+#[derive(Default)]
+struct EditingName(Option<Side>);
+
+/// Runs the handicap screen, and decides and records versus matches.
+pub struct HandicapPlugin;
+
+impl Plugin for HandicapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WinHistoryPath>()
+            .init_resource::<Players>()
+            .init_resource::<EditingName>()
+            .init_resource::<MatchOutcome>()
+            .add_event::<MatchOverEvent>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::HandicapMenu).with_system(setup_handicap_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::HandicapMenu)
+                    .with_system(menu_button_dynamic_colors)
+                    .with_system(handicap_button_action)
+                    .with_system(type_player_names)
+                    .with_system(suggest_for_players.after(type_player_names))
+                    .with_system(
+                        update_handicap_labels
+                            .after(handicap_button_action)
+                            .after(suggest_for_players),
+                    ),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::HandicapMenu).with_system(cleanup_handicap_screen),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame).with_system(reset_match_outcome),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(record_match_result),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(decide_match.after(SnakeAction::Split)),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                stop_decided_match
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .after(SimulationSystem::Gameplay)
+                    .before(SimulationSystem::Physics),
+            );
+    }
+}
+
+fn setup_handicap_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    (history_path, players): (Res<WinHistoryPath>, Res<Players>),
+    mut editing: ResMut<EditingName>,
+    mut config: ResMut<MatchConfig>,
+) {
+    let history = match &history_path.0 {
+        Some(path) => WinHistory::load(path).unwrap_or_else(|error| {
+            warn!(
+                "Could not read the win history from {}: {}",
+                path.display(),
+                error
+            );
+            WinHistory::default()
+        }),
+        None => WinHistory::default(),
+    };
+    // Start from a fresh match on the default level, like the main menu does
+    *config = MatchConfig {
+        seed: config.seed,
+        handicaps: history.suggest(&players),
+        ..Default::default()
+    };
+    editing.0 = None;
+
+    let style = menu_text_style(&asset_server);
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OnHandicapScreen);
+    let mut labels = Vec::new();
+    let mut summary = None;
+    commands
+        .spawn_bundle(menu_panel())
+        .insert(OnHandicapScreen)
+        .with_children(|parent| {
+            for line in [
+                history.summary(&players),
+                "Suggested picks are filled in".to_string(),
+            ] {
+                let text = parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            line,
+                            TextStyle {
+                                font_size: 24.0,
+                                ..style.clone()
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    })
+                    .id();
+                summary.get_or_insert(text);
+            }
+            for button in [
+                HandicapButton::Name(Side::Slayer),
+                HandicapButton::Name(Side::Snake),
+                HandicapButton::ExtraLives,
+                HandicapButton::SwordDamage,
+                HandicapButton::SnakePace,
+                HandicapButton::StartMatch,
+                HandicapButton::Back,
+            ] {
+                let label = button.label(&config.handicaps, &players, None);
+                labels.push((spawn_menu_button(parent, &style, label, button), button));
+            }
+        });
+    for (label, button) in labels {
+        commands.entity(label).insert(HandicapLabel(button));
+    }
+    if let Some(summary) = summary {
+        commands.entity(summary).insert(SummaryLabel);
+    }
+    commands.insert_resource(history);
+}
+
+fn handicap_button_action(
+    buttons: Query<(&Interaction, &HandicapButton), (Changed<Interaction>, With<Button>)>,
+    mut config: ResMut<MatchConfig>,
+    mut editing: ResMut<EditingName>,
+    mut state: ResMut<State<AppState>>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        // Clicking anywhere else finishes typing a name
+        editing.0 = None;
+        let handicaps = &mut config.handicaps;
+        match button {
+            HandicapButton::Name(side) => editing.0 = Some(*side),
+            HandicapButton::ExtraLives => {
+                handicaps.extra_lives = (handicaps.extra_lives + 1) % (MAX_EXTRA_LIVES + 1)
+            }
+            HandicapButton::SwordDamage => {
+                handicaps.sword_damage = handicaps.sword_damage % MAX_SWORD_DAMAGE + 1
+            }
+            HandicapButton::SnakePace => handicaps.snake_pace = handicaps.snake_pace.next(),
+            HandicapButton::StartMatch => state.set(AppState::InOnePlayerGame).unwrap(),
+            HandicapButton::Back => state.set(AppState::MainMenu).unwrap(),
+        }
+    }
+}
+
+/// Types into the name being entered, until Return is pressed or another button is clicked.
+fn type_player_names(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut editing: ResMut<EditingName>,
+    mut players: ResMut<Players>,
+) {
+    // Characters typed while no name was being entered still need reading
+    let typed: Vec<char> = characters
+        .iter()
+        .map(|character| character.char)
+        .filter(|character| !character.is_control())
+        .collect();
+    let side = match editing.0 {
+        Some(side) => side,
+        None => return,
+    };
+    if keys.just_pressed(KeyCode::Return) {
+        editing.0 = None;
+        return;
+    }
+    // Only touched when something was typed, since a changed name picks new handicaps
+    if keys.just_pressed(KeyCode::Back) {
+        players.name_mut(side).pop();
+    }
+    if !typed.is_empty() {
+        let name = players.name_mut(side);
+        let room = MAX_NAME_LENGTH.saturating_sub(name.chars().count());
+        name.extend(typed.into_iter().take(room));
+    }
+}
+
+/// Fills in the suggested handicaps for the players whose names have been entered.
+fn suggest_for_players(
+    players: Res<Players>,
+    history: Option<Res<WinHistory>>,
+    mut config: ResMut<MatchConfig>,
+    mut summaries: Query<&mut Text, With<SummaryLabel>>,
+) {
+    let history = match history {
+        Some(history) if players.is_changed() => history,
+        _ => return,
+    };
+    config.handicaps = history.suggest(&players);
+    for mut text in summaries.iter_mut() {
+        text.sections[0].value = history.summary(&players);
+    }
+}
+
+fn update_handicap_labels(
+    (config, players, editing): (Res<MatchConfig>, Res<Players>, Res<EditingName>),
+    mut labels: Query<(&mut Text, &HandicapLabel)>,
+) {
+    if config.is_changed() || players.is_changed() || editing.is_changed() {
+        for (mut text, label) in labels.iter_mut() {
+            text.sections[0].value = label.0.label(&config.handicaps, &players, editing.0);
+        }
+    }
+}
+
+fn cleanup_handicap_screen(mut commands: Commands, screen: Query<Entity, With<OnHandicapScreen>>) {
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<WinHistory>();
+}
+
+fn reset_match_outcome(mut outcome: ResMut<MatchOutcome>) {
+    outcome.0 = None;
+}
+
+/// Ends the match once every slayer is out of lives or the main snake is down to its head,
+/// whichever happens first. Modes that [clear the arena](MatchMode::clears_arena) are only won
+/// once every snake is gone, and the tutorial ends itself once it has been worked through.
+fn decide_match(
+    mut deaths: EventReader<SlayerDeathEvent>,
+    mut severed: EventReader<SnakeSeveredEvent>,
+    mut outcome: ResMut<MatchOutcome>,
+    mut match_over: EventWriter<MatchOverEvent>,
+    config: Res<MatchConfig>,
+    snakes: Query<&Snake, (Or<(With<PlayerSnake>, With<SnakeAi>)>, Without<DenSnake>)>,
+    slayers: Query<Entity, With<Slayer>>,
+) {
+    // Slayers out of lives are only despawned once this tick is over
+    let out: Vec<Entity> = deaths
+        .iter()
+        .filter(|death| death.lives_left == 0)
+        .map(|death| death.slayer)
+        .collect();
+    let snake_won = !out.is_empty() && slayers.iter().all(|slayer| out.contains(&slayer));
+    let slayer_won = !config.mode.clears_arena()
+        && config.mode != MatchMode::Tutorial
+        && severed.iter().any(|event| {
+            snakes
+                .get(event.snake)
+                .map_or(false, |snake| snake.len() == 1)
+        });
+    if outcome.0.is_some() {
+        return;
+    }
+    let winner = if snake_won {
+        Side::Snake
+    } else if slayer_won {
+        Side::Slayer
+    } else {
+        return;
+    };
+    outcome.0 = Some(winner);
+    match_over.send(MatchOverEvent { winner });
+}
+
+/// Stops the simulation once the match has been decided, however that happened.
+fn stop_decided_match(outcome: Res<MatchOutcome>, mut clock: ResMut<SimulationClock>) {
+    if outcome.0.is_some() {
+        clock.finish();
+    }
+}
+
+fn record_match_result(
+    mut match_over: EventReader<MatchOverEvent>,
+    (config, players): (Res<MatchConfig>, Res<Players>),
+    history_path: Res<WinHistoryPath>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    for event in match_over.iter() {
+        // Only versus matches say anything about who plays better
+        if config.mode != MatchMode::Versus {
+            continue;
+        }
+        // Watching a replay does not change who has been winning
+        let path = match &history_path.0 {
+            Some(path) if playback.is_none() => path,
+            _ => continue,
+        };
+        let mut history = match WinHistory::load(path) {
+            Ok(history) => history,
+            Err(error) => {
+                warn!(
+                    "Could not read the win history from {}: {}",
+                    path.display(),
+                    error
+                );
+                continue;
+            }
+        };
+        history.record(&players, event.winner, config.handicaps);
+        if let Err(error) = history.save(path) {
+            error!(
+                "Could not save the win history to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handicaps_round_trip() {
+        let handicaps = Handicaps {
+            extra_lives: 2,
+            sword_damage: 3,
+            snake_pace: SnakePace::Slower,
+        };
+        assert_eq!(Handicaps::from_bytes(handicaps.to_bytes()), Some(handicaps));
+        assert_eq!(Handicaps::from_bytes([MAX_EXTRA_LIVES + 1, 1, 1]), None);
+        assert_eq!(Handicaps::from_bytes([0, 0, 1]), None);
+        assert_eq!(Handicaps::from_bytes([0, 1, 3]), None);
+    }
+
+    #[test]
+    fn win_history_round_trips_through_text() {
+        let players = Players {
+            slayer: "Ana Maria".to_string(),
+            snake: "Bo".to_string(),
+        };
+        let mut history = WinHistory::default();
+        history.record(&players, Side::Slayer, Handicaps::default());
+        history.record(
+            &Players::default(),
+            Side::Snake,
+            Handicaps {
+                extra_lives: 1,
+                sword_damage: 2,
+                snake_pace: SnakePace::Faster,
+            },
+        );
+        let text = history.to_string();
+        assert_eq!(
+            text,
+            "slayer 0 1 normal\tAna Maria\tBo\nsnake 1 2 faster\t\t\n"
+        );
+        assert_eq!(text.parse::<WinHistory>().unwrap(), history);
+
+        let damaged: WinHistory =
+            "snake 0 1 normal\ta\tb\nsnake nine\n\nslayer 0 1 normal\ta\nslayer 0 1 normal\t\t\n"
+                .parse()
+                .unwrap();
+        assert_eq!(damaged.results.len(), 2);
+    }
+
+    #[test]
+    fn suggestions_wait_for_a_clear_margin() {
+        let even = Handicaps::default();
+        let players = Players::default();
+        let mut history = WinHistory::default();
+        history.record(&players, Side::Slayer, even);
+        history.record(&players, Side::Snake, even);
+        history.record(&players, Side::Slayer, even);
+        assert_eq!(history.suggest(&players), even);
+
+        history.record(&players, Side::Slayer, even);
+        let suggested = history.suggest(&players);
+        assert_eq!(suggested.snake_pace, SnakePace::Faster);
+
+        // Wins from before the change say nothing about the new handicaps
+        history.record(&players, Side::Slayer, suggested);
+        assert_eq!(history.suggest(&players), suggested);
+    }
+
+    #[test]
+    fn suggestions_only_count_matches_between_the_same_players() {
+        let players = Players {
+            slayer: "Ana".to_string(),
+            snake: "Bo".to_string(),
+        };
+        let swapped = Players {
+            slayer: "Bo".to_string(),
+            snake: "Ana".to_string(),
+        };
+        let mut history = WinHistory::default();
+        history.record(&players, Side::Snake, Handicaps::default());
+        history.record(&swapped, Side::Slayer, Handicaps::default());
+        history.record(&players, Side::Snake, Handicaps::default());
+        assert_eq!(history.suggest(&players).extra_lives, 1);
+        assert_eq!(history.suggest(&swapped), Handicaps::default());
+        assert_eq!(
+            history.summary(&players),
+            "Last 2 matches: slayer 0, snake 2"
+        );
+    }
+
+    #[test]
+    fn heavier_swords_cut_closer_to_the_head() {
+        let heavy = Handicaps {
+            sword_damage: 3,
+            ..Default::default()
+        };
+        assert_eq!(Handicaps::default().severed_at(4), 4);
+        assert_eq!(heavy.severed_at(4), 2);
+        assert_eq!(heavy.severed_at(2), 1);
+    }
+}
@@ -0,0 +1,359 @@
+//! Command-line options for launching the game.
+//!
+//! The binary parses its arguments into [`LaunchOptions`], which then sets up the app:
+//! which screen it opens on, how the match is set up, whether a replay is watched or a
+//! network match is hosted, and so on.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::level::{Level, LevelDirectory};
+use crate::netplay::{LinkConditions, Netplay, DEFAULT_PORT};
+use crate::replay::{Replay, ReplayPlayback};
+use crate::rng::fresh_seed;
+use crate::simulation::SimulationClock;
+use crate::snake_grid::Snake;
+use crate::{AppState, MatchConfig, Slayer};
+
+/// What `--help` prints.
+pub const USAGE: &str = "\
+Usage: unfair_advantage_bin [options]
+
+Options:
+  --start <menu|one|two>   open on the main menu, or go straight into a game
+  --level <file>           play the level stored in <file>
+  --seed <number>          seed the match's randomness
+  --window <width>x<height>
+                           size of the window
+  --fullscreen             fill the screen
+  --mute                   play without sound
+  --replay <file>          watch the replay stored in <file>
+  --headless <ticks>       run a match for <ticks> ticks without a window, then print a summary
+  --host [port]            host a network match, playing the slayer
+  --join <address:port>    join a network match, playing the snake
+  --latency <ms>           hold back outgoing network packets
+  --packet-loss <percent>  drop some outgoing network packets
+  --help                   print this message";
+
+/// Which screen the game opens on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartMode {
+    Menu,
+    OnePlayer,
+    TwoPlayer,
+}
+
+/// Everything that can be chosen from the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchOptions {
+    pub start: StartMode,
+    /// A level file to play instead of the default level.
+    pub level: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub window_size: Vec2,
+    pub fullscreen: bool,
+    pub mute: bool,
+    /// A replay file to watch.
+    pub replay: Option<PathBuf>,
+    /// Run this many ticks without a window, then exit.
+    pub headless_ticks: Option<u64>,
+    /// Host a network match on this port.
+    pub host: Option<u16>,
+    /// Join the network match hosted at this address.
+    pub join: Option<SocketAddr>,
+    pub link: LinkConditions,
+    /// `--help` was given.
+    pub help: bool,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        Self {
+            start: StartMode::Menu,
+            level: None,
+            seed: None,
+            window_size: Vec2::new(1280.0, 720.0),
+            fullscreen: false,
+            mute: false,
+            replay: None,
+            headless_ticks: None,
+            host: None,
+            join: None,
+            link: LinkConditions::default(),
+            help: false,
+        }
+    }
+}
+
+fn value<T: std::str::FromStr>(value: Option<String>, flag: &str, what: &str) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs {}", flag, what))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} needs {}, not {}", flag, what, value))
+}
+
+impl LaunchOptions {
+    /// Reads the options from the arguments that follow the program name.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::launcher::{LaunchOptions, StartMode};
+    /// let args = ["--start", "one", "--seed", "42"].map(String::from);
+    /// let options = LaunchOptions::parse(args).unwrap();
+    /// assert_eq!(options.start, StartMode::OnePlayer);
+    /// assert_eq!(options.seed, Some(42));
+    /// ```
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--start" => {
+                    options.start = match args.next().as_deref() {
+                        Some("menu") => StartMode::Menu,
+                        Some("one") => StartMode::OnePlayer,
+                        Some("two") => StartMode::TwoPlayer,
+                        _ => return Err("--start needs menu, one or two".to_string()),
+                    }
+                }
+                "--level" => options.level = Some(value(args.next(), &flag, "a level file")?),
+                "--seed" => options.seed = Some(value(args.next(), &flag, "a number")?),
+                "--window" => {
+                    let size: String = value(args.next(), &flag, "a size like 1280x720")?;
+                    let parsed = size.split_once('x').and_then(|(width, height)| {
+                        Some(Vec2::new(width.parse().ok()?, height.parse().ok()?))
+                    });
+                    options.window_size = parsed
+                        .filter(|size| size.x >= 1.0 && size.y >= 1.0)
+                        .ok_or_else(|| {
+                            format!("--window needs a size like 1280x720, not {}", size)
+                        })?;
+                }
+                "--fullscreen" => options.fullscreen = true,
+                "--mute" => options.mute = true,
+                "--replay" => options.replay = Some(value(args.next(), &flag, "a replay file")?),
+                "--headless" => {
+                    options.headless_ticks = Some(value(args.next(), &flag, "a number of ticks")?)
+                }
+                "--host" => {
+                    let port = args.next_if(|port| !port.starts_with("--"));
+                    options.host = Some(match port {
+                        Some(port) => value(Some(port), &flag, "a port")?,
+                        None => DEFAULT_PORT,
+                    });
+                }
+                "--join" => options.join = Some(value(args.next(), &flag, "an address:port")?),
+                "--latency" => {
+                    let millis = value(args.next(), &flag, "a number of milliseconds")?;
+                    options.link.latency = Duration::from_millis(millis);
+                }
+                "--packet-loss" => {
+                    let percent: f32 = value(args.next(), &flag, "a percentage")?;
+                    if !(0.0..=100.0).contains(&percent) {
+                        return Err("--packet-loss needs a percentage from 0 to 100".to_string());
+                    }
+                    options.link.packet_loss = percent / 100.0;
+                }
+                "--help" | "-h" => options.help = true,
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+        if options.host.is_some() && options.join.is_some() {
+            return Err("--host and --join cannot be used together".to_string());
+        }
+        Ok(options)
+    }
+
+    /// How matches started by these options are set up, with a fresh seed unless one was
+    /// given.
+    pub fn match_config(&self) -> MatchConfig {
+        let mut config = MatchConfig {
+            seed: self.seed.unwrap_or_else(fresh_seed),
+            ..Default::default()
+        };
+        if let Some(name) = self.level.as_ref().and_then(|path| path.file_stem()) {
+            config.level = name.to_string_lossy().into_owned();
+        }
+        config
+    }
+
+    /// The screen to open on, once options that imply a match are taken into account.
+    fn start_state(&self) -> AppState {
+        match self.start {
+            StartMode::TwoPlayer => AppState::InTwoPlayerGame,
+            _ if self.replay.is_some() || self.headless_ticks.is_some() => {
+                AppState::InOnePlayerGame
+            }
+            StartMode::OnePlayer => AppState::InOnePlayerGame,
+            StartMode::Menu => AppState::MainMenu,
+        }
+    }
+
+    /// Sets up `app`, which already has the game's plugin, as these options ask.
+    pub fn configure(&self, app: &mut App) -> Result<(), String> {
+        let mut config = self.match_config();
+        if let Some(path) = &self.level {
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "level")
+            {
+                return Err(format!("{}: level files end in .level", path.display()));
+            }
+            // Loaded here as well, so that a broken level is reported rather than skipped
+            Level::load(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let directory = path.parent().map(PathBuf::from).unwrap_or_default();
+            app.insert_resource(LevelDirectory(directory));
+        }
+        if let Some(path) = &self.replay {
+            let replay =
+                Replay::load(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let directory = app.world.get_resource::<LevelDirectory>().unwrap();
+            replay
+                .check_level(directory)
+                .map_err(|error| format!("{}: {}", path.display(), error))?;
+            config = replay.config();
+            app.insert_resource(ReplayPlayback::new(replay));
+        }
+
+        if let Some(port) = self.host {
+            let address = SocketAddr::from(([0, 0, 0, 0], port));
+            let netplay = Netplay::host(address, config.clone(), self.link)
+                .map_err(|error| format!("could not host on port {}: {}", port, error))?;
+            app.insert_resource(netplay);
+        } else if let Some(address) = self.join {
+            let netplay = Netplay::join(address, self.link)
+                .map_err(|error| format!("could not join {}: {}", address, error))?;
+            app.insert_resource(netplay);
+        }
+
+        app.insert_resource(config);
+        let start = self.start_state();
+        if start != AppState::MainMenu {
+            app.world
+                .get_resource_mut::<State<AppState>>()
+                .unwrap()
+                .set(start)
+                .map_err(|error| format!("could not start the game: {:?}", error))?;
+        }
+        Ok(())
+    }
+}
+
+/// How a headless run ended up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    pub ticks: u64,
+    pub state: AppState,
+    pub config: MatchConfig,
+    pub snake_lengths: Vec<usize>,
+    /// Where the slayer is standing, unless they were chomped.
+    pub slayer: Option<Vec3>,
+}
+
+impl RunSummary {
+    /// Sums up the game running in `world`.
+    pub fn capture(world: &mut World) -> Self {
+        let snake_lengths = world
+            .query::<&Snake>()
+            .iter(world)
+            .map(|snake| snake.len())
+            .collect();
+        let slayer = world
+            .query_filtered::<&Transform, With<Slayer>>()
+            .iter(world)
+            .next()
+            .map(|transform| transform.translation);
+        Self {
+            ticks: world.get_resource::<SimulationClock>().unwrap().tick(),
+            state: world
+                .get_resource::<State<AppState>>()
+                .unwrap()
+                .current()
+                .clone(),
+            config: world.get_resource::<MatchConfig>().unwrap().clone(),
+            snake_lengths,
+            slayer,
+        }
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "ticks:  {}", self.ticks)?;
+        writeln!(f, "state:  {:?}", self.state)?;
+        writeln!(f, "level:  {}", self.config.level)?;
+        writeln!(f, "seed:   {}", self.config.seed)?;
+        writeln!(
+            f,
+            "snakes: {} {:?}",
+            self.snake_lengths.len(),
+            self.snake_lengths
+        )?;
+        match self.slayer {
+            Some(at) => write!(f, "slayer: alive at ({:.1}, {:.1})", at.x, at.y),
+            None => write!(f, "slayer: chomped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<LaunchOptions, String> {
+        LaunchOptions::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = parse(
+            "--start two --level levels/arena.level --seed 7 --window 800x600 --fullscreen \
+             --mute --headless 600 --host --latency 50 --packet-loss 10",
+        )
+        .unwrap();
+        assert_eq!(
+            options,
+            LaunchOptions {
+                start: StartMode::TwoPlayer,
+                level: Some(PathBuf::from("levels/arena.level")),
+                seed: Some(7),
+                window_size: Vec2::new(800.0, 600.0),
+                fullscreen: true,
+                mute: true,
+                replay: None,
+                headless_ticks: Some(600),
+                host: Some(DEFAULT_PORT),
+                join: None,
+                link: LinkConditions {
+                    latency: Duration::from_millis(50),
+                    packet_loss: 0.1,
+                },
+                help: false,
+            }
+        );
+        assert_eq!(options.match_config().level, "arena");
+        assert_eq!(parse("--host 9000").unwrap().host, Some(9000));
+        assert_eq!(parse("").unwrap(), LaunchOptions::default());
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        for args in [
+            "--start three",
+            "--seed",
+            "--seed lots",
+            "--window 800",
+            "--window 0x600",
+            "--packet-loss 150",
+            "--join nowhere",
+            "--host --join 127.0.0.1:7777",
+            "--turbo",
+        ] {
+            assert!(parse(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+}
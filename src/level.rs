@@ -0,0 +1,500 @@
+//! Levels: how the arena of a match is laid out.
+//!
+//! Levels are stored as plain text with one item per line, so they are easy to diff and
+//! to touch up by hand. Platforms are given by their centre and size in world units,
+//! and everything that lives on the grid by its cell:
+//!
+//! ```text
+//! unfair-advantage-level 1
+//! background snake_den.png
+//! slayer 0 0
+//! snake right 8,9 7,9 6,9
+//! platform 0 -500 1500 50
+//! obstacle 3 4
+//! den 24 1
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::snake_grid::{Arena, Direction, Position, Snake};
+use crate::{MatchConfig, ARENA_HEIGHT, ARENA_WIDTH};
+
+/// The name of the level built into the game, which is never read from disk.
+pub const DEFAULT_LEVEL: &str = "default";
+
+/// The arenas of the survival campaign, which are built into the game like the
+/// [`DEFAULT_LEVEL`].
+pub const SURVIVAL_ARENAS: &[&str] = &["open", "pillars", "nest"];
+
+/// The backgrounds a level can be drawn over.
+pub const BACKGROUNDS: &[&str] = &["snake_den.png"];
+
+const HEADER: &str = "unfair-advantage-level";
+const FORMAT_VERSION: u16 = 1;
+
+/// A solid rectangle for the slayer to stand on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Platform {
+    pub centre: Vec2,
+    pub size: Vec2,
+}
+
+impl Platform {
+    /// The platform spanning the rectangle between two opposite corners.
+    pub fn from_corners(a: Vec2, b: Vec2) -> Self {
+        Self {
+            centre: (a + b) / 2.0,
+            size: (a - b).abs(),
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let offset = (point - self.centre).abs();
+        offset.x <= self.size.x / 2.0 && offset.y <= self.size.y / 2.0
+    }
+}
+
+/// The layout of an arena. The level of the current match is kept as a resource.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    /// The image drawn behind the arena, if any.
+    pub background: Option<String>,
+    /// Where the slayer starts, in world units.
+    pub slayer_spawn: Vec2,
+    /// The snake steered by the snake player, as it is at the start of the match.
+    pub snake: Snake,
+    pub platforms: Vec<Platform>,
+    /// Grid cells that are filled with solid blocks.
+    pub obstacles: Vec<Position>,
+    /// Grid cells holding a snake den, which lets out new snakes until it is destroyed.
+    pub dens: Vec<Position>,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        let arena = Arena::new(ARENA_WIDTH, ARENA_HEIGHT);
+        let mut body: Vec<Position> =
+            Snake::straight(Position::new(8, 9), Direction::Right, 7, &arena)
+                .body()
+                .iter()
+                .copied()
+                .collect();
+        body.push(Position::new(2, 8));
+        Self {
+            background: Some(BACKGROUNDS[0].to_string()),
+            slayer_spawn: Vec2::ZERO,
+            snake: Snake::new(body, Direction::Right),
+            platforms: vec![Platform {
+                centre: Vec2::new(0.0, -500.0),
+                size: Vec2::new(1500.0, 50.0),
+            }],
+            obstacles: Vec::new(),
+            dens: Vec::new(),
+        }
+    }
+}
+
+/// Something went wrong while reading or writing a level.
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    /// The file is not a level.
+    BadHeader,
+    /// The level was written by an incompatible version of the game.
+    UnsupportedVersion(u16),
+    /// A line of the file could not be understood.
+    Invalid {
+        line: usize,
+        message: String,
+    },
+    /// The level has no snake.
+    NoSnake,
+    /// More than one of the snake, an obstacle and a den are in the same cell.
+    Overlap(Position),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "{}", error),
+            LevelError::BadHeader => write!(f, "not a level file"),
+            LevelError::UnsupportedVersion(version) => {
+                write!(f, "unsupported level format version {}", version)
+            }
+            LevelError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+            LevelError::NoSnake => write!(f, "level has no snake"),
+            LevelError::Overlap(cell) => {
+                write!(f, "cell {},{} is taken more than once", cell.x, cell.y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(error: io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
+
+/// Where levels are saved and loaded by name.
+pub struct LevelDirectory(pub PathBuf);
+
+impl Default for LevelDirectory {
+    fn default() -> Self {
+        Self(PathBuf::from("levels"))
+    }
+}
+
+impl LevelDirectory {
+    /// The file the level called `name` is stored in.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.0.join(format!("{}.level", name))
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+fn parse_direction(name: &str) -> Option<Direction> {
+    Direction::ALL
+        .iter()
+        .copied()
+        .find(|direction| direction_name(*direction) == name)
+}
+
+impl Level {
+    /// Writes the level out in its text form.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::level::Level;
+    /// let level = Level::default();
+    /// assert_eq!(Level::from_text(&level.to_text()).unwrap(), level);
+    /// ```
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("{} {}", HEADER, FORMAT_VERSION)];
+        if let Some(background) = &self.background {
+            lines.push(format!("background {}", background));
+        }
+        lines.push(format!(
+            "slayer {} {}",
+            self.slayer_spawn.x, self.slayer_spawn.y
+        ));
+        let mut snake = format!("snake {}", direction_name(self.snake.direction()));
+        for cell in self.snake.body() {
+            snake.push_str(&format!(" {},{}", cell.x, cell.y));
+        }
+        lines.push(snake);
+        for platform in &self.platforms {
+            lines.push(format!(
+                "platform {} {} {} {}",
+                platform.centre.x, platform.centre.y, platform.size.x, platform.size.y
+            ));
+        }
+        for obstacle in &self.obstacles {
+            lines.push(format!("obstacle {} {}", obstacle.x, obstacle.y));
+        }
+        for den in &self.dens {
+            lines.push(format!("den {} {}", den.x, den.y));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Reads a level from its text form.
+    pub fn from_text(text: &str) -> Result<Self, LevelError> {
+        let mut lines = text.lines().enumerate();
+        let version = lines
+            .next()
+            .and_then(|(_, header)| header.strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u16>().ok())
+            .ok_or(LevelError::BadHeader)?;
+        if version != FORMAT_VERSION {
+            return Err(LevelError::UnsupportedVersion(version));
+        }
+
+        let arena = Arena::new(ARENA_WIDTH, ARENA_HEIGHT);
+        let mut level = Level {
+            background: None,
+            slayer_spawn: Vec2::ZERO,
+            snake: Snake::new(vec![Position::new(0, 0)], Direction::Right),
+            platforms: Vec::new(),
+            obstacles: Vec::new(),
+            dens: Vec::new(),
+        };
+        let mut has_snake = false;
+        for (index, line) in lines {
+            let invalid = |message: &str| LevelError::Invalid {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let mut words = line.split_whitespace();
+            let numbers = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, LevelError> {
+                words
+                    .map(|word| word.parse().map_err(|_| invalid("expected a number")))
+                    .collect()
+            };
+            let cell = |x: f32, y: f32| -> Result<Position, LevelError> {
+                let cell = Position::new(x as i32, y as i32);
+                if cell.x as f32 != x || cell.y as f32 != y || !arena.contains(cell) {
+                    return Err(invalid("expected a cell inside the arena"));
+                }
+                Ok(cell)
+            };
+            match words.next() {
+                None => {}
+                Some("background") => {
+                    level.background = Some(words.collect::<Vec<_>>().join(" "));
+                }
+                Some("slayer") => match numbers(words)?[..] {
+                    [x, y] => level.slayer_spawn = Vec2::new(x, y),
+                    _ => return Err(invalid("expected slayer x y")),
+                },
+                Some("snake") => {
+                    let direction = words
+                        .next()
+                        .and_then(parse_direction)
+                        .ok_or_else(|| invalid("expected the direction of the snake"))?;
+                    let body = words
+                        .map(|word| {
+                            let (x, y) = word
+                                .split_once(',')
+                                .ok_or_else(|| invalid("expected cells as x,y"))?;
+                            let x = x.parse().map_err(|_| invalid("expected a number"))?;
+                            let y = y.parse().map_err(|_| invalid("expected a number"))?;
+                            cell(x, y)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if body.is_empty() {
+                        return Err(invalid("a snake needs at least a head"));
+                    }
+                    level.snake = Snake::new(body, direction);
+                    has_snake = true;
+                }
+                Some("platform") => match numbers(words)?[..] {
+                    [x, y, width, height] if width > 0.0 && height > 0.0 => {
+                        level.platforms.push(Platform {
+                            centre: Vec2::new(x, y),
+                            size: Vec2::new(width, height),
+                        })
+                    }
+                    _ => return Err(invalid("expected platform x y width height")),
+                },
+                Some("obstacle") => match numbers(words)?[..] {
+                    [x, y] => level.obstacles.push(cell(x, y)?),
+                    _ => return Err(invalid("expected obstacle x y")),
+                },
+                Some("den") => match numbers(words)?[..] {
+                    [x, y] => level.dens.push(cell(x, y)?),
+                    _ => return Err(invalid("expected den x y")),
+                },
+                Some(other) => return Err(invalid(&format!("unknown item {}", other))),
+            }
+        }
+        if !has_snake {
+            return Err(LevelError::NoSnake);
+        }
+        if let Some(cell) = level.overlap() {
+            return Err(LevelError::Overlap(cell));
+        }
+        Ok(level)
+    }
+
+    /// Whether the snake, an obstacle or a den is in `cell`.
+    pub fn is_taken(&self, cell: Position) -> bool {
+        self.snake.body().contains(&cell)
+            || self.obstacles.contains(&cell)
+            || self.dens.contains(&cell)
+    }
+
+    /// A cell that more than one of the snake, the obstacles and the dens are in, if any.
+    fn overlap(&self) -> Option<Position> {
+        let snake = self.snake.body();
+        self.obstacles
+            .iter()
+            .chain(&self.dens)
+            .find(|cell| snake.contains(cell))
+            .or_else(|| self.dens.iter().find(|den| self.obstacles.contains(den)))
+            .copied()
+    }
+
+    /// Writes the level to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), LevelError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// Reads the level stored at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LevelError> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// The level called `name`, read from `directory` unless it is built into the game.
+    pub fn named(name: &str, directory: &LevelDirectory) -> Result<Self, LevelError> {
+        match Self::built_in(name) {
+            Some(level) => Ok(level),
+            None => Self::load(directory.path(name)),
+        }
+    }
+
+    /// The [`DEFAULT_LEVEL`] or one of the [`SURVIVAL_ARENAS`], if `name` is one of them.
+    pub fn built_in(name: &str) -> Option<Self> {
+        let level = Self::default();
+        match name {
+            DEFAULT_LEVEL | "open" => Some(level),
+            "pillars" => Some(Self {
+                obstacles: [5, 10, 17, 22]
+                    .into_iter()
+                    .flat_map(|x| [3, 4, 10, 11].map(|y| Position::new(x, y)))
+                    .collect(),
+                ..level
+            }),
+            "nest" => Some(Self {
+                dens: vec![Position::new(3, 1), Position::new(24, 1)],
+                ..level
+            }),
+            _ => None,
+        }
+    }
+
+    /// A fingerprint of the level's layout, which changes whenever the level is edited.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::level::Level;
+    /// let pillars = Level::built_in("pillars").unwrap();
+    /// assert_eq!(pillars.fingerprint(), pillars.clone().fingerprint());
+    /// assert_ne!(pillars.fingerprint(), Level::default().fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a, which unlike the standard hasher is the same from one build to the next
+        self.to_text()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+}
+
+/// Loads the level named in the [`MatchConfig`], falling back to the default level.
+pub fn load_level(
+    config: Res<MatchConfig>,
+    directory: Res<LevelDirectory>,
+    mut level: ResMut<Level>,
+) {
+    *level = Level::named(&config.level, &directory).unwrap_or_else(|error| {
+        warn!("Could not load level {}: {}", config.level, error);
+        Level::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_survive_a_round_trip() {
+        let level = Level {
+            background: None,
+            slayer_spawn: Vec2::new(-12.5, 96.0),
+            snake: Snake::new(
+                vec![
+                    Position::new(4, 4),
+                    Position::new(4, 5),
+                    Position::new(5, 5),
+                ],
+                Direction::Down,
+            ),
+            platforms: vec![Platform::from_corners(
+                Vec2::new(-64.0, 32.0),
+                Vec2::new(128.0, 0.0),
+            )],
+            obstacles: vec![Position::new(0, 0), Position::new(27, 13)],
+            dens: vec![Position::new(10, 2)],
+        };
+        assert_eq!(Level::from_text(&level.to_text()).unwrap(), level);
+    }
+
+    #[test]
+    fn rejects_broken_levels() {
+        assert!(matches!(
+            Level::from_text("not a level"),
+            Err(LevelError::BadHeader)
+        ));
+        assert!(matches!(
+            Level::from_text("unfair-advantage-level 99\n"),
+            Err(LevelError::UnsupportedVersion(99))
+        ));
+        assert!(matches!(
+            Level::from_text("unfair-advantage-level 1\nslayer 0 0\n"),
+            Err(LevelError::NoSnake)
+        ));
+        for line in [
+            "snake sideways 1,1",
+            "snake up 1;1",
+            "snake up 100,1",
+            "obstacle 1.5 2",
+            "den 3 -1",
+            "platform 0 0 -10 10",
+            "slayer 0",
+            "teleporter 1 2",
+        ] {
+            let text = format!("unfair-advantage-level 1\nsnake up 1,1\n{}\n", line);
+            assert!(
+                matches!(
+                    Level::from_text(&text),
+                    Err(LevelError::Invalid { line: 3, .. })
+                ),
+                "{:?} was accepted",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_levels_with_things_on_top_of_each_other() {
+        for line in ["obstacle 1 1", "den 1 2", "obstacle 5 5\nden 5 5"] {
+            let text = format!("unfair-advantage-level 1\nsnake up 1,1 1,2\n{}\n", line);
+            assert!(
+                matches!(Level::from_text(&text), Err(LevelError::Overlap(_))),
+                "{:?} was accepted",
+                line
+            );
+        }
+        let text = "unfair-advantage-level 1\nsnake up 1,1 1,2\nobstacle 2 2\nden 3 3\n";
+        let level = Level::from_text(text).unwrap();
+        assert!(level.is_taken(Position::new(1, 2)));
+        assert!(level.is_taken(Position::new(3, 3)));
+        assert!(!level.is_taken(Position::new(4, 4)));
+    }
+
+    #[test]
+    fn survival_arenas_are_built_in() {
+        let directory = LevelDirectory(PathBuf::from("no-such-directory"));
+        for name in SURVIVAL_ARENAS {
+            let level = Level::named(name, &directory).unwrap();
+            assert!(level
+                .obstacles
+                .iter()
+                .all(|cell| !level.dens.contains(cell) && !level.snake.body().contains(cell)));
+        }
+        assert!(Level::built_in("my-level").is_none());
+    }
+}
@@ -4,58 +4,199 @@
 #![doc = include_str!("../README.md")]
 
 
+use std::collections::HashMap;
+use std::time::Duration;
+
 use bevy::app::AppExit;
 use bevy::core::Time;
+use bevy::ecs::component::TableStorage;
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::window::ReceivedCharacter;
 use heron::prelude::*;
 use bevy_kira_audio::{Audio, AudioPlugin};
 
+pub mod camera;
+pub mod co_op;
+#[cfg(feature = "dev_console")]
+pub mod console;
+#[cfg(feature = "debug_overlay")]
+pub mod debug_overlay;
+pub mod editor;
+pub mod effects;
+pub mod game_over;
+pub mod handicap;
+pub mod launcher;
+pub mod level;
+pub mod netplay;
+pub mod particles;
+pub mod power_ups;
+pub mod replay;
+pub mod rng;
+pub mod save_game;
+pub mod simulation;
+pub mod snake_abilities;
+pub mod snake_ai;
+pub mod snake_den;
+pub mod snake_grid;
+pub mod survival;
+pub mod time_attack;
+pub mod tutorial;
 pub mod utils;
 
+use camera::{spawn_game_camera, GameCamera, GameCameraPlugin};
+use co_op::{CoOpPlugin, CO_OP_EXTRA_LIVES};
+use editor::LevelEditorPlugin;
+use effects::{ScreenEffects, ScreenEffectsLabel, ScreenEffectsPlugin};
+use game_over::GameOverPlugin;
+use handicap::{ExtraLives, HandicapPlugin, Handicaps, MatchOutcome};
+use level::{load_level, Level, LevelDirectory};
+use netplay::{NetplayPlugin, Side};
+use particles::ParticlePlugin;
+use power_ups::{Buffs, PowerUp, PowerUpPlugin};
+use replay::{Replay, ReplayPlayback, ReplayPlugin, LAST_REPLAY_PATH};
+use rng::{fresh_seed, RngPlugin};
+use save_game::{PendingRestore, SaveGamePath, SaveGamePlugin, SaveMatchEvent, SavedMatch};
+use snake_abilities::{SnakeAbilities, SnakeAbilityPlugin};
+use snake_ai::{AiProfile, SnakeAi, SnakeAiPlugin};
+use snake_den::{SnakeDen, SnakeDenPlugin};
+use survival::{SurvivalPlugin, SurvivalProgress, SurvivalProgressPath, SURVIVAL_EXTRA_LIVES};
+use time_attack::{TimeAttackPlugin, TIME_ATTACK_EXTRA_LIVES};
+use tutorial::{TutorialPlugin, TUTORIAL_LEVEL, TUTORIAL_SNAKE_INTERVAL};
+use simulation::{
+    in_state, SimulationClock, SimulationPlugin, SimulationStage, SimulationSystem,
+    TickCollisions, TickInput, TickedPhysicsPlugin,
+};
+use snake_grid::{Arena, Collision, Direction, Occupancy, Position, Snake};
+
 /// A plugin
-pub struct UnfairAdvantagePlugin;
+///
+/// Use [`UnfairAdvantagePlugin::headless`] to run the game without a window, audio or rendering,
+/// as tests and tools do.
+#[derive(Default)]
+pub struct UnfairAdvantagePlugin {
+    /// Skip everything that needs an audio device or a renderer.
+    pub headless: bool,
+    /// Play without sound.
+    pub muted: bool,
+}
+
+impl UnfairAdvantagePlugin {
+    pub fn headless() -> Self {
+        Self {
+            headless: true,
+            ..Default::default()
+        }
+    }
+}
 
 impl Plugin for UnfairAdvantagePlugin {
     fn build(&self, app: &mut App) {
         app
+        .add_plugin(SimulationPlugin)
+        .add_plugin(RngPlugin)
+        .add_plugin(GameCameraPlugin)
+        .add_plugin(ScreenEffectsPlugin)
+        .add_plugin(ParticlePlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(LevelEditorPlugin)
+        .add_plugin(SaveGamePlugin)
+        .add_plugin(NetplayPlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(PowerUpPlugin)
+        .add_plugin(SnakeAbilityPlugin)
+        .add_plugin(HandicapPlugin)
+        .add_plugin(SnakeAiPlugin)
+        .add_plugin(CoOpPlugin)
+        .add_plugin(SnakeDenPlugin)
+        .add_plugin(SurvivalPlugin)
+        .add_plugin(TimeAttackPlugin)
+        .add_plugin(TutorialPlugin)
         .add_state(AppState::MainMenu)
+        .init_resource::<MatchConfig>()
+        .init_resource::<Level>()
+        .init_resource::<LevelDirectory>()
         .init_resource::<SnakeTimer>()
-        .insert_resource(SnakeSegments::default())
+        .init_resource::<SnakeSlowdown>()
+        .init_resource::<SmoothMotion>()
+        .insert_resource(Arena::new(ARENA_WIDTH, ARENA_HEIGHT))
+        .insert_resource(Occupancy::<Entity>::new(Arena::new(ARENA_WIDTH, ARENA_HEIGHT)))
         .insert_resource(LastTailPosition::default())
         .add_event::<GameOverEvent>()
         .add_event::<SnakeSplitEvent>()
+        .add_event::<SnakeSeveredEvent>()
+        .add_event::<SnakeKilledEvent>()
+        .add_event::<ArenaClearedEvent>()
+        .add_event::<SwordSwingEvent>()
+        .add_event::<SwordHitEvent>()
+        .add_event::<SlayerDeathEvent>()
         .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(setup_main_menu))
         .add_system_set(
             SystemSet::on_update(AppState::MainMenu)
                 .with_system(menu_button_dynamic_colors)
                 .with_system(menu_button_action)
+                .with_system(update_seed_label)
             )
             .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(cleanup_main_menu))
-        .add_system_set(SystemSet::on_enter(AppState::PauseMenu).with_system(setup_pause_menu))
+        .add_system_set(SystemSet::on_enter(AppState::PauseMenu)
+            .with_system(setup_pause_menu)
+            .with_system(pause_simulation_clock)
+        )
         .add_system_set(
             SystemSet::on_update(AppState::PauseMenu)
                 .with_system(menu_button_dynamic_colors)
                 .with_system(menu_button_action)
         )
-        .add_system_set(SystemSet::on_exit(AppState::PauseMenu).with_system(cleanup_pause_menu))
+        .add_system_set(SystemSet::on_exit(AppState::PauseMenu)
+            .with_system(cleanup_pause_menu)
+            .with_system(resume_simulation_clock)
+        )
         .add_system_set(SystemSet::on_enter(AppState::InOnePlayerGame)
-            .with_system(setup_one_player_game)
-            .with_system(spawn_snake)
+            .with_system(load_level.label(MatchSetup::LoadLevel))
+            .with_system(setup_one_player_game.after(MatchSetup::LoadLevel))
+            .with_system(spawn_snake.after(MatchSetup::LoadLevel))
+            .with_system(reset_simulation_clock)
+            .with_system(reset_snake_timer)
+            .with_system(reset_occupancy.after(MatchSetup::LoadLevel))
         )
         .add_system_set(
             SystemSet::on_update(AppState::InOnePlayerGame)
-            .with_system(slayer_controls)
+            .with_system(pause_game)
+            .with_system(snake_rip_sound)
             .with_system(slayer_animator)
+        )
+        // Drawn after the frame's ticks, so the snakes slide between cells even on frames
+        // where the physics step put them on their exact cells
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            position_translation
+                .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                .before(TransformSystem::TransformPropagate),
+        )
+        .add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+            .with_run_criteria(in_state(AppState::InOnePlayerGame))
+            .label(SimulationSystem::Gameplay)
+            .after(SimulationSystem::Input)
+            .with_system(slayer_feet.before(SlayerAction::Controls))
+            .with_system(slayer_controls.label(SlayerAction::Controls))
+            .with_system(
+                sword_hits
+                    .after(SlayerAction::Controls)
+                    .before(SnakeAction::Movement),
+            )
             .with_system(
                 snake_movement_input
                 .label(SnakeAction::Input)
                 .before(SnakeAction::Movement),
             )
             .with_system(game_over.after(SnakeAction::Movement))
+            .with_system(occupy_new_snakes.before(SnakeAction::Movement))
             .with_system(snake_movement.label(SnakeAction::Movement))
-            .with_system(position_translation)
+            .with_system(snake_split.label(SnakeAction::Split).after(SnakeAction::Movement))
+            .with_system(finish_off_snakes.label(SnakeAction::Death).after(SnakeAction::Split))
             .with_system(slayer_death)
-                // .with_system(size_scaling)
         )
         .add_system_set(SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_game))
         .add_system_set(SystemSet::on_enter(AppState::InTwoPlayerGame).with_system(setup_two_player_game))
@@ -66,25 +207,169 @@ impl Plugin for UnfairAdvantagePlugin {
         )
         .add_system_set(SystemSet::on_exit(AppState::InTwoPlayerGame).with_system(cleanup_game))
         .add_plugin(PhysicsPlugin::default())
-        .add_plugin(AudioPlugin)
+        .add_plugin(TickedPhysicsPlugin)
+        .add_system_to_stage(
+            SimulationStage,
+            place_for_physics
+                .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                .label(SimulationSystem::Physics)
+                .after(SimulationSystem::Gameplay),
+        )
         .insert_resource(Gravity::from(Vec3::new(0.0, -300.0, 0.0)));
+
+        if self.headless {
+            // Stand in for what the audio, render and window plugins would provide;
+            // sounds are queued but never played
+            app.init_resource::<Audio>()
+                .add_asset::<Image>()
+                .add_asset::<TextureAtlas>()
+                .add_event::<ReceivedCharacter>();
+        } else if self.muted {
+            app.init_resource::<Audio>();
+        } else {
+            app.add_plugin(AudioPlugin);
+        }
+
+        #[cfg(feature = "debug_overlay")]
+        app.add_plugin(debug_overlay::DebugOverlayPlugin);
+        #[cfg(feature = "dev_console")]
+        app.add_plugin(console::ConsolePlugin);
     }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
-enum AppState {
+pub enum AppState {
     MainMenu,
     PauseMenu,
     InOnePlayerGame,
     InTwoPlayerGame,
+    LevelEditor,
+    /// Where the players pick handicaps before a versus match.
+    HandicapMenu,
+    /// The break between two waves of a survival campaign.
+    WaveIntermission,
+}
+
+/// How a match is set up before its first tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchConfig {
+    pub seed: u64,
+    pub level: String,
+    pub handicaps: Handicaps,
+    pub mode: MatchMode,
+    /// Which wave of the survival campaign is played, counting from 1.
+    pub wave: u32,
+}
+
+impl MatchConfig {
+    /// A survival match on `wave`, in that wave's arena.
+    pub fn survival(seed: u64, wave: u32) -> Self {
+        Self {
+            seed,
+            level: survival::wave(wave).arena.to_string(),
+            mode: MatchMode::Survival,
+            wave,
+            ..Default::default()
+        }
+    }
+
+    /// The tutorial, in its own arena.
+    pub fn tutorial(seed: u64) -> Self {
+        Self {
+            seed,
+            level: TUTORIAL_LEVEL.to_string(),
+            mode: MatchMode::Tutorial,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            level: "default".to_string(),
+            handicaps: Handicaps::default(),
+            mode: MatchMode::Versus,
+            wave: 1,
+        }
+    }
+}
+
+/// Who plays against whom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    /// The slayer against the snake player.
+    Versus,
+    /// Two slayers against a snake that steers itself.
+    CoOp,
+    /// The slayer against waves of snakes that steer themselves.
+    Survival,
+    /// The slayer against the clock, to clear the level's snake as fast as they can.
+    TimeAttack,
+    /// A scripted match that teaches a new player the slayer's controls.
+    Tutorial,
+}
+
+impl MatchMode {
+    /// How many slayers take part.
+    pub fn slayers(self) -> usize {
+        match self {
+            MatchMode::Versus | MatchMode::Survival | MatchMode::TimeAttack | MatchMode::Tutorial => 1,
+            MatchMode::CoOp => 2,
+        }
+    }
+
+    /// Do snakes cut down to their head die, so that the slayer wins by clearing the arena?
+    pub fn clears_arena(self) -> bool {
+        matches!(self, MatchMode::Survival | MatchMode::TimeAttack)
+    }
+
+    /// Can a match be saved part of the way through? Time attack runs are timed from the start,
+    /// and the tutorial's progress is not kept in saves.
+    pub fn can_be_saved(self) -> bool {
+        !matches!(self, MatchMode::TimeAttack | MatchMode::Tutorial)
+    }
+
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(MatchMode::Versus),
+            1 => Some(MatchMode::CoOp),
+            2 => Some(MatchMode::Survival),
+            3 => Some(MatchMode::TimeAttack),
+            4 => Some(MatchMode::Tutorial),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MatchMode {
+    fn default() -> Self {
+        MatchMode::Versus
+    }
 }
 
 #[derive(Component)]
 enum MenuButtonAction {
+    ContinueSavedMatch,
     StartOnePlayerGame,
+    RerollSeed,
     StartTwoPlayerGame,
+    StartCoOpGame,
+    StartSurvival,
+    StartTimeAttack,
+    StartTutorial,
+    OpenHandicaps,
+    WatchReplay,
+    OpenLevelEditor,
     ExitApp,
     ResumeGame,
+    CycleScreenEffects,
+    SaveAndQuit,
     QuitGame,
 }
 
@@ -94,127 +379,142 @@ struct OnMainMenuScreen;
 #[derive(Component)]
 struct OnPauseMenuScreen;
 
+/// The text of the main menu button that shows the seed of the next match.
+#[derive(Component)]
+struct SeedLabel;
+
 #[derive(Component)]
 struct Slayer;
 
+/// Which player steers a slayer, counting from 0.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlayerPlayer(pub usize);
+
+/// How each slayer is tinted, in player order, so that co-op players can tell their slayers apart.
+pub(crate) const SLAYER_TINTS: [Color; 2] = [Color::WHITE, Color::rgb(0.55, 0.8, 1.0)];
+
+/// Snake heads pass harmlessly through a slayer with this.
+#[derive(Component)]
+pub struct Invulnerable;
+
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 
-fn setup_main_menu(mut commands: Commands, asset_server: Res<AssetServer>, audio: Res<Audio>) {
-    // ui camera
-    audio.play_looped(asset_server.load("music/main_menu_theme.ogg"));
-    commands.spawn_bundle(UiCameraBundle::default()).insert(OnMainMenuScreen);
-    commands
-        .spawn_bundle(NodeBundle {
+/// The text style of menu buttons.
+pub(crate) fn menu_text_style(asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size: 40.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    }
+}
+
+/// The panel menu buttons are laid out in, which grows to fit them but never past the window.
+pub(crate) fn menu_panel() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            margin: Rect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            padding: Rect::all(Val::Px(30.0)),
+            max_size: Size::new(Val::Percent(90.0), Val::Percent(95.0)),
+            ..Default::default()
+        },
+        color: Color::TEAL.into(),
+        ..Default::default()
+    }
+}
+
+/// Adds a button reading `label` to a menu, tagged with `action`, and returns the label's entity.
+pub(crate) fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    style: &TextStyle,
+    label: impl Into<String>,
+    action: impl Component,
+) -> Entity {
+    let mut text = None;
+    parent
+        .spawn_bundle(ButtonBundle {
             style: Style {
-                margin: Rect::all(Val::Auto),
-                flex_direction: FlexDirection::ColumnReverse,
+                size: Size::new(Val::Px(500.0), Val::Auto),
+                // Shrink to the text before running off the bottom of a small window
+                max_size: Size::new(Val::Percent(100.0), Val::Px(100.0)),
+                flex_grow: 1.0,
+                margin: Rect::all(Val::Px(5.0)),
+                padding: Rect::all(Val::Px(10.0)),
+                // horizontally center child text
+                justify_content: JustifyContent::Center,
+                // vertically center child text
                 align_items: AlignItems::Center,
-                border: Rect::all(Val::Px(30.0)),
-                size: Size{
-                    width: Val::Px(700.0),
-                    height: Val::Px(700.0),
-                },
                 ..Default::default()
             },
-            color: Color::TEAL.into(),
+            color: NORMAL_BUTTON.into(),
             ..Default::default()
         })
-        .insert(OnMainMenuScreen)
+        .insert(action)
         .with_children(|parent| {
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(500.0), Val::Px(100.0)),
-                        // center button
-                        margin: Rect::all(Val::Auto),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(MenuButtonAction::StartOnePlayerGame)
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Start 1 Player Game",
-                            TextStyle {
-                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
+            text = Some(
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text::with_section(label, style.clone(), Default::default()),
                         ..Default::default()
-                    });
-                });
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(500.0), Val::Px(100.0)),
-                        // center button
-                        margin: Rect::all(Val::Auto),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(MenuButtonAction::StartTwoPlayerGame)
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Start 2 Player Game",
-                            TextStyle {
-                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(500.0), Val::Px(100.0)),
-                        // center button
-                        margin: Rect::all(Val::Auto),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(MenuButtonAction::ExitApp)
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Exit App",
-                            TextStyle {
-                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
-        
+                    })
+                    .id(),
+            );
+        });
+    text.unwrap()
+}
+
+fn setup_main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    save_path: Res<SaveGamePath>,
+    config: Res<MatchConfig>,
+) {
+    let has_save = save_path.0.as_ref().map_or(false, |path| path.exists());
+    let style = menu_text_style(&asset_server);
+    // ui camera
+    audio.play_looped(asset_server.load("music/main_menu_theme.ogg"));
+    commands.spawn_bundle(UiCameraBundle::default()).insert(OnMainMenuScreen);
+    let mut seed = None;
+    commands
+        .spawn_bundle(menu_panel())
+        .insert(OnMainMenuScreen)
+        .with_children(|parent| {
+            if has_save {
+                spawn_menu_button(parent, &style, "Continue Saved Game", MenuButtonAction::ContinueSavedMatch);
+            }
+            spawn_menu_button(parent, &style, "Start 1 Player Game", MenuButtonAction::StartOnePlayerGame);
+            seed = Some(spawn_menu_button(
+                parent, &style, seed_label(config.seed), MenuButtonAction::RerollSeed,
+            ));
+            spawn_menu_button(parent, &style, "Start 2 Player Game", MenuButtonAction::StartTwoPlayerGame);
+            spawn_menu_button(parent, &style, "Start Co-op Game", MenuButtonAction::StartCoOpGame);
+            spawn_menu_button(parent, &style, "Survival Campaign", MenuButtonAction::StartSurvival);
+            spawn_menu_button(parent, &style, "Time Attack", MenuButtonAction::StartTimeAttack);
+            spawn_menu_button(parent, &style, "Tutorial", MenuButtonAction::StartTutorial);
+            spawn_menu_button(parent, &style, "Versus Handicaps", MenuButtonAction::OpenHandicaps);
+            spawn_menu_button(parent, &style, "Watch Last Replay", MenuButtonAction::WatchReplay);
+            spawn_menu_button(parent, &style, "Level Editor", MenuButtonAction::OpenLevelEditor);
+            spawn_menu_button(parent, &style, "Exit App", MenuButtonAction::ExitApp);
         });
+    if let Some(seed) = seed {
+        commands.entity(seed).insert(SeedLabel);
+    }
+}
+
+fn seed_label(seed: u64) -> String {
+    format!("Seed: {}", seed)
+}
+
+fn update_seed_label(config: Res<MatchConfig>, mut labels: Query<&mut Text, With<SeedLabel>>) {
+    if config.is_changed() {
+        for mut text in labels.iter_mut() {
+            text.sections[0].value = seed_label(config.seed);
+        }
+    }
 }
 
 fn menu_button_dynamic_colors(
@@ -249,88 +549,32 @@ fn cleanup_main_menu(
     }
 }
 
-fn setup_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    screen_effects: Res<ScreenEffects>,
+    config: Res<MatchConfig>,
+) {
+    let style = menu_text_style(&asset_server);
     // ui camera
     commands.spawn_bundle(UiCameraBundle::default()).insert(OnPauseMenuScreen);
+    let mut effects_label = None;
     commands
-        .spawn_bundle(NodeBundle {
-            style: Style {
-                margin: Rect::all(Val::Auto),
-                flex_direction: FlexDirection::ColumnReverse,
-                align_items: AlignItems::Center,
-                border: Rect::all(Val::Px(30.0)),
-                size: Size{
-                    width: Val::Px(700.0),
-                    height: Val::Px(500.0),
-                },
-                ..Default::default()
-            },
-            color: Color::TEAL.into(),
-            ..Default::default()
-        })
+        .spawn_bundle(menu_panel())
         .insert(OnPauseMenuScreen)
         .with_children(|parent| {
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(500.0), Val::Px(100.0)),
-                        // center button
-                        margin: Rect::all(Val::Auto),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(MenuButtonAction::ResumeGame)
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Resume",
-                            TextStyle {
-                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
-            parent
-                .spawn_bundle(ButtonBundle {
-                    style: Style {
-                        size: Size::new(Val::Px(500.0), Val::Px(100.0)),
-                        // center button
-                        margin: Rect::all(Val::Auto),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        ..Default::default()
-                    },
-                    color: NORMAL_BUTTON.into(),
-                    ..Default::default()
-                })
-                .insert(MenuButtonAction::QuitGame)
-                .with_children(|parent| {
-                    parent.spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Quit Game",
-                            TextStyle {
-                                font: asset_server.load("fonts/GoMono-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    });
-                });
-        });  
+            spawn_menu_button(parent, &style, "Resume", MenuButtonAction::ResumeGame);
+            effects_label = Some(spawn_menu_button(
+                parent, &style, screen_effects.label(), MenuButtonAction::CycleScreenEffects,
+            ));
+            if config.mode.can_be_saved() {
+                spawn_menu_button(parent, &style, "Save and Quit", MenuButtonAction::SaveAndQuit);
+            }
+            spawn_menu_button(parent, &style, "Quit Game", MenuButtonAction::QuitGame);
+        });
+    if let Some(effects_label) = effects_label {
+        commands.entity(effects_label).insert(ScreenEffectsLabel);
+    }
 }
 
 fn cleanup_pause_menu(
@@ -343,20 +587,89 @@ fn cleanup_pause_menu(
 }
 
 fn menu_button_action(
+    mut commands: Commands,
     interaction_query: Query<
         (&Interaction, &MenuButtonAction),
         (Changed<Interaction>, With<Button>),
     >,
     mut state: ResMut<State<AppState>>,
-    mut app_exit_events: EventWriter<AppExit>,
+    mut screen_effects: ResMut<ScreenEffects>,
+    (save_path, survival_path, level_directory):
+        (Res<SaveGamePath>, Res<SurvivalProgressPath>, Res<LevelDirectory>),
+    (mut app_exit_events, mut save_events): (EventWriter<AppExit>, EventWriter<SaveMatchEvent>),
+    mut config: ResMut<MatchConfig>,
 ) {
     for (interaction, menu_button_action) in interaction_query.iter() {
         if *interaction == Interaction::Clicked {
             match menu_button_action {
-                MenuButtonAction::StartOnePlayerGame => state.set(AppState::InOnePlayerGame).unwrap(),
+                MenuButtonAction::ContinueSavedMatch => {
+                    let saved = save_path.0.as_ref().map(|path| (path, SavedMatch::load(path)));
+                    match saved {
+                        Some((_, Ok(saved))) => {
+                            commands.insert_resource(saved.config.clone());
+                            commands.insert_resource(PendingRestore(saved));
+                            state.set(AppState::InOnePlayerGame).unwrap();
+                        }
+                        Some((path, Err(error))) => {
+                            warn!("Could not load the saved match from {}: {}", path.display(), error)
+                        }
+                        None => {}
+                    }
+                }
+                MenuButtonAction::StartOnePlayerGame => {
+                    // Keep the seed shown on the menu, but nothing else a replay or test play left behind
+                    *config = MatchConfig {
+                        seed: config.seed,
+                        ..Default::default()
+                    };
+                    state.set(AppState::InOnePlayerGame).unwrap();
+                }
+                MenuButtonAction::RerollSeed => config.seed = fresh_seed(),
                 MenuButtonAction::StartTwoPlayerGame => state.set(AppState::InTwoPlayerGame).unwrap(),
+                MenuButtonAction::StartCoOpGame => {
+                    *config = MatchConfig {
+                        seed: config.seed,
+                        mode: MatchMode::CoOp,
+                        ..Default::default()
+                    };
+                    state.set(AppState::InOnePlayerGame).unwrap();
+                }
+                MenuButtonAction::StartSurvival => {
+                    // Carry on from the wave the last campaign got to
+                    let progress = survival_path.0.as_ref()
+                        .map(SurvivalProgress::load)
+                        .unwrap_or_default();
+                    *config = MatchConfig::survival(config.seed, progress.wave);
+                    state.set(AppState::InOnePlayerGame).unwrap();
+                }
+                MenuButtonAction::StartTimeAttack => {
+                    *config = MatchConfig {
+                        seed: config.seed,
+                        mode: MatchMode::TimeAttack,
+                        ..Default::default()
+                    };
+                    state.set(AppState::InOnePlayerGame).unwrap();
+                }
+                MenuButtonAction::StartTutorial => {
+                    *config = MatchConfig::tutorial(config.seed);
+                    state.set(AppState::InOnePlayerGame).unwrap();
+                }
+                MenuButtonAction::OpenHandicaps => state.set(AppState::HandicapMenu).unwrap(),
+                MenuButtonAction::WatchReplay => match Replay::load(LAST_REPLAY_PATH)
+                    .and_then(|replay| replay.check_level(&level_directory).map(|_| replay))
+                {
+                    Ok(replay) => {
+                        commands.insert_resource(replay.config());
+                        commands.insert_resource(ReplayPlayback::new(replay));
+                        state.set(AppState::InOnePlayerGame).unwrap();
+                    }
+                    Err(error) => warn!("Could not load replay from {}: {}", LAST_REPLAY_PATH, error),
+                },
+                MenuButtonAction::OpenLevelEditor => state.set(AppState::LevelEditor).unwrap(),
                 MenuButtonAction::ExitApp => app_exit_events.send(AppExit),
                 MenuButtonAction::ResumeGame => state.pop().unwrap(),
+                MenuButtonAction::CycleScreenEffects => *screen_effects = screen_effects.next(),
+                MenuButtonAction::SaveAndQuit => save_events.send(SaveMatchEvent),
                 MenuButtonAction::QuitGame => state.replace(AppState::MainMenu).unwrap(),
             }
         }
@@ -367,58 +680,125 @@ fn setup_one_player_game(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    audio: Res<Audio>
+    audio: Res<Audio>,
+    (level, config): (Res<Level>, Res<MatchConfig>),
 ) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    spawn_game_camera(&mut commands);
     audio.play_looped(asset_server.load("music/game_theme.ogg"));
     let slayer_texture_handle = asset_server.load("slayer_run.png");
     let slayer_texture_atlas = TextureAtlas::from_grid(slayer_texture_handle, Vec2::new(64.0, 64.0), 8, 1);
     let slayer_texture_atlas_handle = texture_atlases.add(slayer_texture_atlas);
 
-    commands.spawn_bundle(SpriteBundle {
-        texture: asset_server.load("snake_den.png"),
-        ..Default::default()
-    });
+    if let Some(background) = &level.background {
+        commands.spawn_bundle(SpriteBundle {
+            texture: asset_server.load(background.as_str()),
+            ..Default::default()
+        });
+    }
+
+    for platform in &level.platforms {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: GROUND_COLOR,
+                    custom_size: Some(platform.size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(platform.centre.extend(0.5)),
+                ..Default::default()
+            })
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Cuboid {
+                half_extends: platform.size.extend(0.0) / 2.0,
+                border_radius: None,
+            });
+    }
+
+    for obstacle in &level.obstacles {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: OBSTACLE_COLOR,
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(
+                    cell_to_world(obstacle.x as f32, obstacle.y as f32).extend(0.5),
+                ),
+                ..Default::default()
+            })
+            .insert(*obstacle)
+            .insert(Obstacle)
+            .insert(RigidBody::Static)
+            .insert(CollisionShape::Cuboid {
+                half_extends: Vec2::splat(TILE_SIZE).extend(0.0) / 2.0,
+                border_radius: None,
+            });
+    }
 
-    // The Ground
-    commands.spawn_bundle((
-        Transform::from_translation(Vec3::new(0.0, -500.0, 0.0)),
-        GlobalTransform::default(),
-        RigidBody::Static,
-        CollisionShape::Cuboid {
-            half_extends: Vec2::new(1500.0, 50.0).extend(0.0) / 2.0,
+    let extra_lives = match config.mode {
+        MatchMode::Versus => config.handicaps.extra_lives,
+        MatchMode::CoOp => CO_OP_EXTRA_LIVES,
+        MatchMode::Survival => SURVIVAL_EXTRA_LIVES,
+        MatchMode::TimeAttack => TIME_ATTACK_EXTRA_LIVES,
+        // The tutorial's slayer cannot be chomped
+        MatchMode::Tutorial => 0,
+    };
+    for player in 0..config.mode.slayers() {
+        let slayer = commands.spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                color: SLAYER_TINTS[player],
+                ..Default::default()
+            },
+            texture_atlas: slayer_texture_atlas_handle.clone(),
+            transform: Transform::from_translation(slayer_spawn(&level, player)),
+            ..Default::default()
+        })
+        .insert(AnimationTimer(Timer::from_seconds(0.04, true)))
+        .insert(AttackCooldown(Timer::from_seconds(ATTACK_COOLDOWN, false)))
+        .insert(Facing::Right)
+        .insert(SwordDirection::NotAttacking)
+        .insert(FeetState::InAir)
+        .insert(Jumps::default())
+        .insert(Buffs::default())
+        .insert(ExtraLives(extra_lives))
+        .insert(SlayerAnim::Jump)
+        .insert(RigidBody::Dynamic)
+        .insert(CollisionShape::Cuboid {
+            half_extends: SLAYER_SIZE.extend(0.0) / 2.0,
             border_radius: None,
-        },
-    ));
+        })
+        .insert(RotationConstraints::lock())
+        .insert(Velocity::default())
+        .insert(
+            // The slayer touches every kind of pickup, each on its own layer
+            PowerUp::ALL
+                .into_iter()
+                .fold(CollisionLayers::new(Layer::Slayer, Layer::SnakeHead), |layers, power_up| {
+                    layers.with_mask(power_up.layer())
+                }),
+        )
+        .insert(SlayerPlayer(player))
+        .insert(Slayer)
+        .id();
+        if config.mode == MatchMode::Tutorial {
+            commands.entity(slayer).insert(Invulnerable);
+        }
+    }
+}
 
-    let slayer_size = Vec2::new(64.0, 64.0);
-    commands.spawn_bundle(SpriteSheetBundle {
-        texture_atlas: slayer_texture_atlas_handle,
-        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 1.0)),
-        ..Default::default()
-    })
-    .insert(AnimationTimer(Timer::from_seconds(0.04, true)))
-    .insert(AttackCooldown(Timer::from_seconds(0.6, false)))
-    .insert(Facing::Right)
-    .insert(SwordDirection::NotAttacking)
-    .insert(FeetState::InAir)
-    .insert(SlayerAnim::Jump)
-    .insert(RigidBody::Dynamic)
-    .insert(CollisionShape::Cuboid {
-        half_extends: slayer_size.extend(0.0) / 2.0,
-        border_radius: None,
-    })
-    .insert(RotationConstraints::lock())
-    .insert(Velocity::default())
-    .insert(CollisionLayers::new(Layer::Slayer, Layer::SnakeHead))
-    .insert(Slayer);
+/// Where the slayer of `player` starts out, and comes back after losing a life.
+///
+/// Players after the first start a little to the right of the level's spawn point.
+fn slayer_spawn(level: &Level, player: usize) -> Vec3 {
+    (level.slayer_spawn + Vec2::new(player as f32 * 2.0 * TILE_SIZE, 0.0)).extend(1.0)
 }
 
 fn setup_two_player_game(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    spawn_game_camera(&mut commands);
     commands.spawn_bundle(SpriteBundle {
         texture: asset_server.load("snake_head.png"),
         ..Default::default()
@@ -427,7 +807,7 @@ fn setup_two_player_game(
 
 fn cleanup_game(
     mut commands: Commands,
-    entities: Query<Entity, Without<Camera>>,
+    entities: Query<Entity, (Without<Parent>, Or<(Without<Camera>, With<GameCamera>)>)>,
     audio: Res<Audio>
 ) {
     audio.stop();
@@ -437,72 +817,311 @@ fn cleanup_game(
 }
 
 const SPEED: f32 = 300.0;
+/// How fast the slayer leaves the ground when jumping, in world units per second.
+const JUMP_SPEED: f32 = 400.0;
+/// How big the slayer is, in world units.
+const SLAYER_SIZE: Vec2 = Vec2::new(64.0, 64.0);
+/// How far above the slayer's feet the top of a body may be for the slayer to stand on it,
+/// in world units, since bodies sink into each other a little as they land.
+const FOOTING_TOLERANCE: f32 = 8.0;
+/// How far in front of the slayer the sword connects, in world units.
+const SWORD_REACH: f32 = 64.0;
+/// How far the sword connects with [`PowerUp::SwordReach`], in world units.
+const LONG_SWORD_REACH: f32 = 128.0;
+/// How long the slayer has to wait between sword swings, in seconds.
+const ATTACK_COOLDOWN: f32 = 0.6;
+
+/// Moves each slayer and swings their sword, as their player's input for the tick says.
+///
+/// Left and right run, and gravity pulls the slayer down. Up jumps, once per press, and only
+/// off something under the slayer's feet, or once more in mid-air with
+/// [`PowerUp::DoubleJump`]; down drops faster. The slayer used to fly freely in all eight
+/// directions, which left jumping and the double jump with nothing to add.
 fn slayer_controls(
-    mut commands: Commands,
-    mut state: ResMut<State<AppState>>,
-    input: Res<Input<KeyCode>>,
+    tick_input: Res<TickInput>,
+    clock: Res<SimulationClock>,
+    mut swings: EventWriter<SwordSwingEvent>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
     mut slayer_info: Query<
         (Entity,
         &mut Velocity,
         &Facing,
-        &AttackCooldown),
+        &mut SwordDirection,
+        &mut AttackCooldown,
+        &mut Jumps,
+        &Buffs,
+        &SlayerPlayer),
         With<Slayer>
     >,
 ) {
-    for (entity, mut velocity, facing, attack_cooldown) in slayer_info.iter_mut() {
-        let x = if input.pressed(KeyCode::A) {
+    for (entity, mut velocity, facing, mut sword_direction, mut attack_cooldown, mut jumps, buffs, player) in slayer_info.iter_mut() {
+        let input = tick_input.slayer_input(player.0);
+        attack_cooldown.0.tick(clock.step());
+        let x = if input.left {
             -1.0
-        } else if input.pressed(KeyCode::D) {
+        } else if input.right {
             1.0
         } else {
             0.0
         };
     
-        let y = if input.pressed(KeyCode::S) {
+        let y = if input.down {
             -1.0
-        } else if input.pressed(KeyCode::W) {
+        } else if input.up {
             1.0
         } else {
             0.0
         };
 
-        if input.just_pressed(KeyCode::B) && attack_cooldown.0.finished() { // Attack button
-            if x > 0.0 {
-                commands.entity(entity).insert(SwordDirection::Right);
+        if input.attack && attack_cooldown.0.finished() { // Attack button
+            let direction = if x > 0.0 {
+                Direction::Right
             } else if x < 0.0 {
-                commands.entity(entity).insert(SwordDirection::Left);
+                Direction::Left
             } else if y > 0.0 {
-                commands.entity(entity).insert(SwordDirection::Up);
+                Direction::Up
             } else if y < 0.0 {
-                commands.entity(entity).insert(SwordDirection::Down);
+                Direction::Down
             } else {
-                let _sword_direction = match facing {
-                    Facing::Left => SwordDirection::Left,
-                    Facing::Right => SwordDirection::Right,
-                };
-                commands.entity(entity).insert(_sword_direction);
-            }
+                match facing {
+                    Facing::Left => Direction::Left,
+                    Facing::Right => Direction::Right,
+                }
+            };
+            *sword_direction = match direction {
+                Direction::Left => SwordDirection::Left,
+                Direction::Right => SwordDirection::Right,
+                Direction::Up => SwordDirection::Up,
+                Direction::Down => SwordDirection::Down,
+            };
+            attack_cooldown.0.reset();
+            swings.send(SwordSwingEvent {
+                slayer: entity,
+                direction,
+            });
         } else if attack_cooldown.0.finished() {
-            commands.entity(entity).insert(SwordDirection::NotAttacking);
+            *sword_direction = SwordDirection::NotAttacking;
         }
-        if input.pressed(KeyCode::P) || input.pressed(KeyCode::Escape) {
-            state.push(AppState::PauseMenu).unwrap();
+
+        if x != 0.0 {
+            velocity.linear.x = x * SPEED;
         }
 
-        let target_velocity = Vec2::new(x, y).normalize_or_zero() * SPEED;
+        // Holding up only jumps once
+        let jump = input.up && !jumps.holding_up;
+        jumps.holding_up = input.up;
+        if jump && jumps.take_jump(buffs) {
+            velocity.linear.y = JUMP_SPEED;
+            audio.play(asset_server.load("sfx/jump.ogg"));
+        } else if input.down {
+            velocity.linear.y = -SPEED;
+        }
+    }
+}
+
+/// Keeps track of whether the slayer is standing on something they can jump off.
+///
+/// Only solid bodies under the slayer's feet count, so leaning on a wall does not let the
+/// slayer climb it.
+fn slayer_feet(
+    collisions: Res<TickCollisions>,
+    mut slayers: Query<(&Transform, &mut FeetState, &mut Jumps), With<Slayer>>,
+    bodies: Query<(&RigidBody, &Transform, &CollisionShape)>,
+) {
+    for event in collisions.0.iter() {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (slayer, other) = if slayers.get(entity_1).is_ok() {
+            (entity_1, entity_2)
+        } else {
+            (entity_2, entity_1)
+        };
+        let (body, body_transform, shape) = match bodies.get(other) {
+            Ok((RigidBody::Static, transform, shape)) => (other, transform, shape),
+            _ => continue,
+        };
+        if let Ok((transform, mut feet, mut jumps)) = slayers.get_mut(slayer) {
+            let underfoot = is_underfoot(transform, body_transform, shape);
+            jumps.touch(body, event.is_started(), underfoot);
+            *feet = if jumps.ground_contacts > 0 {
+                FeetState::OnGround
+            } else {
+                FeetState::InAir
+            };
+        }
+    }
+}
+
+/// The cells a sword swung in `direction` by the slayer at `transform` reaches, nearest first.
+fn sword_cells(transform: &Transform, buffs: &Buffs, direction: Direction) -> Vec<Position> {
+    let reaches: &[f32] = if buffs.is_active(PowerUp::SwordReach) {
+        &[SWORD_REACH, LONG_SWORD_REACH]
+    } else {
+        &[SWORD_REACH]
+    };
+    let (dx, dy) = direction.offset();
+    reaches
+        .iter()
+        .map(|reach| world_to_cell(transform.translation.truncate() + Vec2::new(dx as f32, dy as f32) * *reach))
+        .collect()
+}
 
-        if target_velocity != Vec2::ZERO {
-            velocity.linear = target_velocity.extend(0.0);
+/// Checks each sword swing for a snake segment at the tip of the sword.
+///
+/// Hitting a segment behind the head severs the snake there, or closer to the head with a
+/// heavier sword, unless the snake is armored.
+fn sword_hits(
+    mut swings: EventReader<SwordSwingEvent>,
+    mut hits: EventWriter<SwordHitEvent>,
+    mut splits: EventWriter<SnakeSplitEvent>,
+    (occupancy, config): (Res<Occupancy<Entity>>, Res<MatchConfig>),
+    slayers: Query<(&Transform, &Buffs), With<Slayer>>,
+    snakes: Query<(&Snake, Option<&SnakeAbilities>)>,
+) {
+    for swing in swings.iter() {
+        let (transform, buffs) = match slayers.get(swing.slayer) {
+            Ok(slayer) => slayer,
+            Err(_) => continue,
+        };
+        // The first snake along the blade takes the hit
+        let target = sword_cells(transform, buffs, swing.direction)
+            .into_iter()
+            .find_map(|cell| occupancy.get(cell).map(|snake| (snake, cell)));
+        let (snake, cell) = match target {
+            Some(target) => target,
+            None => continue,
+        };
+        let index = match snakes.get(snake) {
+            Ok((_, Some(abilities))) if abilities.is_armored() => None,
+            Ok((body, _)) => body.segment_at(cell),
+            Err(_) => None,
+        };
+        if let Some(index) = index {
+            hits.send(SwordHitEvent {
+                slayer: swing.slayer,
+                snake,
+                index,
+                position: cell,
+            });
+            if index > 0 {
+                splits.send(SnakeSplitEvent {
+                    snake,
+                    index: config.handicaps.severed_at(index),
+                });
+            }
         }
     }
 }
 
+fn pause_game(input: Res<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if input.just_pressed(KeyCode::P) || input.just_pressed(KeyCode::Escape) {
+        state.push(AppState::PauseMenu).unwrap();
+    }
+}
+
+// No ticks are counted while the pause menu is up, so tick numbers only count time spent playing
+fn pause_simulation_clock(mut clock: ResMut<SimulationClock>) {
+    clock.set_paused(true);
+}
+
+fn resume_simulation_clock(mut clock: ResMut<SimulationClock>) {
+    clock.set_paused(false);
+}
+
+fn reset_simulation_clock(mut clock: ResMut<SimulationClock>, mut collisions: ResMut<TickCollisions>) {
+    clock.reset();
+    // Whatever touched in the last match is gone
+    collisions.0.clear();
+}
+
+fn reset_snake_timer(mut snake_timer: ResMut<SnakeTimer>, config: Res<MatchConfig>) {
+    *snake_timer = SnakeTimer::new();
+    let interval = match config.mode {
+        MatchMode::Survival => survival::wave(config.wave).snake_interval,
+        MatchMode::Tutorial => TUTORIAL_SNAKE_INTERVAL,
+        _ => config.handicaps.snake_pace.interval(),
+    };
+    snake_timer.0.set_duration(interval);
+}
+
+fn reset_occupancy(mut occupancy: ResMut<Occupancy<Entity>>, level: Res<Level>) {
+    clear_occupancy(&mut occupancy, &level);
+}
+
+/// Empties the grid of everything but the level's obstacles.
+fn clear_occupancy(occupancy: &mut Occupancy<Entity>, level: &Level) {
+    occupancy.clear();
+    for obstacle in &level.obstacles {
+        occupancy.block(*obstacle);
+    }
+}
+
 #[derive(Component)]
 struct AnimationTimer(Timer);
 
 #[derive(Component)]
 struct AttackCooldown(Timer);
 
+/// Whether the top of the solid `body` at `transform` is under the feet of the slayer at
+/// `slayer`, rather than beside or above them.
+fn is_underfoot(slayer: &Transform, transform: &Transform, body: &CollisionShape) -> bool {
+    let half_height = match body {
+        CollisionShape::Cuboid { half_extends, .. } => half_extends.y,
+        _ => return false,
+    };
+    let feet = slayer.translation.y - SLAYER_SIZE.y / 2.0;
+    transform.translation.y + half_height <= feet + FOOTING_TOLERANCE
+}
+
+/// What decides whether the slayer can jump.
+#[derive(Component, Clone, Default)]
+struct Jumps {
+    /// How many solid bodies the slayer is standing on.
+    ground_contacts: u32,
+    /// The bodies counted in `ground_contacts` since the match started or was restored, so
+    /// that stepping off them is counted too.
+    underfoot: Vec<Entity>,
+    /// Whether the slayer jumped in mid-air since they last touched the ground.
+    jumped_in_air: bool,
+    /// Whether up was held on the previous tick.
+    holding_up: bool,
+    /// Contacts a restored match started with, which physics reports as new once more.
+    restored_contacts: u32,
+}
+
+impl Jumps {
+    /// Counts a solid body the slayer has started or stopped touching, if they are standing on it.
+    fn touch(&mut self, body: Entity, started: bool, underfoot: bool) {
+        if !started {
+            if let Some(index) = self.underfoot.iter().position(|other| *other == body) {
+                self.underfoot.remove(index);
+                self.ground_contacts = self.ground_contacts.saturating_sub(1);
+            }
+        } else if underfoot && !self.underfoot.contains(&body) {
+            self.underfoot.push(body);
+            if self.restored_contacts > 0 {
+                // Already counted when the match was saved
+                self.restored_contacts -= 1;
+            } else {
+                self.ground_contacts += 1;
+                self.jumped_in_air = false;
+            }
+        }
+    }
+
+    /// Uses up a jump, if the slayer has one left.
+    fn take_jump(&mut self, buffs: &Buffs) -> bool {
+        if self.ground_contacts > 0 {
+            true
+        } else if !self.jumped_in_air && buffs.is_active(PowerUp::DoubleJump) {
+            self.jumped_in_air = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
 // fn slayer_anim_selector(
 //     commands: Commands,
 //     texture_atlases: Res<Assets<TextureAtlas>>,
@@ -534,13 +1153,22 @@ fn slayer_animator(
     }
 }
 
+/// Chomps the slayer when a snake head runs into them.
+///
+/// A slayer with [`ExtraLives`] left starts over at the level's spawn point instead.
 fn slayer_death (
     mut commands: Commands,
-    mut events: EventReader<CollisionEvent>,
+    collisions: Res<TickCollisions>,
+    mut deaths: EventWriter<SlayerDeathEvent>,
+    mut slayers: Query<
+        (&mut Transform, &mut Velocity, &Buffs, Option<&mut ExtraLives>, &SlayerPlayer),
+        (With<Slayer>, Without<Invulnerable>)
+    >,
+    level: Res<Level>,
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
 ) {
-    events
+    let chomped = collisions.0
         .iter()
         .filter(|e| e.is_started())
         .filter_map(|event| {
@@ -553,15 +1181,40 @@ fn slayer_death (
             } else {
                 None
             }
-        })
-        .for_each(|slayer_entity| {
-            // audio.play(asset_server.load("sfx/slayer_death.ogg"));
-            audio.play(asset_server.load("sfx/snake_chomp.ogg"));
-            commands.entity(slayer_entity).despawn()
         });
+    for slayer_entity in chomped {
+        let (mut transform, mut velocity, buffs, extra_lives, player) = match slayers.get_mut(slayer_entity) {
+            Ok(slayer) => slayer,
+            Err(_) => continue,
+        };
+        if buffs.is_active(PowerUp::Invulnerability) {
+            continue;
+        }
+        // audio.play(asset_server.load("sfx/slayer_death.ogg"));
+        audio.play(asset_server.load("sfx/snake_chomp.ogg"));
+        let translation = transform.translation;
+        let lives_left = match extra_lives {
+            Some(mut extra_lives) if extra_lives.0 > 0 => {
+                extra_lives.0 -= 1;
+                transform.translation = slayer_spawn(&level, player.0);
+                *velocity = Velocity::default();
+                extra_lives.0 + 1
+            }
+            _ => {
+                commands.entity(slayer_entity).despawn();
+                0
+            }
+        };
+        deaths.send(SlayerDeathEvent {
+            slayer: slayer_entity,
+            player: player.0,
+            translation,
+            lives_left,
+        });
+    }
 }
 
-fn is_slayer(layers: CollisionLayers) -> bool {
+pub(crate) fn is_slayer(layers: CollisionLayers) -> bool {
     layers.contains_group(Layer::Slayer) && !layers.contains_group(Layer::SnakeHead)
 }
 
@@ -572,22 +1225,40 @@ fn is_snake_head(layers: CollisionLayers) -> bool {
 const SNAKE_HEAD_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
 const SNAKE_SEGMENT_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
 const GROUND_COLOR: Color = Color::rgb(0.3, 0.8, 0.3);
+const OBSTACLE_COLOR: Color = Color::rgb(0.35, 0.25, 0.2);
 
 const ARENA_HEIGHT: u32 = 14;
 const ARENA_WIDTH: u32 = 28;
 
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum SlayerAction {
+    Controls,
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum MatchSetup {
+    SeedRng,
+    LoadLevel,
+}
+
 #[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
 pub enum SnakeAction {
     Input,
     Movement,
     Eating,
     Growth,
+    /// Snakes are cut in two where they were severed.
+    Split,
+    /// Snakes cut down to their head are removed, in modes that clear the arena.
+    Death,
 }
 
-#[derive(Component, Clone, Copy, PartialEq, Eq)]
-struct Position {
-    x: i32,
-    y: i32,
+impl Component for Position {
+    type Storage = TableStorage;
+}
+
+impl Component for Snake {
+    type Storage = TableStorage;
 }
 
 #[derive(Component)]
@@ -604,9 +1275,27 @@ impl SnakeSize {
     }
 }
 
+/// Marks the head of a snake, which also carries the snake's [`Snake`] and [`SnakeSegments`].
 #[derive(Component)]
-struct SnakeHead {
-    direction: Direction,
+pub struct SnakeHead;
+
+/// The snake steered by the snake player's inputs.
+#[derive(Component)]
+struct PlayerSnake;
+
+/// Where an entity was on the grid before its last move, so it can be drawn sliding between cells.
+#[derive(Component, Clone, Copy)]
+pub struct PreviousPosition(pub Position);
+
+/// Should snakes slide smoothly from cell to cell instead of jumping?
+///
+/// This only changes how they are drawn; the grid still moves a whole cell at a time.
+pub struct SmoothMotion(pub bool);
+
+impl Default for SmoothMotion {
+    fn default() -> Self {
+        Self(true)
+    }
 }
 
 #[derive(Component)]
@@ -615,17 +1304,57 @@ struct SnakeSegment {
 }
 
 struct SnakeDeathEvent;
-struct SlayerDeathEvent;
-struct SnakeSplitEvent;
+/// The slayer was chomped while standing at `translation`.
+pub struct SlayerDeathEvent {
+    pub slayer: Entity,
+    /// The player steering the slayer, counting from 0.
+    pub player: usize,
+    pub translation: Vec3,
+    /// The lives the slayer still has, where 0 means they are out of the match.
+    pub lives_left: u8,
+}
+/// The slayer swung their sword in `direction`.
+pub struct SwordSwingEvent {
+    pub slayer: Entity,
+    pub direction: Direction,
+}
+/// A sword swing by `slayer` connected with the segment at `index` of the snake headed by `snake`.
+pub struct SwordHitEvent {
+    pub slayer: Entity,
+    pub snake: Entity,
+    pub index: usize,
+    pub position: Position,
+}
+/// Cuts the snake headed by `snake` in front of the segment at `index`.
+pub struct SnakeSplitEvent {
+    pub snake: Entity,
+    pub index: usize,
+}
+/// A snake was cut in two at `position`, leaving a new snake headed by `tail`.
+pub struct SnakeSeveredEvent {
+    pub snake: Entity,
+    pub tail: Entity,
+    pub position: Position,
+}
+/// The snake headed by `snake`, down to just its head at `position`, was removed.
+pub struct SnakeKilledEvent {
+    pub snake: Entity,
+    pub position: Position,
+}
+/// Every snake and den in the arena is gone, which wins modes that clear the arena.
+pub struct ArenaClearedEvent;
 struct GameOverEvent;
 
+/// Where the tail of the last snake to move was before it moved.
 #[derive(Default)]
-struct LastTailPosition(Option<Position>);
+pub struct LastTailPosition(pub Option<Position>);
 
-#[derive(Default)]
-struct SnakeSegments(Vec<Entity>);
+/// The entities making up a snake, in the same order as its [`Snake::body`].
+#[derive(Component, Default)]
+pub struct SnakeSegments(pub Vec<Entity>);
 
-struct SnakeTimer(Timer);
+/// Fires every time the snakes should move one cell.
+pub struct SnakeTimer(pub Timer);
 
 impl SnakeTimer {
     pub fn new() -> Self {
@@ -639,6 +1368,51 @@ impl Default for SnakeTimer {
     }
 }
 
+/// How many times more slowly than usual the [`SnakeTimer`] runs, worked out afresh every
+/// tick from what slows the snakes down at the time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeSlowdown(pub u32);
+
+impl Default for SnakeSlowdown {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+/// How many cells a snake moves each time the [`SnakeTimer`] fires, spread evenly over
+/// the timer's interval.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeSpeed(pub u32);
+
+impl Default for SnakeSpeed {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl SnakeSpeed {
+    /// How many cells the snake moves while the [`SnakeTimer`], firing every `interval`,
+    /// runs on from `elapsed` by `step`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use unfair_advantage_lib::SnakeSpeed;
+    /// let interval = Duration::from_millis(400);
+    /// let step = Duration::from_millis(100);
+    /// assert_eq!(SnakeSpeed(1).moves(interval, Duration::from_millis(200), step), 0);
+    /// assert_eq!(SnakeSpeed(1).moves(interval, Duration::from_millis(300), step), 1);
+    /// // Twice as fast, the snake moves halfway through as well
+    /// assert_eq!(SnakeSpeed(2).moves(interval, Duration::from_millis(100), step), 1);
+    /// ```
+    pub fn moves(self, interval: Duration, elapsed: Duration, step: Duration) -> u32 {
+        // A timer shortened to less than what has elapsed fires straight away
+        let from = elapsed.min(interval.saturating_sub(Duration::from_nanos(1)));
+        let beats = |time: Duration| time.as_nanos() * self.0 as u128 / interval.as_nanos();
+        (beats(from + step) - beats(from)) as u32
+    }
+}
+
 #[derive(Component)]
 enum Facing {
     Left,
@@ -682,167 +1456,102 @@ struct Game {
 #[derive(Component)]
 struct Food;
 
-#[derive(PartialEq, Copy, Clone)]
-enum Direction {
-    Left,
-    Up,
-    Right,
-    Down,
-}
-
-impl Direction {
-    fn opposite(self) -> Self {
-        match self {
-            Self::Left => Self::Right,
-            Self::Right => Self::Left,
-            Self::Up => Self::Down,
-            Self::Down => Self::Up,
-        }
-    }
-}
+/// A solid block filling a grid cell.
+#[derive(Component)]
+struct Obstacle;
 
 #[derive(PhysicsLayer)]
 enum Layer {
     Slayer,
     SnakeHead,
+    DoubleJumpPickup,
+    SwordReachPickup,
+    QuickAttackPickup,
+    InvulnerabilityPickup,
+    SlowSnakePickup,
 }
 
+/// Spawns the level's snake, steered by the snake player or, in the other modes, by itself.
 fn spawn_snake(
     mut commands: Commands,
-    mut segments: ResMut<SnakeSegments>,
+    level: Res<Level>,
+    config: Res<MatchConfig>,
     asset_server: Res<AssetServer>
 ) {
+    let ai = match config.mode {
+        // Survival matches put their own snakes in the arena instead
+        MatchMode::Survival => return,
+        MatchMode::Versus => None,
+        MatchMode::CoOp => Some(SnakeAi::default()),
+        // A snake that never chases keeps every run the same, and leaves new players be
+        MatchMode::TimeAttack | MatchMode::Tutorial => Some(SnakeAi::new(AiProfile::Wanderer)),
+    };
+    let head = spawn_snake_entities(&mut commands, &asset_server, level.snake.clone());
+    match ai {
+        Some(ai) => {
+            commands.entity(head).insert(ai);
+        }
+        None => {
+            commands
+                .entity(head)
+                .insert(PlayerSnake)
+                .insert(SnakeAbilities::default());
+        }
+    }
+}
+
+/// Spawns the entities for each cell of `snake`, returning its head.
+pub fn spawn_snake_entities(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    snake: Snake,
+) -> Entity {
     let snake_sprite_size = Vec2::new(64.0, 64.0);
-    segments.0 = vec![
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_head.png"),
-                ..Default::default()
-            })
-            .insert(SnakeHead {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 8, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .insert(CollisionLayers::new(Layer::SnakeHead, Layer::Slayer))
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                sprite: Sprite {
-                    flip_x: true,
-                    ..Default::default()
-                },
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 7, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 6, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 5, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 4, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 3, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_section.png"),
-                ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 2, y: 9 })
-            .insert(RigidBody::KinematicPositionBased)
-            .insert(CollisionShape::Cuboid {
-                half_extends: snake_sprite_size.extend(0.0) / 2.0,
-                border_radius: None,
-            })
-            .id(),
-        commands
-            .spawn_bundle(SpriteBundle {
-                texture: asset_server.load("snake_tail.png"),
+    let last = snake.len() - 1;
+    let mut segments = Vec::with_capacity(snake.len());
+    for (index, position) in snake.body().iter().enumerate() {
+        let texture = if index == 0 {
+            "snake_head.png"
+        } else if index == last {
+            "snake_tail.png"
+        } else {
+            "snake_section.png"
+        };
+        let mut segment = commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                flip_x: index % 2 == 1,
                 ..Default::default()
-            })
-            .insert(SnakeSegment {
-                direction: Direction::Right,
-            })
-            .insert(Position { x: 2, y: 8 })
+            },
+            texture: asset_server.load(texture),
+            ..Default::default()
+        });
+        segment
+            .insert(*position)
+            .insert(PreviousPosition(*position))
             .insert(RigidBody::KinematicPositionBased)
             .insert(CollisionShape::Cuboid {
                 half_extends: snake_sprite_size.extend(0.0) / 2.0,
                 border_radius: None,
-            })
-            .id(),
-    ];
+            });
+        if index == 0 {
+            segment.insert(CollisionLayers::new(Layer::SnakeHead, Layer::Slayer));
+        } else {
+            segment.insert(SnakeSegment {
+                direction: snake.direction(),
+            });
+        }
+        segments.push(segment.id());
+    }
+    commands
+        .entity(segments[0])
+        .insert(SnakeHead)
+        .insert(snake)
+        .insert(SnakeSpeed::default())
+        .insert(SnakeSegments(segments.clone()));
+    segments[0]
 }
 
-struct SnakeFlipFlop {
+pub struct SnakeFlipFlop {
     flip_x: bool
 }
 
@@ -852,133 +1561,266 @@ impl Default for SnakeFlipFlop {
     }
 }
 
-fn snake_movement(
+/// Claims the cells of snakes that were just spawned or split off.
+///
+/// Cells are keyed by the head entity of the snake holding them.
+pub fn occupy_new_snakes(
+    mut occupancy: ResMut<Occupancy<Entity>>,
+    snakes: Query<(Entity, &Snake), Added<Snake>>,
+) {
+    for (head, snake) in snakes.iter() {
+        occupancy.occupy_snake(snake, head);
+    }
+}
+
+/// Moves every snake one cell each time the [`SnakeTimer`] fires, or as often as its
+/// [`SnakeSpeed`] says, and two cells when it lunges. The timer runs as much slower as the
+/// [`SnakeSlowdown`] says.
+pub fn snake_movement(
     mut last_tail_position: ResMut<LastTailPosition>,
-    mut game_over_writer: EventWriter<GameOverEvent>,
     mut snake_split_writer: EventWriter<SnakeSplitEvent>,
-    segments: ResMut<SnakeSegments>,
+    mut occupancy: ResMut<Occupancy<Entity>>,
     mut snake_timer: ResMut<SnakeTimer>,
-    time: Res<Time>,
-    mut heads: Query<(Entity, &SnakeHead, &mut Transform, &mut Sprite)>,
-    mut positions: Query<&mut Position>,
-    mut snake_segments: Query<&mut SnakeSegment>,
-    mut snake_flip_flop: Local<SnakeFlipFlop>,
+    (clock, slowdown): (Res<SimulationClock>, Res<SnakeSlowdown>),
+    mut heads: Query<
+        (Entity, &mut Snake, &SnakeSegments, &mut Transform, &mut Sprite, Option<&mut SnakeAbilities>, &SnakeSpeed),
+        With<SnakeHead>
+    >,
+    (mut positions, mut snake_flip_flop): (Query<(&mut Position, &mut PreviousPosition)>, Local<SnakeFlipFlop>),
 ) {
-    snake_timer.0.tick(time.delta());
-    if snake_timer.0.just_finished() {
-        if let Some((head_entity, head, mut head_transform, mut head_sprite)) = heads.iter_mut().next() {
-            let segment_positions = segments
-                .0
-                .iter()
-                .map(|e| *positions.get_mut(*e).unwrap())
-                .collect::<Vec<Position>>();
-            // let segment_directions = segments
-            //     .0
-            //     .iter()
-            //     .map(|e| *snake_segments.get_mut(*e).unwrap())
-            //     .collect::<Vec<SnakeSegment>>();
-            let mut head_pos = positions.get_mut(head_entity).unwrap();
-            match &head.direction {
-                Direction::Left => {
-                    head_pos.x -= 1;
-                    head_transform.rotation = Quat::from_rotation_z(f32::to_radians(270.0));
-                }
-                Direction::Right => {
-                    head_pos.x += 1;
-                    head_transform.rotation = Quat::from_rotation_z(f32::to_radians(90.0));
-                }
-                Direction::Up => {
-                    head_pos.y += 1;
-                    head_transform.rotation = Quat::from_rotation_z(f32::to_radians(180.0));
+    if snake_timer.0.paused() {
+        return;
+    }
+    let (interval, elapsed) = (snake_timer.0.duration(), snake_timer.0.elapsed());
+    let step = clock.step() / slowdown.0.max(1);
+    snake_timer.0.tick(step);
+    for (head_entity, mut snake, segments, mut head_transform, mut head_sprite, mut abilities, speed) in heads.iter_mut() {
+        for _ in 0..speed.moves(interval, elapsed, step) {
+            let moves = abilities.as_mut().map_or(1, |abilities| abilities.take_moves());
+            for _ in 0..moves {
+                // Snakes wait in front of obstacles until they are turned away from them
+                let (step, collision) = match occupancy.advance_snake(&mut snake, head_entity) {
+                    Some(moved) => moved,
+                    None => break,
+                };
+                head_transform.rotation = head_rotation(snake.direction());
+                head_sprite.flip_x = snake_flip_flop.flip_x;
+                snake_flip_flop.flip_x = !snake_flip_flop.flip_x;
+                for (segment, cell) in segments.0.iter().zip(snake.body()) {
+                    let (mut position, mut previous) = positions.get_mut(*segment).unwrap();
+                    previous.0 = *position;
+                    *position = *cell;
                 }
-                Direction::Down => {
-                    head_pos.y -= 1;
-                    head_transform.rotation = Quat::from_rotation_z(f32::to_radians(0.0));
+                // Snakes pass over each other for now
+                if let Some(Collision::Itself(index)) = collision {
+                    snake_split_writer.send(SnakeSplitEvent {
+                        snake: head_entity,
+                        index,
+                    });
                 }
-            };
-            head_sprite.flip_x = snake_flip_flop.flip_x;
-            snake_flip_flop.flip_x = !snake_flip_flop.flip_x;
-            if head_pos.x < 0 {
-                head_pos.x = ARENA_WIDTH as i32 - 1;
-            } else if head_pos.x as u32 >= ARENA_WIDTH {
-                head_pos.x = 0;
-            } else if head_pos.y < 0 {
-                head_pos.y = ARENA_HEIGHT as i32 - 1;
-            } else if head_pos.y as u32 >= ARENA_HEIGHT {
-                head_pos.y = 0;
-            }
-            if segment_positions.contains(&head_pos) {
-                snake_split_writer.send(SnakeSplitEvent);
+                last_tail_position.0 = Some(step.vacated);
             }
-            segment_positions
-                .iter()
-                .zip(segments.0.iter().skip(1))
-                .for_each(|(pos, segment)| {
-                    *positions.get_mut(*segment).unwrap() = *pos;
+        }
+    }
+}
+
+/// How a snake head sprite is turned to face `direction`.
+fn head_rotation(direction: Direction) -> Quat {
+    match direction {
+        Direction::Left => Quat::from_rotation_z(f32::to_radians(270.0)),
+        Direction::Right => Quat::from_rotation_z(f32::to_radians(90.0)),
+        Direction::Up => Quat::from_rotation_z(f32::to_radians(180.0)),
+        Direction::Down => Quat::from_rotation_z(f32::to_radians(0.0)),
+    }
+}
+
+fn snake_movement_input(tick_input: Res<TickInput>, mut heads: Query<&mut Snake, With<PlayerSnake>>) {
+    if let Some(mut snake) = heads.iter_mut().next() {
+        if let Some(direction) = tick_input.snake.turn {
+            snake.turn(direction);
+        }
+    }
+}
+
+/// Cuts snakes in two as [`SnakeSplitEvent`]s come in.
+pub fn snake_split(
+    mut commands: Commands,
+    mut events: EventReader<SnakeSplitEvent>,
+    mut severed: EventWriter<SnakeSeveredEvent>,
+    (arena, mut occupancy): (Res<Arena>, ResMut<Occupancy<Entity>>),
+    asset_server: Res<AssetServer>,
+    mut snakes: Query<(&mut Snake, &mut SnakeSegments)>,
+) {
+    for event in events.iter() {
+        if let Ok((mut snake, mut segments)) = snakes.get_mut(event.snake) {
+            if let Some(tail) = snake.split_off(event.index, &arena) {
+                // The tail claims its cells for itself once it is spawned
+                occupancy.release_snake(&tail, event.snake);
+                let tail_segments = segments.0.split_off(event.index);
+                severed.send(SnakeSeveredEvent {
+                    snake: event.snake,
+                    tail: tail_segments[0],
+                    position: tail.head(),
                 });
-            last_tail_position.0 = Some(*segment_positions.last().unwrap());
+                let new_tail = segments.0[segments.0.len() - 1];
+                if new_tail != event.snake {
+                    commands
+                        .entity(new_tail)
+                        .insert(asset_server.load::<Image, _>("snake_tail.png"));
+                }
+                commands
+                    .entity(tail_segments[0])
+                    .remove::<SnakeSegment>()
+                    .insert(asset_server.load::<Image, _>("snake_head.png"))
+                    .insert(CollisionLayers::new(Layer::SnakeHead, Layer::Slayer))
+                    .insert(SnakeHead)
+                    .insert(tail)
+                    .insert(SnakeSpeed::default())
+                    .insert(SnakeSegments(tail_segments));
+            }
         }
     }
 }
 
-fn snake_movement_input(keyboard_input: Res<Input<KeyCode>>, mut heads: Query<&mut SnakeHead>) {
-    if let Some(mut head) = heads.iter_mut().next() {
-        let dir: Direction = if keyboard_input.pressed(KeyCode::Left) {
-            Direction::Left
-        } else if keyboard_input.pressed(KeyCode::Down) {
-            Direction::Down
-        } else if keyboard_input.pressed(KeyCode::Up) {
-            Direction::Up
-        } else if keyboard_input.pressed(KeyCode::Right) {
-            Direction::Right
-        } else {
-            head.direction
-        };
-        if dir != head.direction.opposite() {
-            head.direction = dir;
+/// Removes snakes that are down to their head, and clears the arena once nothing is left.
+///
+/// Only modes that [clear the arena](MatchMode::clears_arena) finish snakes off; elsewhere a
+/// lone head keeps going.
+fn finish_off_snakes(
+    mut commands: Commands,
+    mut severed: EventReader<SnakeSeveredEvent>,
+    (mut killed, mut cleared): (EventWriter<SnakeKilledEvent>, EventWriter<ArenaClearedEvent>),
+    (config, mut outcome): (Res<MatchConfig>, ResMut<MatchOutcome>),
+    mut occupancy: ResMut<Occupancy<Entity>>,
+    snakes: Query<(Entity, &Snake, &SnakeSegments)>,
+    dens: Query<&SnakeDen>,
+) {
+    if !config.mode.clears_arena() {
+        return;
+    }
+    let mut snakes_left = false;
+    for (entity, snake, segments) in snakes.iter() {
+        if snake.len() > 1 {
+            snakes_left = true;
+            continue;
         }
+        occupancy.release_snake(snake, entity);
+        for segment in &segments.0 {
+            commands.entity(*segment).despawn();
+        }
+        killed.send(SnakeKilledEvent {
+            snake: entity,
+            position: snake.head(),
+        });
+    }
+    // Tails cut off this tick only become snakes once it is over
+    let cutting = severed.iter().count() > 0;
+    let dens_left = dens.iter().any(|den| den.health > 0);
+    if outcome.0.is_some() || cutting || snakes_left || dens_left {
+        return;
+    }
+    outcome.0 = Some(Side::Slayer);
+    cleared.send(ArenaClearedEvent);
+}
+
+fn snake_rip_sound(
+    mut severed: EventReader<SnakeSeveredEvent>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+) {
+    for _ in severed.iter() {
+        audio.play(asset_server.load("sfx/snake_rip.ogg"));
     }
 }
 
 fn game_over(
     mut commands: Commands,
     mut reader: EventReader<GameOverEvent>,
-    segments_res: ResMut<SnakeSegments>,
+    (level, config): (Res<Level>, Res<MatchConfig>),
+    mut occupancy: ResMut<Occupancy<Entity>>,
     food: Query<Entity, With<Food>>,
-    segments: Query<Entity, With<SnakeSegment>>,
+    segments: Query<Entity, Or<(With<SnakeHead>, With<SnakeSegment>)>>,
     asset_server: Res<AssetServer>,
 ) {
     if reader.iter().next().is_some() {
         for ent in food.iter().chain(segments.iter()) {
             commands.entity(ent).despawn();
         }
-        spawn_snake(commands, segments_res, asset_server);
+        clear_occupancy(&mut occupancy, &level);
+        spawn_snake(commands, level, config, asset_server);
     }
 }
 
-// fn size_scaling(windows: Res<Windows>, mut q: Query<(&SnakeSize, &mut Transform)>) {
-//     let window = windows.get_primary().unwrap();
-//     for (sprite_size, mut transform) in q.iter_mut() {
-//         transform.scale = Vec3::new(
-//             sprite_size.width / ARENA_WIDTH as f32 * window.width() as f32,
-//             sprite_size.height / ARENA_HEIGHT as f32 * window.height() as f32,
-//             1.0,
-//         );
-//     }
-// }
+const TILE_SIZE: f32 = 64.0;
+
+/// The world coordinates of the middle of the cell at `(x, y)`.
+///
+/// Fractional coordinates land between cells, for things that are on their way from one to the next.
+pub fn cell_to_world(x: f32, y: f32) -> Vec2 {
+    Vec2::new(
+        (x + 0.5) * TILE_SIZE - ARENA_WIDTH as f32 * TILE_SIZE / 2.0,
+        (y + 0.5) * TILE_SIZE - ARENA_HEIGHT as f32 * TILE_SIZE / 2.0,
+    )
+}
+
+/// The cell that the world point `point` falls in, which may be outside the arena.
+///
+/// # Examples
+/// ```
+/// # use unfair_advantage_lib::{cell_to_world, world_to_cell};
+/// # use unfair_advantage_lib::snake_grid::Position;
+/// let middle = cell_to_world(3.0, 7.0);
+/// assert_eq!(world_to_cell(middle), Position::new(3, 7));
+/// ```
+pub fn world_to_cell(point: Vec2) -> Position {
+    Position::new(
+        ((point.x + ARENA_WIDTH as f32 * TILE_SIZE / 2.0) / TILE_SIZE).floor() as i32,
+        ((point.y + ARENA_HEIGHT as f32 * TILE_SIZE / 2.0) / TILE_SIZE).floor() as i32,
+    )
+}
 
-fn position_translation(windows: Res<Windows>, mut q: Query<(&Position, &mut Transform)>) {
-    fn convert(pos: f32, bound_game: f32) -> f32 {
-        let tile_size = 64.0;
-        let bound_window = tile_size * bound_game;
-        pos / bound_game * bound_window - (bound_window / 2.) + (tile_size / 2.)
+/// Places everything with a grid [`Position`] at the matching spot on screen.
+///
+/// With [`SmoothMotion`] on, entities that know their [`PreviousPosition`] are drawn
+/// part of the way there, according to how far along the [`SnakeTimer`] is, or how far
+/// towards its next move for a snake that is faster than that.
+pub fn position_translation(
+    (smooth_motion, arena): (Res<SmoothMotion>, Res<Arena>),
+    snake_timer: Res<SnakeTimer>,
+    clock: Res<SimulationClock>,
+    speeds: Query<(&SnakeSegments, &SnakeSpeed)>,
+    mut q: Query<(Entity, &Position, Option<&PreviousPosition>, &mut Transform)>,
+) {
+    // Count the time that has passed since the last tick too, so motion stays smooth
+    // when frames do not line up with ticks
+    let progress = (snake_timer.0.elapsed() + clock.overstep()).as_secs_f32()
+        / snake_timer.0.duration().as_secs_f32();
+    // Segments of faster snakes are that much further between their moves
+    let faster: HashMap<Entity, u32> = speeds
+        .iter()
+        .filter(|(_, speed)| speed.0 != 1)
+        .flat_map(|(segments, speed)| segments.0.iter().map(move |segment| (*segment, speed.0)))
+        .collect();
+    for (entity, pos, previous, mut transform) in q.iter_mut() {
+        let progress = match faster.get(&entity) {
+            Some(speed) => (progress * *speed as f32).fract(),
+            None => progress,
+        };
+        let (x, y) = match previous {
+            Some(previous) if smooth_motion.0 => arena.interpolate(previous.0, *pos, progress),
+            _ => (pos.x as f32, pos.y as f32),
+        };
+        transform.translation = cell_to_world(x, y).extend(2.0);
     }
-    let window = windows.get_primary().unwrap();
+}
+
+/// Places everything with a grid [`Position`] exactly on its cell, for the physics step.
+///
+/// Bodies always collide where the grid has them, whether or not [`SmoothMotion`] draws them
+/// sliding there and however the frames fell; [`position_translation`] moves them back to
+/// where they are drawn once the frame's ticks are done.
+fn place_for_physics(mut q: Query<(&Position, &mut Transform)>) {
     for (pos, mut transform) in q.iter_mut() {
-        transform.translation = Vec3::new(
-            convert(pos.x as f32, ARENA_WIDTH as f32),
-            convert(pos.y as f32, ARENA_HEIGHT as f32),
-            2.0,
-        );
+        transform.translation = cell_to_world(pos.x as f32, pos.y as f32).extend(2.0);
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,73 @@
+use bevy::asset::AssetPlugin;
+use bevy::input::InputPlugin;
 use bevy::prelude::*;
 use bevy::window::WindowMode;
+use unfair_advantage_lib::launcher::{LaunchOptions, RunSummary, USAGE};
+use unfair_advantage_lib::simulation::{ClockMode, SimulationClock};
 use unfair_advantage_lib::*;
 
 fn main() {
-    App::new()
-        // Configure the game window
-        .insert_resource(WindowDescriptor {
-            width: 1920.0,
-            height: 1080.0,
-            title: "Unfair Snek".to_string(),
-            mode: WindowMode::Windowed,
-            scale_factor_override: Some(1.0),
-            ..Default::default()
-        })
-        // Standard Bevy functionality
-        .add_plugins(DefaultPlugins)
-        // Add plugins here
-        .add_plugin(UnfairAdvantagePlugin)
-        .run();
+    let options = match LaunchOptions::parse(std::env::args().skip(1)) {
+        Ok(options) if options.help => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    if options.headless_ticks.is_some() {
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(UnfairAdvantagePlugin::headless());
+    } else {
+        app
+            // Configure the game window
+            .insert_resource(WindowDescriptor {
+                width: options.window_size.x,
+                height: options.window_size.y,
+                title: "Unfair Snek".to_string(),
+                mode: if options.fullscreen {
+                    WindowMode::BorderlessFullscreen
+                } else {
+                    WindowMode::Windowed
+                },
+                // The game camera fits the arena to any window size, so let players pick one
+                resizable: true,
+                ..Default::default()
+            })
+            // Standard Bevy functionality
+            .add_plugins(DefaultPlugins)
+            // Add plugins here
+            .add_plugin(UnfairAdvantagePlugin {
+                muted: options.mute,
+                ..Default::default()
+            });
+    }
+    if let Err(error) = options.configure(&mut app) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+
+    match options.headless_ticks {
+        Some(ticks) => {
+            app.world
+                .get_resource_mut::<SimulationClock>()
+                .unwrap()
+                .set_mode(ClockMode::StepPerUpdate);
+            // The first update only opens the match
+            app.update();
+            while app.world.get_resource::<SimulationClock>().unwrap().tick() < ticks {
+                app.update();
+            }
+            println!("{}", RunSummary::capture(&mut app.world));
+        }
+        None => app.run(),
+    }
 }
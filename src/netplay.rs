@@ -0,0 +1,710 @@
+//! Versus play over the local network.
+//!
+//! One instance hosts and the other joins it by address. The host plays the slayer and
+//! the joiner plays the snake. Only inputs cross the wire: both peers run the same
+//! deterministic match in lockstep, simulating a tick only once both sides' inputs for
+//! it are known. Local inputs are scheduled [`INPUT_DELAY`] ticks ahead so that a little
+//! latency does not stall the game, and every packet repeats all inputs the peer has not
+//! acknowledged yet, so a lost packet is covered by the next one.
+//!
+//! The host's welcome carries a fingerprint of the level, and a joiner whose copy of the level
+//! differs leaves straight away rather than play a different match.
+//!
+//! [`LinkConditions`] adds artificial latency and packet loss to outgoing packets, to try
+//! out bad connections between two instances on the same machine.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::handicap::Handicaps;
+use crate::level::{Level, LevelDirectory};
+use crate::replay::{decode_input, encode_input};
+use crate::rng::GameRng;
+use crate::simulation::{
+    in_state, SimulationClock, SimulationStage, SimulationSystem, SlayerInput, SnakeInput,
+    TickInput,
+};
+use crate::{AppState, MatchConfig};
+
+/// The port a host listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// How many ticks ahead local inputs are scheduled.
+pub const INPUT_DELAY: u64 = 4;
+
+const MAGIC: &[u8; 4] = b"UAN\0";
+const PROTOCOL_VERSION: u16 = 1;
+
+/// The most inputs repeated in a single packet.
+const MAX_INPUTS_PER_PACKET: usize = 256;
+
+/// How often a joining peer knocks until the host answers.
+const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many times the goodbye is sent, in the hope that one gets through.
+const BYE_REPEATS: usize = 3;
+
+/// Which side of the match a peer plays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Slayer,
+    Snake,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Slayer => "slayer",
+            Side::Snake => "snake",
+        }
+    }
+
+    /// Keeps only this side's half of `input`.
+    fn half(self, input: TickInput) -> TickInput {
+        match self {
+            Side::Slayer => TickInput {
+                slayer: input.slayer,
+                snake: SnakeInput::default(),
+                second_slayer: SlayerInput::default(),
+            },
+            Side::Snake => TickInput {
+                slayer: SlayerInput::default(),
+                snake: input.snake,
+                second_slayer: SlayerInput::default(),
+            },
+        }
+    }
+}
+
+/// Artificial trouble for outgoing packets.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    /// How long each packet is held back before it is sent.
+    pub latency: Duration,
+    /// The chance of each packet being dropped, from 0 to 1.
+    pub packet_loss: f32,
+}
+
+/// A message between peers.
+#[derive(Debug, Clone, PartialEq)]
+enum Packet {
+    /// A joining peer asks to play.
+    Hello,
+    /// The host accepts, telling the joiner how the match is set up and what its level
+    /// looks like.
+    Welcome {
+        config: MatchConfig,
+        level_fingerprint: u64,
+        input_delay: u8,
+    },
+    /// The sender's inputs from `first_tick` on, and how many of the receiver's inputs it has.
+    Inputs {
+        ack: u64,
+        first_tick: u64,
+        inputs: Vec<TickInput>,
+    },
+    /// The sender has left the match.
+    Bye,
+}
+
+impl Packet {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        match self {
+            Packet::Hello => bytes.push(0),
+            Packet::Welcome {
+                config,
+                level_fingerprint,
+                input_delay,
+            } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&config.seed.to_le_bytes());
+                bytes.extend_from_slice(&(config.level.len() as u16).to_le_bytes());
+                bytes.extend_from_slice(config.level.as_bytes());
+                bytes.extend_from_slice(&level_fingerprint.to_le_bytes());
+                bytes.extend_from_slice(&config.handicaps.to_bytes());
+                bytes.push(*input_delay);
+            }
+            Packet::Inputs {
+                ack,
+                first_tick,
+                inputs,
+            } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&ack.to_le_bytes());
+                bytes.extend_from_slice(&first_tick.to_le_bytes());
+                bytes.extend_from_slice(&(inputs.len() as u16).to_le_bytes());
+                for input in inputs {
+                    bytes.extend_from_slice(&encode_input(*input).to_le_bytes());
+                }
+            }
+            Packet::Bye => bytes.push(3),
+        }
+        bytes
+    }
+
+    /// Reads a packet, or `None` if it is not one of ours.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = bytes;
+        let mut take = |len: usize| -> Option<&[u8]> {
+            if reader.len() < len {
+                return None;
+            }
+            let (taken, rest) = reader.split_at(len);
+            reader = rest;
+            Some(taken)
+        };
+        if take(MAGIC.len())? != MAGIC {
+            return None;
+        }
+        if u16::from_le_bytes(take(2)?.try_into().ok()?) != PROTOCOL_VERSION {
+            return None;
+        }
+        match take(1)?[0] {
+            0 => Some(Packet::Hello),
+            1 => {
+                let seed = u64::from_le_bytes(take(8)?.try_into().ok()?);
+                let level_len = u16::from_le_bytes(take(2)?.try_into().ok()?) as usize;
+                let level = String::from_utf8(take(level_len)?.to_vec()).ok()?;
+                let level_fingerprint = u64::from_le_bytes(take(8)?.try_into().ok()?);
+                let handicaps = Handicaps::from_bytes(take(3)?.try_into().ok()?)?;
+                let input_delay = take(1)?[0];
+                Some(Packet::Welcome {
+                    config: MatchConfig {
+                        seed,
+                        level,
+                        handicaps,
+                        ..Default::default()
+                    },
+                    level_fingerprint,
+                    input_delay,
+                })
+            }
+            2 => {
+                let ack = u64::from_le_bytes(take(8)?.try_into().ok()?);
+                let first_tick = u64::from_le_bytes(take(8)?.try_into().ok()?);
+                let count = u16::from_le_bytes(take(2)?.try_into().ok()?) as usize;
+                let inputs = take(count * 2)?
+                    .chunks_exact(2)
+                    .map(|bits| decode_input(u16::from_le_bytes([bits[0], bits[1]])).ok())
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Packet::Inputs {
+                    ack,
+                    first_tick,
+                    inputs,
+                })
+            }
+            3 => Some(Packet::Bye),
+            _ => None,
+        }
+    }
+}
+
+/// Both peers' inputs for every tick, as far as they are known.
+#[derive(Debug)]
+pub struct Lockstep {
+    side: Side,
+    /// Our half of the inputs, starting with tick 1.
+    local: Vec<TickInput>,
+    /// The peer's half of the inputs, starting with tick 1.
+    remote: Vec<TickInput>,
+    /// How many of our inputs the peer is known to have.
+    acked: u64,
+    /// How many ticks have been simulated.
+    simulated: u64,
+}
+
+impl Lockstep {
+    /// Starts a match played as `side`, with inputs scheduled `delay` ticks ahead.
+    ///
+    /// Nobody has pressed anything during the first `delay` ticks.
+    pub fn new(side: Side, delay: u64) -> Self {
+        Self {
+            side,
+            local: vec![TickInput::default(); delay as usize],
+            remote: vec![TickInput::default(); delay as usize],
+            acked: 0,
+            simulated: 0,
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Schedules our half of `input` for the next tick that has no local input yet.
+    pub fn push_local(&mut self, input: TickInput) {
+        self.local.push(self.side.half(input));
+    }
+
+    /// Takes in inputs from the peer, starting at `first_tick`, ignoring any that are already known.
+    pub fn receive(&mut self, ack: u64, first_tick: u64, inputs: &[TickInput]) {
+        self.acked = self.acked.max(ack.min(self.local.len() as u64));
+        let known = self.remote.len() as u64;
+        if first_tick == 0 || first_tick > known + 1 {
+            // A gap; the missing inputs will be repeated in a later packet
+            return;
+        }
+        let skip = (known + 1 - first_tick) as usize;
+        let peer = self.side_of_peer();
+        self.remote
+            .extend(inputs.iter().skip(skip).map(|input| peer.half(*input)));
+    }
+
+    fn side_of_peer(&self) -> Side {
+        match self.side {
+            Side::Slayer => Side::Snake,
+            Side::Snake => Side::Slayer,
+        }
+    }
+
+    /// The last tick for which both sides' inputs are known.
+    pub fn confirmed_tick(&self) -> u64 {
+        self.local.len().min(self.remote.len()) as u64
+    }
+
+    /// How many more ticks can be simulated before the peer's inputs run out.
+    pub fn ticks_ready(&self) -> u64 {
+        self.confirmed_tick().saturating_sub(self.simulated)
+    }
+
+    /// Moves on to the next tick, given what was pressed locally, and returns its combined inputs.
+    pub fn advance(&mut self, input: TickInput) -> Option<TickInput> {
+        self.push_local(input);
+        self.simulated += 1;
+        self.input(self.simulated)
+    }
+
+    /// The combined inputs for `tick`, if both sides' are known.
+    pub fn input(&self, tick: u64) -> Option<TickInput> {
+        let index = tick.checked_sub(1)? as usize;
+        let (local, remote) = (self.local.get(index)?, self.remote.get(index)?);
+        let (slayer, snake) = match self.side {
+            Side::Slayer => (local, remote),
+            Side::Snake => (remote, local),
+        };
+        Some(TickInput {
+            slayer: slayer.slayer,
+            snake: snake.snake,
+            ..Default::default()
+        })
+    }
+
+    /// The packet that tells the peer everything it does not have yet.
+    fn outgoing(&self) -> Packet {
+        let first = self.acked as usize;
+        let last = self.local.len().min(first + MAX_INPUTS_PER_PACKET);
+        Packet::Inputs {
+            ack: self.remote.len() as u64,
+            first_tick: self.acked + 1,
+            inputs: self.local[first..last].to_vec(),
+        }
+    }
+}
+
+/// A UDP socket, with optional artificial latency and loss on the way out.
+struct Link {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    conditions: LinkConditions,
+    delayed: Vec<(Instant, SocketAddr, Vec<u8>)>,
+    /// Decides which packets to drop; nothing in the match depends on it.
+    rng: GameRng,
+}
+
+impl Link {
+    fn bind(address: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        let rng = GameRng::new(socket.local_addr()?.port() as u64);
+        Ok(Self {
+            socket,
+            peer: None,
+            conditions,
+            delayed: Vec::new(),
+            rng,
+        })
+    }
+
+    fn send(&mut self, packet: &Packet) {
+        let peer = match self.peer {
+            Some(peer) => peer,
+            None => return,
+        };
+        if self.rng.chance(self.conditions.packet_loss) {
+            return;
+        }
+        let due = Instant::now() + self.conditions.latency;
+        self.delayed.push((due, peer, packet.to_bytes()));
+        self.flush();
+    }
+
+    /// Sends whatever has been held back for long enough.
+    fn flush(&mut self) {
+        let now = Instant::now();
+        let socket = &self.socket;
+        self.delayed.retain(|(due, peer, bytes)| {
+            if *due > now {
+                return true;
+            }
+            if let Err(error) = socket.send_to(bytes, peer) {
+                warn!("Could not send to {}: {}", peer, error);
+            }
+            false
+        });
+    }
+
+    /// Every packet that has arrived since the last call.
+    fn receive(&mut self) -> Vec<(SocketAddr, Packet)> {
+        let mut packets = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    if self.peer.map_or(true, |peer| peer == from) {
+                        packets.extend(Packet::from_bytes(&buffer[..len]).map(|p| (from, p)));
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    // Windows reports a peer that went away as an error on the next read
+                    debug!("Could not receive: {}", error);
+                    break;
+                }
+            }
+        }
+        packets
+    }
+}
+
+enum Phase {
+    Connecting { last_hello: Option<Instant> },
+    Playing(Lockstep),
+}
+
+/// A network match, from looking for a peer until one of the two leaves.
+///
+/// Insert it as a resource to start hosting or joining.
+pub struct Netplay {
+    link: Link,
+    config: MatchConfig,
+    hosting: bool,
+    phase: Phase,
+}
+
+impl Netplay {
+    /// Waits for a peer on `address` and then plays the slayer in a match set up by `config`.
+    pub fn host(
+        address: SocketAddr,
+        config: MatchConfig,
+        conditions: LinkConditions,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            link: Link::bind(address, conditions)?,
+            config,
+            hosting: true,
+            phase: Phase::Connecting { last_hello: None },
+        })
+    }
+
+    /// Joins the match hosted at `host`, to play the snake.
+    pub fn join(host: SocketAddr, conditions: LinkConditions) -> io::Result<Self> {
+        let any = match host {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0; 16], 0)),
+        };
+        let mut link = Link::bind(any, conditions)?;
+        link.peer = Some(host);
+        Ok(Self {
+            link,
+            config: MatchConfig::default(),
+            hosting: false,
+            phase: Phase::Connecting { last_hello: None },
+        })
+    }
+
+    /// The address this end of the match is reachable at.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.link.socket.local_addr()
+    }
+
+    /// The inputs of the match, once both peers have found each other.
+    pub fn lockstep(&self) -> Option<&Lockstep> {
+        match &self.phase {
+            Phase::Playing(lockstep) => Some(lockstep),
+            Phase::Connecting { .. } => None,
+        }
+    }
+}
+
+/// Finds a peer, then keeps inputs flowing both ways while the match is on.
+pub struct NetplayPlugin;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(run_netplay)
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(leave_netplay),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                lockstep_tick_input
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Netplay)
+                    .after(SimulationSystem::Input)
+                    .before(SimulationSystem::Gameplay),
+            );
+    }
+}
+
+/// The fingerprint of the level `name` as this end would play it, falling back to the default
+/// level as [`load_level`](crate::level::load_level) does.
+fn level_fingerprint(name: &str, directory: &LevelDirectory) -> u64 {
+    Level::named(name, directory)
+        .unwrap_or_default()
+        .fingerprint()
+}
+
+fn start_match(commands: &mut Commands, state: &mut State<AppState>, config: MatchConfig) {
+    commands.insert_resource(config);
+    if let Err(error) = state.set(AppState::InOnePlayerGame) {
+        warn!("Could not start the network match: {:?}", error);
+    }
+}
+
+fn run_netplay(
+    mut commands: Commands,
+    netplay: Option<ResMut<Netplay>>,
+    mut state: ResMut<State<AppState>>,
+    mut clock: ResMut<SimulationClock>,
+    directory: Res<LevelDirectory>,
+) {
+    let mut netplay = match netplay {
+        Some(netplay) => netplay,
+        None => return,
+    };
+    let netplay = &mut *netplay;
+    for (from, packet) in netplay.link.receive() {
+        match packet {
+            Packet::Hello if netplay.hosting => {
+                if netplay.link.peer.is_none() {
+                    info!("{} joined the match", from);
+                    netplay.link.peer = Some(from);
+                    netplay.phase = Phase::Playing(Lockstep::new(Side::Slayer, INPUT_DELAY));
+                    start_match(&mut commands, &mut state, netplay.config.clone());
+                }
+                // Answered every time, in case an earlier welcome was lost
+                netplay.link.send(&Packet::Welcome {
+                    config: netplay.config.clone(),
+                    level_fingerprint: level_fingerprint(&netplay.config.level, &directory),
+                    input_delay: INPUT_DELAY as u8,
+                });
+            }
+            Packet::Welcome {
+                config,
+                level_fingerprint: fingerprint,
+                input_delay,
+            } if matches!(netplay.phase, Phase::Connecting { .. }) => {
+                if level_fingerprint(&config.level, &directory) != fingerprint {
+                    warn!(
+                        "Could not join the match at {}: level {} is not the same as the host's",
+                        from, config.level
+                    );
+                    send_bye(&mut netplay.link);
+                    commands.remove_resource::<Netplay>();
+                    return;
+                }
+                info!("Joined the match at {}", from);
+                netplay.config = config.clone();
+                netplay.phase = Phase::Playing(Lockstep::new(Side::Snake, input_delay as u64));
+                start_match(&mut commands, &mut state, config);
+            }
+            Packet::Inputs {
+                ack,
+                first_tick,
+                inputs,
+            } => {
+                if let Phase::Playing(lockstep) = &mut netplay.phase {
+                    lockstep.receive(ack, first_tick, &inputs);
+                }
+            }
+            Packet::Bye if matches!(netplay.phase, Phase::Playing(_)) => {
+                info!("The other player left the match");
+                commands.remove_resource::<Netplay>();
+                clock.set_tick_limit(None);
+                if *state.current() != AppState::MainMenu {
+                    if let Err(error) = state.replace(AppState::MainMenu) {
+                        warn!("Could not leave the network match: {:?}", error);
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    match &netplay.phase {
+        Phase::Connecting { last_hello } if !netplay.hosting => {
+            if last_hello.map_or(true, |last| last.elapsed() >= HELLO_INTERVAL) {
+                netplay.phase = Phase::Connecting {
+                    last_hello: Some(Instant::now()),
+                };
+                netplay.link.send(&Packet::Hello);
+            }
+        }
+        Phase::Connecting { .. } => {}
+        Phase::Playing(lockstep) => {
+            let packet = lockstep.outgoing();
+            clock.set_tick_limit(Some(lockstep.confirmed_tick()));
+            netplay.link.send(&packet);
+        }
+    }
+    netplay.link.flush();
+}
+
+fn lockstep_tick_input(
+    netplay: Option<ResMut<Netplay>>,
+    clock: Res<SimulationClock>,
+    mut tick_input: ResMut<TickInput>,
+) {
+    if let Some(mut netplay) = netplay {
+        if let Phase::Playing(lockstep) = &mut netplay.phase {
+            match lockstep.advance(*tick_input) {
+                Some(input) => *tick_input = input,
+                None => error!("Simulated tick {} before its inputs arrived", clock.tick()),
+            }
+        }
+    }
+}
+
+fn leave_netplay(
+    mut commands: Commands,
+    netplay: Option<ResMut<Netplay>>,
+    mut clock: ResMut<SimulationClock>,
+) {
+    if let Some(mut netplay) = netplay {
+        send_bye(&mut netplay.link);
+        commands.remove_resource::<Netplay>();
+        clock.set_tick_limit(None);
+    }
+}
+
+/// Tells the peer that this end is leaving the match.
+fn send_bye(link: &mut Link) {
+    // Skip the artificial trouble, since there will be no later packets to make up for it
+    link.conditions = LinkConditions::default();
+    for _ in 0..BYE_REPEATS {
+        link.send(&Packet::Bye);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_grid::Direction;
+
+    fn input(right: bool, turn: Option<Direction>) -> TickInput {
+        TickInput {
+            slayer: SlayerInput {
+                right,
+                ..Default::default()
+            },
+            snake: SnakeInput {
+                turn,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let packets = [
+            Packet::Hello,
+            Packet::Welcome {
+                config: MatchConfig {
+                    seed: 99,
+                    level: "arena".to_string(),
+                    handicaps: Handicaps {
+                        sword_damage: 2,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                level_fingerprint: Level::default().fingerprint(),
+                input_delay: 4,
+            },
+            Packet::Inputs {
+                ack: 12,
+                first_tick: 7,
+                inputs: vec![input(true, None), input(false, Some(Direction::Up))],
+            },
+            Packet::Bye,
+        ];
+        for packet in packets {
+            assert_eq!(Packet::from_bytes(&packet.to_bytes()), Some(packet));
+        }
+        assert_eq!(Packet::from_bytes(b"UAN\0\x01"), None);
+        assert_eq!(Packet::from_bytes(b"hello"), None);
+    }
+
+    #[test]
+    fn lockstep_waits_for_both_sides_and_survives_lost_packets() {
+        let mut slayer = Lockstep::new(Side::Slayer, 2);
+        let mut snake = Lockstep::new(Side::Snake, 2);
+        assert_eq!(slayer.confirmed_tick(), 2);
+        assert_eq!(slayer.input(1), Some(TickInput::default()));
+
+        // Each side presses everything, but only its own half counts
+        let everything = input(true, Some(Direction::Down));
+        for _ in 0..3 {
+            slayer.push_local(everything);
+            snake.push_local(everything);
+        }
+        assert_eq!(slayer.confirmed_tick(), 2);
+
+        // The first packet from the slayer is lost, the second gets through
+        let _lost = slayer.outgoing();
+        slayer.push_local(everything);
+        if let Packet::Inputs {
+            ack,
+            first_tick,
+            inputs,
+        } = slayer.outgoing()
+        {
+            snake.receive(ack, first_tick, &inputs);
+        }
+        if let Packet::Inputs {
+            ack,
+            first_tick,
+            inputs,
+        } = snake.outgoing()
+        {
+            slayer.receive(ack, first_tick, &inputs);
+        }
+
+        assert_eq!(snake.confirmed_tick(), 5);
+        assert_eq!(slayer.confirmed_tick(), 5);
+        for tick in 1..=5 {
+            assert_eq!(slayer.input(tick), snake.input(tick));
+        }
+        assert_eq!(slayer.input(3), Some(everything));
+        assert_eq!(slayer.input(6), None);
+
+        // Acknowledged inputs are not sent again
+        match slayer.outgoing() {
+            Packet::Inputs {
+                first_tick, inputs, ..
+            } => {
+                assert_eq!(first_tick, 7);
+                assert!(inputs.is_empty());
+            }
+            packet => panic!("unexpected {:?}", packet),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Lightweight CPU sprite particles.
+//!
+//! Particles are plain coloured sprites drawn from a fixed pool of entities that is spawned
+//! once per match, so bursts never spawn or despawn anything. Each burst is described by a
+//! [`ParticleEffect`]; the effects used by the game are the constants in this module.
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use bevy::core::Time;
+use bevy::prelude::*;
+use heron::prelude::Gravity;
+
+use crate::rng::GameRng;
+use crate::{
+    cell_to_world, AppState, MatchConfig, SlayerDeathEvent, SnakeSeveredEvent, SwordHitEvent,
+};
+
+/// How many particles can be alive at once; bursts beyond this are cut short.
+const POOL_SIZE: usize = 512;
+
+/// How a burst of particles looks and moves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleEffect {
+    /// How many particles are emitted at once.
+    pub count: u32,
+    /// The range of launch speeds, in world units per second.
+    pub speed: (f32, f32),
+    /// The launch angle in the middle of the spread, in radians anticlockwise from the right.
+    pub direction: f32,
+    /// How wide a fan the particles are launched in, in radians.
+    pub spread: f32,
+    /// The range of lifetimes, in seconds.
+    pub lifetime: (f32, f32),
+    /// The width and height of each particle, in world units.
+    pub size: f32,
+    pub color: Color,
+    /// How strongly the [`Gravity`] resource pulls on the particles.
+    pub gravity_scale: f32,
+}
+
+/// Chunks of snake flying out of a severed segment.
+pub const SNAKE_RIP: ParticleEffect = ParticleEffect {
+    count: 24,
+    speed: (80.0, 260.0),
+    direction: FRAC_PI_2,
+    spread: TAU,
+    lifetime: (0.4, 0.9),
+    size: 8.0,
+    color: Color::rgb(0.25, 0.55, 0.15),
+    gravity_scale: 1.0,
+};
+
+/// Sparks where the sword strikes a snake.
+pub const SWORD_SPARK: ParticleEffect = ParticleEffect {
+    count: 10,
+    speed: (200.0, 420.0),
+    direction: FRAC_PI_2,
+    spread: TAU,
+    lifetime: (0.1, 0.25),
+    size: 4.0,
+    color: Color::rgb(1.0, 0.9, 0.5),
+    gravity_scale: 0.3,
+};
+
+/// What is left of the slayer after being chomped.
+pub const SLAYER_DEATH: ParticleEffect = ParticleEffect {
+    count: 40,
+    speed: (60.0, 300.0),
+    direction: FRAC_PI_2,
+    spread: PI,
+    lifetime: (0.6, 1.2),
+    size: 10.0,
+    color: Color::rgb(0.7, 0.05, 0.05),
+    gravity_scale: 1.5,
+};
+
+/// Emits a burst of `effect` centred on `at`.
+pub struct SpawnParticles {
+    pub effect: &'static ParticleEffect,
+    pub at: Vec2,
+}
+
+#[derive(Component, Default)]
+struct Particle {
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    gravity_scale: f32,
+    color: Color,
+}
+
+/// The particle entities that are not currently alive.
+#[derive(Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+}
+
+/// The stream of the match seed that particles draw from.
+const PARTICLE_STREAM: u64 = 1;
+
+/// Randomness for particles, seeded from the match so that they look the same in a replay,
+/// but kept apart from the [`GameRng`] so that they cannot change how the match plays out.
+struct ParticleRng(GameRng);
+
+impl Default for ParticleRng {
+    fn default() -> Self {
+        Self(GameRng::with_stream(0, PARTICLE_STREAM))
+    }
+}
+
+impl ParticleRng {
+    /// A number between 0 and 1.
+    fn fraction(&mut self) -> f32 {
+        self.0.fraction()
+    }
+
+    fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+        self.0.range(min, max)
+    }
+}
+
+/// Pools, moves and fades particles, and emits them for hits, severs and deaths.
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticlePool>()
+            .init_resource::<ParticleRng>()
+            .add_event::<SpawnParticles>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(spawn_particle_pool)
+                    .with_system(seed_particle_rng),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame)
+                    .with_system(particles_for_gameplay_events.before(ParticleSystem::Emit))
+                    .with_system(emit_particles.label(ParticleSystem::Emit))
+                    .with_system(update_particles.after(ParticleSystem::Emit)),
+            );
+    }
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum ParticleSystem {
+    Emit,
+}
+
+/// Spawns the hidden particle entities; they are despawned along with the rest of the match.
+fn spawn_particle_pool(mut commands: Commands, mut pool: ResMut<ParticlePool>) {
+    pool.free = (0..POOL_SIZE)
+        .map(|_| {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(Particle::default())
+                .id()
+        })
+        .collect();
+}
+
+fn seed_particle_rng(config: Res<MatchConfig>, mut rng: ResMut<ParticleRng>) {
+    rng.0 = GameRng::with_stream(config.seed, PARTICLE_STREAM);
+}
+
+fn particles_for_gameplay_events(
+    mut sword_hits: EventReader<SwordHitEvent>,
+    mut severs: EventReader<SnakeSeveredEvent>,
+    mut deaths: EventReader<SlayerDeathEvent>,
+    mut spawns: EventWriter<SpawnParticles>,
+) {
+    let cell_centre = |x: i32, y: i32| cell_to_world(x as f32, y as f32);
+    for hit in sword_hits.iter() {
+        spawns.send(SpawnParticles {
+            effect: &SWORD_SPARK,
+            at: cell_centre(hit.position.x, hit.position.y),
+        });
+    }
+    for sever in severs.iter() {
+        spawns.send(SpawnParticles {
+            effect: &SNAKE_RIP,
+            at: cell_centre(sever.position.x, sever.position.y),
+        });
+    }
+    for death in deaths.iter() {
+        spawns.send(SpawnParticles {
+            effect: &SLAYER_DEATH,
+            at: death.translation.truncate(),
+        });
+    }
+}
+
+fn emit_particles(
+    mut spawns: EventReader<SpawnParticles>,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<ParticleRng>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Sprite, &mut Visibility)>,
+) {
+    for spawn in spawns.iter() {
+        let effect = spawn.effect;
+        for _ in 0..effect.count {
+            let entity = match pool.free.pop() {
+                Some(entity) => entity,
+                None => return,
+            };
+            let (mut particle, mut transform, mut sprite, mut visibility) =
+                match particles.get_mut(entity) {
+                    Ok(particle) => particle,
+                    Err(_) => continue,
+                };
+            let angle = effect.direction + (rng.fraction() - 0.5) * effect.spread;
+            *particle = Particle {
+                velocity: Vec2::new(angle.cos(), angle.sin()) * rng.range(effect.speed),
+                age: 0.0,
+                lifetime: rng.range(effect.lifetime),
+                gravity_scale: effect.gravity_scale,
+                color: effect.color,
+            };
+            // In front of the arena, behind the camera's overlays
+            transform.translation = spawn.at.extend(5.0);
+            sprite.color = effect.color;
+            sprite.custom_size = Some(Vec2::splat(effect.size));
+            visibility.is_visible = true;
+        }
+    }
+}
+
+fn update_particles(
+    time: Res<Time>,
+    gravity: Res<Gravity>,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    let gravity = gravity.vector().truncate();
+    for (entity, mut particle, mut transform, mut sprite, mut visibility) in particles.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            visibility.is_visible = false;
+            pool.free.push(entity);
+            continue;
+        }
+        let pull = gravity * particle.gravity_scale;
+        particle.velocity += pull * delta;
+        transform.translation += (particle.velocity * delta).extend(0.0);
+        let mut color = particle.color;
+        color.set_a(particle.color.a() * (1.0 - particle.age / particle.lifetime));
+        sprite.color = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_ranges_stay_in_bounds() {
+        let mut rng = ParticleRng::default();
+        for _ in 0..10_000 {
+            let value = rng.range(SNAKE_RIP.lifetime);
+            assert!((SNAKE_RIP.lifetime.0..=SNAKE_RIP.lifetime.1).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,515 @@
+//! Pickups that give the slayer a timed advantage over the snake.
+//!
+//! Every so often a [`Pickup`] appears in a free cell of the arena. Touching it gives the
+//! slayer its [`PowerUp`] for a while, which is tracked in the slayer's [`Buffs`] and shown
+//! on the HUD until it wears off. Each kind of pickup collides on its own layer, so the
+//! collision alone tells which power-up was picked up.
+//!
+//! Where pickups appear and what they hold is drawn from the match's [`GameRng`], so they
+//! turn up in the same places in a replay or on both ends of a network match.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::level::Level;
+use crate::rng::GameRng;
+use crate::simulation::{
+    in_state, SimulationClock, SimulationStage, SimulationSystem, TickCollisions,
+};
+use crate::snake_grid::{Occupancy, Position};
+use crate::{
+    cell_to_world, is_slayer, AppState, AttackCooldown, Layer, SlayerAction, SlayerPlayer,
+    SnakeAction, SnakeSlowdown, ARENA_HEIGHT, ARENA_WIDTH, ATTACK_COOLDOWN, SLAYER_TINTS,
+    TILE_SIZE,
+};
+
+/// How often a new pickup is placed, in seconds.
+const PICKUP_INTERVAL: f32 = 8.0;
+/// The most pickups that can be waiting in the arena at once.
+const MAX_PICKUPS: usize = 2;
+/// How many random cells are tried before giving up on placing a pickup until next time.
+const PLACEMENT_ATTEMPTS: u32 = 16;
+/// The attack cooldown while [`PowerUp::QuickAttack`] lasts, in seconds.
+const QUICK_ATTACK_COOLDOWN: f32 = 0.25;
+/// How many times longer the snake takes per move while any slayer has
+/// [`PowerUp::SlowSnake`]. Having it twice over does not slow the snake down any further.
+const SLOW_SNAKE_FACTOR: u32 = 2;
+
+/// A timed advantage for the slayer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerUp {
+    /// The slayer can jump once more in mid-air.
+    DoubleJump,
+    /// The sword reaches a cell further.
+    SwordReach,
+    /// The sword can be swung again sooner.
+    QuickAttack,
+    /// The snake cannot chomp the slayer.
+    Invulnerability,
+    /// The snake moves more slowly.
+    SlowSnake,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 5] = [
+        PowerUp::DoubleJump,
+        PowerUp::SwordReach,
+        PowerUp::QuickAttack,
+        PowerUp::Invulnerability,
+        PowerUp::SlowSnake,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// How long the power-up lasts once picked up.
+    pub fn duration(self) -> Duration {
+        let seconds = match self {
+            PowerUp::DoubleJump => 12.0,
+            PowerUp::SwordReach => 10.0,
+            PowerUp::QuickAttack => 10.0,
+            PowerUp::Invulnerability => 5.0,
+            PowerUp::SlowSnake => 5.0,
+        };
+        Duration::from_secs_f32(seconds)
+    }
+
+    /// The collision layer of pickups holding this power-up.
+    pub(crate) fn layer(self) -> Layer {
+        match self {
+            PowerUp::DoubleJump => Layer::DoubleJumpPickup,
+            PowerUp::SwordReach => Layer::SwordReachPickup,
+            PowerUp::QuickAttack => Layer::QuickAttackPickup,
+            PowerUp::Invulnerability => Layer::InvulnerabilityPickup,
+            PowerUp::SlowSnake => Layer::SlowSnakePickup,
+        }
+    }
+
+    /// The colour of the pickup and of its HUD indicator.
+    fn color(self) -> Color {
+        match self {
+            PowerUp::DoubleJump => Color::rgb(0.3, 0.7, 1.0),
+            PowerUp::SwordReach => Color::rgb(1.0, 0.55, 0.1),
+            PowerUp::QuickAttack => Color::rgb(1.0, 0.9, 0.2),
+            PowerUp::Invulnerability => Color::rgb(0.95, 0.95, 0.95),
+            PowerUp::SlowSnake => Color::rgb(0.7, 0.35, 0.9),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            PowerUp::DoubleJump => "Double jump",
+            PowerUp::SwordReach => "Long sword",
+            PowerUp::QuickAttack => "Quick sword",
+            PowerUp::Invulnerability => "Invulnerable",
+            PowerUp::SlowSnake => "Slow snake",
+        }
+    }
+}
+
+/// The power-ups the slayer has, and how long each has left.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Buffs {
+    remaining: [Duration; PowerUp::ALL.len()],
+}
+
+impl Buffs {
+    /// How long `power_up` has left, which is zero if the slayer does not have it.
+    pub fn remaining(&self, power_up: PowerUp) -> Duration {
+        self.remaining[power_up.index()]
+    }
+
+    pub fn is_active(&self, power_up: PowerUp) -> bool {
+        !self.remaining(power_up).is_zero()
+    }
+
+    /// Gives the slayer `power_up` for at least `duration`, returning whether they did not
+    /// have it yet.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use unfair_advantage_lib::power_ups::{Buffs, PowerUp};
+    /// let mut buffs = Buffs::default();
+    /// assert!(buffs.grant(PowerUp::DoubleJump, Duration::from_secs(5)));
+    /// // Picking it up again only tops it up
+    /// assert!(!buffs.grant(PowerUp::DoubleJump, Duration::from_secs(3)));
+    /// assert_eq!(buffs.remaining(PowerUp::DoubleJump), Duration::from_secs(5));
+    /// ```
+    pub fn grant(&mut self, power_up: PowerUp, duration: Duration) -> bool {
+        let was_active = self.is_active(power_up);
+        let remaining = &mut self.remaining[power_up.index()];
+        *remaining = (*remaining).max(duration);
+        !was_active && self.is_active(power_up)
+    }
+
+    /// Lets `elapsed` pass, returning the power-ups that wore off.
+    pub fn tick(&mut self, elapsed: Duration) -> Vec<PowerUp> {
+        PowerUp::ALL
+            .into_iter()
+            .filter(|power_up| {
+                let remaining = &mut self.remaining[power_up.index()];
+                let was_active = !remaining.is_zero();
+                *remaining = remaining.saturating_sub(elapsed);
+                was_active && remaining.is_zero()
+            })
+            .collect()
+    }
+}
+
+/// A power-up waiting in the arena to be picked up.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pickup(pub PowerUp);
+
+/// Fires every time a new pickup is due.
+pub struct PickupTimer(pub Timer);
+
+impl Default for PickupTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(PICKUP_INTERVAL, true))
+    }
+}
+
+/// The slayer `slayer` picked up `power_up`.
+pub struct PowerUpCollectedEvent {
+    pub slayer: Entity,
+    pub power_up: PowerUp,
+}
+
+#[derive(Component)]
+struct OnPowerUpHud;
+
+/// Names the slayer whose power-ups are shown on a row of the HUD, counting from 0.
+#[derive(Component)]
+struct BuffRowLabel(usize);
+
+/// Shows how long one power-up of one slayer has left.
+#[derive(Component)]
+struct BuffIndicator {
+    player: usize,
+    power_up: PowerUp,
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+enum PowerUpSystem {
+    Grant,
+    Tick,
+}
+
+/// Places pickups, hands out their power-ups and shows them on the HUD.
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PickupTimer>()
+            .add_event::<PowerUpCollectedEvent>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(reset_pickup_timer)
+                    .with_system(setup_power_up_hud),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(update_power_up_hud),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_power_up_hud),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(collect_pickups.before(PowerUpSystem::Grant))
+                    .with_system(
+                        grant_power_ups
+                            .label(PowerUpSystem::Grant)
+                            .before(PowerUpSystem::Tick),
+                    )
+                    .with_system(
+                        tick_buffs
+                            .label(PowerUpSystem::Tick)
+                            .before(SlayerAction::Controls)
+                            .before(SnakeAction::Movement),
+                    )
+                    .with_system(place_pickups.after(SnakeAction::Movement)),
+            );
+    }
+}
+
+/// The attack cooldown that goes with `buffs`, in seconds.
+fn attack_cooldown(buffs: &Buffs) -> f32 {
+    if buffs.is_active(PowerUp::QuickAttack) {
+        QUICK_ATTACK_COOLDOWN
+    } else {
+        ATTACK_COOLDOWN
+    }
+}
+
+fn reset_pickup_timer(mut timer: ResMut<PickupTimer>) {
+    *timer = PickupTimer::default();
+}
+
+/// Places a new pickup in a random free cell whenever the [`PickupTimer`] fires.
+fn place_pickups(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    mut timer: ResMut<PickupTimer>,
+    mut rng: ResMut<GameRng>,
+    (occupancy, level): (Res<Occupancy<Entity>>, Res<Level>),
+    pickups: Query<&Position, With<Pickup>>,
+) {
+    timer.0.tick(clock.step());
+    if !timer.0.just_finished() || pickups.iter().count() >= MAX_PICKUPS {
+        return;
+    }
+    let power_up = *rng.pick(&PowerUp::ALL).unwrap();
+    let cell = (0..PLACEMENT_ATTEMPTS)
+        .map(|_| {
+            Position::new(
+                rng.below(ARENA_WIDTH) as i32,
+                rng.below(ARENA_HEIGHT) as i32,
+            )
+        })
+        .find(|cell| {
+            let centre = cell_to_world(cell.x as f32, cell.y as f32);
+            occupancy.is_free(*cell)
+                && !level.obstacles.contains(cell)
+                && !level.dens.contains(cell)
+                && !level
+                    .platforms
+                    .iter()
+                    .any(|platform| platform.contains(centre))
+                && !pickups.iter().any(|pickup| pickup == cell)
+        });
+    if let Some(cell) = cell {
+        spawn_pickup(&mut commands, power_up, cell);
+    }
+}
+
+/// Spawns a pickup holding `power_up` in `cell`.
+pub(crate) fn spawn_pickup(commands: &mut Commands, power_up: PowerUp, cell: Position) {
+    let size = Vec2::splat(TILE_SIZE / 2.0);
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: power_up.color(),
+                custom_size: Some(size),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(
+                cell_to_world(cell.x as f32, cell.y as f32).extend(2.0),
+            ),
+            ..Default::default()
+        })
+        .insert(Pickup(power_up))
+        .insert(cell)
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Cuboid {
+            half_extends: size.extend(0.0) / 2.0,
+            border_radius: None,
+        })
+        .insert(CollisionLayers::new(power_up.layer(), Layer::Slayer));
+}
+
+/// Removes the pickups the slayer touches, telling which power-up each held.
+fn collect_pickups(
+    mut commands: Commands,
+    collisions: Res<TickCollisions>,
+    mut collected: EventWriter<PowerUpCollectedEvent>,
+    (pickups, slayers): (Query<&Pickup>, Query<(), With<Buffs>>),
+) {
+    let mut taken = Vec::new();
+    for event in collisions.0.iter().filter(|event| event.is_started()) {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        // Platforms and snakes are on every layer, so neither can collect a pickup
+        let (slayer, pickup) = if is_slayer(layers_1) {
+            (entity_1, (entity_2, layers_2))
+        } else if is_slayer(layers_2) {
+            (entity_2, (entity_1, layers_1))
+        } else {
+            continue;
+        };
+        if slayers.get(slayer).is_err() {
+            continue;
+        }
+        // Make sure the other body really is a pickup
+        let power_up = PowerUp::ALL
+            .into_iter()
+            .find(|power_up| pickup.1.contains_group(power_up.layer()));
+        // The same pickup may be touched twice before it is gone
+        if let (Some(power_up), Ok(_)) = (power_up, pickups.get(pickup.0)) {
+            if !taken.contains(&pickup.0) {
+                taken.push(pickup.0);
+                commands.entity(pickup.0).despawn();
+                collected.send(PowerUpCollectedEvent { slayer, power_up });
+            }
+        }
+    }
+}
+
+fn grant_power_ups(
+    mut collected: EventReader<PowerUpCollectedEvent>,
+    mut slayers: Query<&mut Buffs>,
+) {
+    for event in collected.iter() {
+        if let Ok(mut buffs) = slayers.get_mut(event.slayer) {
+            buffs.grant(event.power_up, event.power_up.duration());
+        }
+    }
+}
+
+/// Wears power-ups down and keeps their effects up to date.
+///
+/// The snake is slowed for as long as any slayer still in the arena has
+/// [`PowerUp::SlowSnake`], so a slayer that is gone takes the slow with them.
+fn tick_buffs(
+    clock: Res<SimulationClock>,
+    mut slowdown: ResMut<SnakeSlowdown>,
+    mut slayers: Query<(&mut Buffs, &mut AttackCooldown)>,
+) {
+    let mut slowed = false;
+    for (mut buffs, mut cooldown) in slayers.iter_mut() {
+        buffs.tick(clock.step());
+        slowed |= buffs.is_active(PowerUp::SlowSnake);
+        cooldown
+            .0
+            .set_duration(Duration::from_secs_f32(attack_cooldown(&buffs)));
+    }
+    *slowdown = SnakeSlowdown(if slowed { SLOW_SNAKE_FACTOR } else { 1 });
+}
+
+fn setup_power_up_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OnPowerUpHud);
+    let style = |color| TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size: 30.0,
+        color,
+    };
+    let text = |style| TextBundle {
+        text: Text::with_section("", style, Default::default()),
+        style: Style {
+            margin: Rect {
+                left: Val::Px(10.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(OnPowerUpHud)
+        .with_children(|parent| {
+            // A row for each slayer, so co-op players can tell whose power-ups are whose
+            for (player, tint) in SLAYER_TINTS.into_iter().enumerate() {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        color: Color::NONE.into(),
+                        ..Default::default()
+                    })
+                    .with_children(|row| {
+                        row.spawn_bundle(text(style(tint)))
+                            .insert(BuffRowLabel(player));
+                        for power_up in PowerUp::ALL {
+                            row.spawn_bundle(text(style(power_up.color())))
+                                .insert(BuffIndicator { player, power_up });
+                        }
+                    });
+            }
+        });
+}
+
+fn update_power_up_hud(
+    slayers: Query<(&SlayerPlayer, &Buffs)>,
+    mut labels: Query<(&BuffRowLabel, &mut Text), Without<BuffIndicator>>,
+    mut indicators: Query<(&BuffIndicator, &mut Text), Without<BuffRowLabel>>,
+) {
+    let buffs = |player: usize| {
+        slayers
+            .iter()
+            .find(|(slayer, _)| slayer.0 == player)
+            .map(|(_, buffs)| buffs.clone())
+            .unwrap_or_default()
+    };
+    for (label, mut text) in labels.iter_mut() {
+        let buffs = buffs(label.0);
+        let any_active = PowerUp::ALL
+            .into_iter()
+            .any(|power_up| buffs.is_active(power_up));
+        text.sections[0].value = if any_active {
+            format!("P{}", label.0 + 1)
+        } else {
+            String::new()
+        };
+    }
+    for (indicator, mut text) in indicators.iter_mut() {
+        let (buffs, power_up) = (buffs(indicator.player), indicator.power_up);
+        text.sections[0].value = if buffs.is_active(power_up) {
+            let seconds = buffs.remaining(power_up).as_secs_f32().ceil();
+            format!("{} {}s", power_up.name(), seconds)
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn cleanup_power_up_hud(mut commands: Commands, hud: Query<Entity, With<OnPowerUpHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffs_wear_off() {
+        let mut buffs = Buffs::default();
+        assert!(!buffs.is_active(PowerUp::SlowSnake));
+        assert!(buffs.grant(PowerUp::SlowSnake, Duration::from_secs(2)));
+        assert!(buffs.grant(PowerUp::QuickAttack, Duration::from_secs(1)));
+
+        assert_eq!(buffs.tick(Duration::from_millis(500)), vec![]);
+        assert_eq!(
+            buffs.tick(Duration::from_millis(500)),
+            vec![PowerUp::QuickAttack]
+        );
+        assert!(buffs.is_active(PowerUp::SlowSnake));
+        assert_eq!(buffs.tick(Duration::from_secs(5)), vec![PowerUp::SlowSnake]);
+        assert_eq!(buffs, Buffs::default());
+    }
+
+    #[test]
+    fn every_power_up_has_its_own_layer() {
+        for power_up in PowerUp::ALL {
+            let others = PowerUp::ALL.into_iter().filter(|other| *other != power_up);
+            for other in others {
+                assert_ne!(power_up.layer().to_bits(), other.layer().to_bits());
+            }
+            assert_ne!(power_up.layer().to_bits(), Layer::Slayer.to_bits());
+            assert_eq!(PowerUp::ALL[power_up.index()], power_up);
+        }
+    }
+}
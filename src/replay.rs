@@ -0,0 +1,612 @@
+//! Recording matches and playing them back.
+//!
+//! A replay is the match setup, handicaps included, plus the [`TickInput`] of every simulated tick.
+//! Since the simulation is deterministic, feeding the same inputs back in reproduces the match,
+//! as long as the level has not been edited since; replays of changed levels are turned away.
+//!
+//! On disk, each tick's input is packed into two bytes and runs of identical ticks
+//! are run-length encoded, so a minute of play usually takes a few hundred bytes.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::handicap::Handicaps;
+use crate::level::{Level, LevelDirectory};
+use crate::simulation::{
+    in_state, SimulationClock, SimulationStage, SimulationSystem, SlayerInput, SnakeInput,
+    TickInput,
+};
+use crate::snake_grid::Direction;
+use crate::{AppState, MatchConfig, MatchMode, MatchSetup};
+
+/// Where the most recently finished match is saved.
+pub const LAST_REPLAY_PATH: &str = "replays/last_match.replay";
+
+const MAGIC: &[u8; 4] = b"UAR\0";
+const FORMAT_VERSION: u16 = 1;
+
+const MIN_PLAYBACK_SPEED: f64 = 0.25;
+const MAX_PLAYBACK_SPEED: f64 = 4.0;
+
+/// A recorded match.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Replay {
+    /// The seed the match was started with.
+    pub seed: u64,
+    /// The level the match was played on.
+    pub level: String,
+    /// The [`Level::fingerprint`] of the level as it was when the match was played.
+    pub level_fingerprint: u64,
+    /// The handicaps the match was played with.
+    pub handicaps: Handicaps,
+    pub mode: MatchMode,
+    /// The survival wave that was played; 0 and 1 both stand for the first.
+    pub wave: u32,
+    /// The inputs of every tick, starting with the first one.
+    pub inputs: Vec<TickInput>,
+}
+
+/// Something went wrong while reading or writing a replay.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The file is not a replay.
+    BadMagic,
+    /// The replay was written by an incompatible version of the game.
+    UnsupportedVersion(u16),
+    /// The file ended in the middle of the data.
+    Truncated,
+    /// A tick's input could not be decoded.
+    InvalidInput(u16),
+    /// The handicaps are out of range.
+    InvalidHandicaps,
+    /// The match mode is not one this version knows.
+    InvalidMode(u8),
+    /// The named level is missing or has changed since the replay was recorded.
+    LevelChanged(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::BadMagic => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay version {}", version)
+            }
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::InvalidInput(bits) => write!(f, "invalid tick input {:#06x}", bits),
+            ReplayError::InvalidHandicaps => write!(f, "invalid handicaps"),
+            ReplayError::InvalidMode(mode) => write!(f, "invalid match mode {}", mode),
+            ReplayError::LevelChanged(level) => {
+                write!(f, "level {} has changed since the replay was recorded", level)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+impl Replay {
+    /// Starts an empty recording for a match with the given setup, played on `level`.
+    pub fn new(config: &MatchConfig, level: &Level) -> Self {
+        Self {
+            seed: config.seed,
+            level: config.level.clone(),
+            level_fingerprint: level.fingerprint(),
+            handicaps: config.handicaps,
+            mode: config.mode,
+            wave: config.wave,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// The setup this replay was recorded with.
+    pub fn config(&self) -> MatchConfig {
+        MatchConfig {
+            seed: self.seed,
+            level: self.level.clone(),
+            handicaps: self.handicaps,
+            mode: self.mode,
+            wave: self.wave.max(1),
+        }
+    }
+
+    /// Serializes the replay into its compact binary form.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::replay::Replay;
+    /// # use unfair_advantage_lib::simulation::TickInput;
+    /// let replay = Replay {
+    ///     inputs: vec![TickInput::default(); 600],
+    ///     ..Default::default()
+    /// };
+    /// let bytes = replay.to_bytes();
+    /// assert!(bytes.len() < 50);
+    /// assert_eq!(Replay::from_bytes(&bytes).unwrap(), replay);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&(self.level.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.level.as_bytes());
+        bytes.extend_from_slice(&self.level_fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&self.handicaps.to_bytes());
+        bytes.push(self.mode.to_byte());
+        bytes.extend_from_slice(&self.wave.to_le_bytes());
+        bytes.extend_from_slice(&(self.inputs.len() as u32).to_le_bytes());
+
+        let mut ticks = self
+            .inputs
+            .iter()
+            .map(|input| encode_input(*input))
+            .peekable();
+        while let Some(bits) = ticks.next() {
+            let mut run: u8 = 1;
+            while run < u8::MAX && ticks.peek() == Some(&bits) {
+                ticks.next();
+                run += 1;
+            }
+            bytes.extend_from_slice(&bits.to_le_bytes());
+            bytes.push(run);
+        }
+        bytes
+    }
+
+    /// Reads a replay from its compact binary form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ByteReader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(reader.array()?);
+        let level_len = u16::from_le_bytes(reader.array()?) as usize;
+        let level = String::from_utf8_lossy(reader.take(level_len)?).into_owned();
+        let level_fingerprint = u64::from_le_bytes(reader.array()?);
+        let handicaps =
+            Handicaps::from_bytes(reader.array()?).ok_or(ReplayError::InvalidHandicaps)?;
+        let [mode] = reader.array()?;
+        let mode = MatchMode::from_byte(mode).ok_or(ReplayError::InvalidMode(mode))?;
+        let wave = u32::from_le_bytes(reader.array()?);
+        let tick_count = u32::from_le_bytes(reader.array()?) as usize;
+        // Each run of up to 255 ticks takes 3 bytes
+        if tick_count > reader.0.len() / 3 * u8::MAX as usize {
+            return Err(ReplayError::Truncated);
+        }
+
+        let mut inputs = Vec::with_capacity(tick_count);
+        while inputs.len() < tick_count {
+            let bits = u16::from_le_bytes(reader.array()?);
+            let [run] = reader.array()?;
+            let input = decode_input(bits)?;
+            inputs.resize(inputs.len() + run as usize, input);
+        }
+        inputs.truncate(tick_count);
+
+        Ok(Self {
+            seed,
+            level,
+            level_fingerprint,
+            handicaps,
+            mode,
+            wave,
+            inputs,
+        })
+    }
+
+    /// Writes the replay to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the replay stored at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Makes sure the level the replay was recorded on is still the same, as found in `directory`.
+    pub fn check_level(&self, directory: &LevelDirectory) -> Result<(), ReplayError> {
+        match Level::named(&self.level, directory) {
+            Ok(level) if level.fingerprint() == self.level_fingerprint => Ok(()),
+            _ => Err(ReplayError::LevelChanged(self.level.clone())),
+        }
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.0.len() < len {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+// Bits 0 to 4 hold the slayer's buttons, bits 5 to 7 the snake's turn,
+// bits 8 to 10 the snake's ability buttons and bits 11 to 15 the second slayer's buttons
+pub(crate) fn encode_input(input: TickInput) -> u16 {
+    let snake = input.snake;
+    let turn = match input.snake.turn {
+        None => 0,
+        Some(Direction::Left) => 1,
+        Some(Direction::Up) => 2,
+        Some(Direction::Right) => 3,
+        Some(Direction::Down) => 4,
+    };
+    encode_slayer(input.slayer)
+        | (turn << 5)
+        | ((snake.lunge as u16) << 8)
+        | ((snake.armor as u16) << 9)
+        | ((snake.burst as u16) << 10)
+        | (encode_slayer(input.second_slayer) << 11)
+}
+
+fn encode_slayer(slayer: SlayerInput) -> u16 {
+    (slayer.left as u16)
+        | ((slayer.right as u16) << 1)
+        | ((slayer.up as u16) << 2)
+        | ((slayer.down as u16) << 3)
+        | ((slayer.attack as u16) << 4)
+}
+
+fn decode_slayer(bits: u16) -> SlayerInput {
+    SlayerInput {
+        left: bits & 1 != 0,
+        right: bits & (1 << 1) != 0,
+        up: bits & (1 << 2) != 0,
+        down: bits & (1 << 3) != 0,
+        attack: bits & (1 << 4) != 0,
+    }
+}
+
+pub(crate) fn decode_input(bits: u16) -> Result<TickInput, ReplayError> {
+    let turn = match (bits >> 5) & 0b111 {
+        0 => None,
+        1 => Some(Direction::Left),
+        2 => Some(Direction::Up),
+        3 => Some(Direction::Right),
+        4 => Some(Direction::Down),
+        _ => return Err(ReplayError::InvalidInput(bits)),
+    };
+    Ok(TickInput {
+        slayer: decode_slayer(bits),
+        snake: SnakeInput {
+            turn,
+            lunge: bits & (1 << 8) != 0,
+            armor: bits & (1 << 9) != 0,
+            burst: bits & (1 << 10) != 0,
+        },
+        second_slayer: decode_slayer(bits >> 11),
+    })
+}
+
+/// The match that is currently being recorded, if any.
+#[derive(Default)]
+pub struct ReplayRecorder(pub Option<Replay>);
+
+/// Where finished matches are saved; `None` turns saving off.
+pub struct ReplaySavePath(pub Option<PathBuf>);
+
+impl Default for ReplaySavePath {
+    fn default() -> Self {
+        Self(Some(PathBuf::from(LAST_REPLAY_PATH)))
+    }
+}
+
+/// Present while a replay is being watched instead of a live match.
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    finished: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            finished: false,
+        }
+    }
+
+    /// Have all recorded ticks been played?
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[derive(Component)]
+struct OnReplayScreen;
+
+/// Records every match, and plays back replays in place of live input.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .init_resource::<ReplaySavePath>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(start_recording.after(MatchSetup::LoadLevel))
+                    .with_system(setup_replay_hud),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame)
+                    .with_system(replay_playback_controls)
+                    .with_system(update_replay_hud),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(finish_replay),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Replay)
+                    .after(SimulationSystem::Input)
+                    .after(SimulationSystem::Netplay)
+                    .before(SimulationSystem::Gameplay)
+                    .with_system(record_tick_input)
+                    .with_system(play_back_tick_input),
+            );
+    }
+}
+
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    playback: Option<Res<ReplayPlayback>>,
+    config: Res<MatchConfig>,
+    level: Res<Level>,
+) {
+    recorder.0 = if playback.is_some() {
+        None
+    } else {
+        Some(Replay::new(&config, &level))
+    };
+}
+
+fn record_tick_input(mut recorder: ResMut<ReplayRecorder>, tick_input: Res<TickInput>) {
+    if let Some(replay) = &mut recorder.0 {
+        replay.inputs.push(*tick_input);
+    }
+}
+
+fn play_back_tick_input(
+    playback: Option<ResMut<ReplayPlayback>>,
+    mut clock: ResMut<SimulationClock>,
+    mut tick_input: ResMut<TickInput>,
+) {
+    if let Some(mut playback) = playback {
+        let index = clock.tick() as usize - 1;
+        match playback.replay.inputs.get(index) {
+            Some(input) => *tick_input = *input,
+            None => {
+                *tick_input = TickInput::default();
+                if !playback.finished {
+                    playback.finished = true;
+                    clock.set_paused(true);
+                }
+            }
+        }
+    }
+}
+
+fn finish_replay(
+    mut commands: Commands,
+    mut recorder: ResMut<ReplayRecorder>,
+    save_path: Res<ReplaySavePath>,
+    hud: Query<Entity, With<OnReplayScreen>>,
+) {
+    if let (Some(replay), Some(path)) = (recorder.0.take(), &save_path.0) {
+        if let Err(error) = replay.save(path) {
+            error!("Could not save replay to {}: {}", path.display(), error);
+        }
+    }
+    commands.remove_resource::<ReplayPlayback>();
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Space pauses, `.` steps a single tick while paused, `-` and `=` change the speed.
+fn replay_playback_controls(
+    input: Res<Input<KeyCode>>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut clock: ResMut<SimulationClock>,
+) {
+    if playback.is_none() {
+        return;
+    }
+    if input.just_pressed(KeyCode::Space) {
+        let paused = clock.is_paused();
+        clock.set_paused(!paused);
+    }
+    if input.just_pressed(KeyCode::Period) && clock.is_paused() {
+        clock.step_once();
+    }
+    if input.just_pressed(KeyCode::Equals) {
+        let speed = (clock.time_scale() * 2.0).min(MAX_PLAYBACK_SPEED);
+        clock.set_time_scale(speed);
+    }
+    if input.just_pressed(KeyCode::Minus) {
+        let speed = (clock.time_scale() / 2.0).max(MIN_PLAYBACK_SPEED);
+        clock.set_time_scale(speed);
+    }
+}
+
+fn setup_replay_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if playback.is_none() {
+        return;
+    }
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OnReplayScreen);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/GoMono-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(OnReplayScreen);
+}
+
+fn update_replay_hud(
+    playback: Option<Res<ReplayPlayback>>,
+    clock: Res<SimulationClock>,
+    mut hud: Query<&mut Text, With<OnReplayScreen>>,
+) {
+    if let Some(playback) = playback {
+        let status = if playback.is_finished() {
+            "finished"
+        } else if clock.is_paused() {
+            "paused"
+        } else {
+            "playing"
+        };
+        for mut text in hud.iter_mut() {
+            text.sections[0].value = format!(
+                "REPLAY {} x{} - tick {}/{}",
+                status,
+                clock.time_scale(),
+                clock.tick(),
+                playback.replay.inputs.len()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_tick_input_round_trips() {
+        let turns = [
+            None,
+            Some(Direction::Left),
+            Some(Direction::Up),
+            Some(Direction::Right),
+            Some(Direction::Down),
+        ];
+        let mut replay = Replay {
+            seed: 42,
+            level: "test".to_string(),
+            level_fingerprint: 0x1234_5678_9abc_def0,
+            handicaps: Handicaps {
+                extra_lives: 1,
+                ..Default::default()
+            },
+            mode: MatchMode::CoOp,
+            wave: 3,
+            inputs: Vec::new(),
+        };
+        for buttons in 0..8192u16 {
+            for turn in turns {
+                replay.inputs.push(TickInput {
+                    slayer: SlayerInput {
+                        left: buttons & 1 != 0,
+                        right: buttons & (1 << 1) != 0,
+                        up: buttons & (1 << 2) != 0,
+                        down: buttons & (1 << 3) != 0,
+                        attack: buttons & (1 << 4) != 0,
+                    },
+                    snake: SnakeInput {
+                        turn,
+                        lunge: buttons & (1 << 5) != 0,
+                        armor: buttons & (1 << 6) != 0,
+                        burst: buttons & (1 << 7) != 0,
+                    },
+                    second_slayer: decode_slayer(buttons >> 8),
+                });
+            }
+        }
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()).unwrap(), replay);
+    }
+
+    #[test]
+    fn truncated_replay_is_rejected() {
+        let replay = Replay {
+            inputs: vec![TickInput::default(); 10],
+            ..Default::default()
+        };
+        let bytes = replay.to_bytes();
+        assert!(matches!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::Truncated)
+        ));
+
+        // A tick count the file could never hold is turned away before anything is allocated
+        let mut bytes = Replay::default().to_bytes();
+        let count = bytes.len() - 4;
+        bytes[count..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Replay::from_bytes(&bytes),
+            Err(ReplayError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn replays_of_changed_levels_are_rejected() {
+        let config = MatchConfig {
+            level: "pillars".to_string(),
+            ..Default::default()
+        };
+        let directory = LevelDirectory::default();
+        let pillars = Level::built_in("pillars").unwrap();
+        assert!(Replay::new(&config, &pillars).check_level(&directory).is_ok());
+        assert!(matches!(
+            Replay::new(&config, &Level::default()).check_level(&directory),
+            Err(ReplayError::LevelChanged(level)) if level == "pillars"
+        ));
+    }
+}
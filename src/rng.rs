@@ -0,0 +1,166 @@
+//! Seeded randomness for everything that happens in a match.
+//!
+//! All gameplay randomness is drawn from the [`GameRng`] resource, which is reseeded from
+//! [`MatchConfig::seed`] whenever a match starts. Together with the recorded inputs this
+//! makes every match reproducible: the same seed and inputs always play out the same way.
+//! Nothing in the game may draw on an unseeded source; [`fresh_seed`] only picks the seed
+//! a match starts with, and that seed is shown to the player.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use crate::{AppState, MatchConfig, MatchMode, MatchSetup};
+
+/// A small, fast generator whose whole state is a single number, so it is easy to save.
+///
+/// This is SplitMix64, which is good enough for games and takes any seed, zero included.
+///
+/// # Examples
+/// ```
+/// # use unfair_advantage_lib::rng::GameRng;
+/// let mut rng = GameRng::new(42);
+/// let first = rng.next_u64();
+/// assert_eq!(GameRng::new(42).next_u64(), first);
+///
+/// let resumed = GameRng::from_state(rng.state());
+/// assert_eq!(resumed, rng);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// A generator for `stream` that is independent of the main one for the same `seed`,
+    /// for randomness that must not disturb the simulation, like particles.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut mixer = Self::new(stream);
+        Self::new(seed ^ mixer.next_u64())
+    }
+
+    /// Carries on from a state saved with [`GameRng::state`].
+    pub fn from_state(state: u64) -> Self {
+        Self { state }
+    }
+
+    /// Everything needed to carry on drawing the same numbers later.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to but not including 1.
+    pub fn fraction(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A number from `min` up to `max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.fraction()
+    }
+
+    /// A whole number from 0 up to but not including `n`, which must not be 0.
+    pub fn below(&mut self, n: u32) -> u32 {
+        // Multiplying keeps the bias negligible without a retry loop
+        (((self.next_u64() >> 32) * n as u64) >> 32) as u32
+    }
+
+    /// `true` with a chance of `probability`, from 0 to 1.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.fraction() < probability
+    }
+
+    /// One of `items`, or `None` if there are none.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            items.get(self.below(items.len() as u32) as usize)
+        }
+    }
+}
+
+/// A new seed taken from the clock, for matches the player has not picked a seed for.
+pub fn fresh_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64);
+    GameRng::new(nanos).next_u64()
+}
+
+/// Keeps the match's [`GameRng`] and seeds it as each match starts.
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRng>().add_system_set(
+            SystemSet::on_enter(AppState::InOnePlayerGame)
+                .with_system(seed_match_rng.label(MatchSetup::SeedRng)),
+        );
+    }
+}
+
+fn seed_match_rng(config: Res<MatchConfig>, mut rng: ResMut<GameRng>) {
+    *rng = match config.mode {
+        // Each wave of a campaign plays out differently from the same seed
+        MatchMode::Survival => GameRng::with_stream(config.seed, config.wave as u64),
+        // Every run plays out the same, so that time attack runs can be compared
+        MatchMode::TimeAttack | MatchMode::Tutorial => GameRng::default(),
+        _ => GameRng::new(config.seed),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_and_streams_give_different_numbers() {
+        let draw = |mut rng: GameRng| (0..8).map(|_| rng.next_u64()).collect::<Vec<_>>();
+        assert_eq!(draw(GameRng::new(1)), draw(GameRng::new(1)));
+        assert_ne!(draw(GameRng::new(1)), draw(GameRng::new(2)));
+        assert_ne!(draw(GameRng::new(1)), draw(GameRng::with_stream(1, 1)));
+        assert_ne!(
+            draw(GameRng::with_stream(1, 1)),
+            draw(GameRng::with_stream(1, 2))
+        );
+    }
+
+    #[test]
+    fn draws_stay_in_bounds() {
+        let mut rng = GameRng::new(7);
+        let mut seen = [false; 5];
+        for _ in 0..1000 {
+            let fraction = rng.fraction();
+            assert!((0.0..1.0).contains(&fraction));
+            let value = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+            seen[rng.below(5) as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+        assert_eq!(rng.pick::<u8>(&[]), None);
+        assert_eq!(rng.pick(&[3]), Some(&3));
+    }
+}
@@ -0,0 +1,1065 @@
+//! Saving a match part of the way through and carrying on with it later.
+//!
+//! A save holds everything the simulation needs to carry on exactly where it left off:
+//! the match setup, the tick count and any hit-stop in progress, timers and random numbers, every snake down to where each segment
+//! was before its last move along with the snake player's abilities, each slayer's body, jumps, sword,
+//! power-ups, lives and score, the pickups waiting in the arena, and the snake dens. Restoring a save and feeding
+//! in the same inputs plays out just like the original match would have.
+//!
+//! Saves use a small versioned binary format. Matches are saved from the pause menu and
+//! continued from the main menu. Time attack runs and the tutorial cannot be saved.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::co_op::SlayerScores;
+use crate::handicap::{ExtraLives, Handicaps};
+use crate::power_ups::{spawn_pickup, Buffs, Pickup, PickupTimer, PowerUp};
+use crate::replay::ReplayRecorder;
+use crate::rng::GameRng;
+use crate::simulation::{SimulationClock, TickCollisions};
+use crate::snake_abilities::SnakeAbilities;
+use crate::snake_ai::{AiProfile, SnakeAi};
+use crate::snake_den::{spawn_den, DenSnake, SnakeDen};
+use crate::snake_grid::{Direction, Position, Snake};
+use crate::{
+    is_underfoot, spawn_snake_entities, AppState, AttackCooldown, Facing, Invulnerable, Jumps,
+    LastTailPosition, MatchConfig, MatchMode, PlayerSnake, PreviousPosition, SlayerPlayer,
+    SnakeSegment, SnakeSegments, SnakeTimer, SwordDirection,
+};
+
+/// Where the match is saved by default.
+pub const SAVE_GAME_PATH: &str = "saves/match.save";
+
+const MAGIC: &[u8; 4] = b"UAS\0";
+const FORMAT_VERSION: u16 = 1;
+
+/// A snake as it was when the match was saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSnake {
+    pub snake: Snake,
+    /// Is this the snake steered by the snake player?
+    pub player: bool,
+    /// How the snake steers itself, if it does.
+    pub ai: Option<AiProfile>,
+    /// Was this snake let out by a den?
+    pub from_den: bool,
+    /// Where each segment was before its last move.
+    pub previous: Vec<Position>,
+    /// The direction each segment is facing.
+    pub directions: Vec<Direction>,
+    /// The abilities of the snake player, if this is their snake.
+    pub abilities: Option<SnakeAbilities>,
+}
+
+/// A slayer as they were when the match was saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSlayer {
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    /// How many solid bodies the slayer is touching.
+    pub ground_contacts: u32,
+    /// Whether the slayer has used up their jump in mid-air.
+    pub jumped_in_air: bool,
+    /// Whether up was held on the last tick, so that holding it does not jump again.
+    pub holding_up: bool,
+    pub facing: Direction,
+    /// The direction of the sword swing in progress, if any.
+    pub sword: Option<Direction>,
+    /// How long ago the last attack started, as far as its cooldown is concerned.
+    pub attack_cooldown: Duration,
+    pub invulnerable: bool,
+    pub buffs: Buffs,
+    pub extra_lives: u8,
+}
+
+/// Everything needed to carry on with a match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedMatch {
+    pub config: MatchConfig,
+    pub tick: u64,
+    /// How many more frames the hit-stop in progress holds the simulation still for.
+    pub hit_stop_frames: u32,
+    pub rng: GameRng,
+    pub snake_timer_elapsed: Duration,
+    pub snake_timer_duration: Duration,
+    pub last_tail: Option<Position>,
+    pub snakes: Vec<SavedSnake>,
+    /// The slayer, unless they had already been chomped.
+    pub slayer: Option<SavedSlayer>,
+    /// The second slayer of a co-op match, unless they had already been chomped.
+    pub second_slayer: Option<SavedSlayer>,
+    /// The score of each slayer in a co-op match.
+    pub scores: SlayerScores,
+    /// The pickups waiting in the arena, and where they are.
+    pub pickups: Vec<(PowerUp, Position)>,
+    pub pickup_timer_elapsed: Duration,
+    /// The dens still standing, and where they are.
+    pub dens: Vec<(Position, SnakeDen)>,
+}
+
+/// Something went wrong while reading or writing a save.
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file is not a save.
+    BadMagic,
+    /// The save was written by an incompatible version of the game.
+    UnsupportedVersion(u16),
+    /// The file ended in the middle of the data.
+    Truncated,
+    /// A value in the file makes no sense.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "{}", error),
+            SaveError::BadMagic => write!(f, "not a save file"),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save version {}", version)
+            }
+            SaveError::Truncated => write!(f, "save file is truncated"),
+            SaveError::Invalid(what) => write!(f, "invalid {} in save file", what),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(error: io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+fn encode_direction(direction: Direction) -> u8 {
+    match direction {
+        Direction::Left => 0,
+        Direction::Up => 1,
+        Direction::Right => 2,
+        Direction::Down => 3,
+    }
+}
+
+fn decode_direction(byte: u8) -> Result<Direction, SaveError> {
+    match byte {
+        0 => Ok(Direction::Left),
+        1 => Ok(Direction::Up),
+        2 => Ok(Direction::Right),
+        3 => Ok(Direction::Down),
+        _ => Err(SaveError::Invalid("direction")),
+    }
+}
+
+fn encode_power_up(power_up: PowerUp) -> u8 {
+    PowerUp::ALL
+        .iter()
+        .position(|other| *other == power_up)
+        .unwrap() as u8
+}
+
+fn decode_power_up(byte: u8) -> Result<PowerUp, SaveError> {
+    PowerUp::ALL
+        .get(byte as usize)
+        .copied()
+        .ok_or(SaveError::Invalid("power-up"))
+}
+
+#[derive(Default)]
+struct ByteWriter(Vec<u8>);
+
+impl ByteWriter {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    fn duration(&mut self, value: Duration) {
+        self.u64(value.as_nanos() as u64);
+    }
+
+    fn position(&mut self, value: Position) {
+        self.0.extend_from_slice(&value.x.to_le_bytes());
+        self.0.extend_from_slice(&value.y.to_le_bytes());
+    }
+
+    fn direction(&mut self, value: Direction) {
+        self.u8(encode_direction(value));
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn slayer(&mut self, slayer: Option<&SavedSlayer>) {
+        self.u8(slayer.is_some() as u8);
+        if let Some(slayer) = slayer {
+            self.vec3(slayer.translation);
+            self.vec3(slayer.velocity);
+            self.u32(slayer.ground_contacts);
+            self.u8(slayer.jumped_in_air as u8);
+            self.u8(slayer.holding_up as u8);
+            self.direction(slayer.facing);
+            self.u8(slayer.sword.map_or(u8::MAX, encode_direction));
+            self.duration(slayer.attack_cooldown);
+            self.u8(slayer.invulnerable as u8);
+            for power_up in PowerUp::ALL {
+                self.duration(slayer.buffs.remaining(power_up));
+            }
+            self.u8(slayer.extra_lives);
+        }
+    }
+
+    fn abilities(&mut self, abilities: Option<&SnakeAbilities>) {
+        self.u8(abilities.is_some() as u8);
+        if let Some(abilities) = abilities {
+            for cooldown in abilities.cooldowns {
+                self.duration(cooldown);
+            }
+            self.duration(abilities.armor);
+            self.duration(abilities.burst);
+            self.u8(abilities.lunge_pending as u8);
+            self.u8(abilities.recovering as u8);
+        }
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveError> {
+        if self.0.len() < len {
+            return Err(SaveError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveError::Invalid("flag")),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, SaveError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn duration(&mut self) -> Result<Duration, SaveError> {
+        Ok(Duration::from_nanos(self.u64()?))
+    }
+
+    fn position(&mut self) -> Result<Position, SaveError> {
+        Ok(Position::new(
+            i32::from_le_bytes(self.array()?),
+            i32::from_le_bytes(self.array()?),
+        ))
+    }
+
+    fn direction(&mut self) -> Result<Direction, SaveError> {
+        decode_direction(self.u8()?)
+    }
+
+    /// A count of things that each take at least `min_size` more bytes.
+    fn count(&mut self, min_size: usize) -> Result<usize, SaveError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(min_size) > self.0.len() {
+            return Err(SaveError::Truncated);
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self) -> Result<String, SaveError> {
+        let len = self.count(1)?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| SaveError::Invalid("text"))
+    }
+
+    /// A slayer, if there is one.
+    fn slayer(&mut self) -> Result<Option<SavedSlayer>, SaveError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        Ok(Some(SavedSlayer {
+            translation: self.vec3()?,
+            velocity: self.vec3()?,
+            ground_contacts: self.u32()?,
+            jumped_in_air: self.bool()?,
+            holding_up: self.bool()?,
+            facing: self.direction()?,
+            sword: match self.u8()? {
+                u8::MAX => None,
+                byte => Some(decode_direction(byte)?),
+            },
+            attack_cooldown: self.duration()?,
+            invulnerable: self.bool()?,
+            buffs: {
+                let mut buffs = Buffs::default();
+                for power_up in PowerUp::ALL {
+                    buffs.grant(power_up, self.duration()?);
+                }
+                buffs
+            },
+            extra_lives: self.u8()?,
+        }))
+    }
+
+    /// The snake player's abilities, if they are saved.
+    fn abilities(&mut self) -> Result<Option<SnakeAbilities>, SaveError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        let mut abilities = SnakeAbilities::default();
+        for cooldown in abilities.cooldowns.iter_mut() {
+            *cooldown = self.duration()?;
+        }
+        abilities.armor = self.duration()?;
+        abilities.burst = self.duration()?;
+        abilities.lunge_pending = self.bool()?;
+        abilities.recovering = self.bool()?;
+        Ok(Some(abilities))
+    }
+}
+
+impl SavedMatch {
+    /// Serializes the save into its binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::default();
+        writer.0.extend_from_slice(MAGIC);
+        writer.0.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        writer.u64(self.config.seed);
+        writer.string(&self.config.level);
+        writer
+            .0
+            .extend_from_slice(&self.config.handicaps.to_bytes());
+        writer.u8(self.config.mode.to_byte());
+        writer.u32(self.config.wave);
+        writer.u64(self.tick);
+        writer.u32(self.hit_stop_frames);
+        writer.u64(self.rng.state());
+        writer.duration(self.snake_timer_elapsed);
+        writer.duration(self.snake_timer_duration);
+        writer.u8(self.last_tail.is_some() as u8);
+        if let Some(last_tail) = self.last_tail {
+            writer.position(last_tail);
+        }
+
+        writer.u32(self.snakes.len() as u32);
+        for saved in &self.snakes {
+            writer.u8(saved.player as u8);
+            writer.u8(saved.ai.map_or(0, |profile| profile.to_byte() + 1));
+            writer.u8(saved.from_den as u8);
+            writer.direction(saved.snake.direction());
+            writer.direction(saved.snake.last_moved());
+            writer.abilities(saved.abilities.as_ref());
+            writer.u32(saved.snake.len() as u32);
+            let segments = saved
+                .snake
+                .body()
+                .iter()
+                .zip(&saved.previous)
+                .zip(&saved.directions);
+            for ((position, previous), direction) in segments {
+                writer.position(*position);
+                writer.position(*previous);
+                writer.direction(*direction);
+            }
+        }
+
+        writer.slayer(self.slayer.as_ref());
+        writer.slayer(self.second_slayer.as_ref());
+        for score in self.scores.0 {
+            writer.u32(score);
+        }
+
+        writer.duration(self.pickup_timer_elapsed);
+        writer.u32(self.pickups.len() as u32);
+        for (power_up, position) in &self.pickups {
+            writer.u8(encode_power_up(*power_up));
+            writer.position(*position);
+        }
+
+        writer.u32(self.dens.len() as u32);
+        for (position, den) in &self.dens {
+            writer.position(*position);
+            writer.u8(den.health);
+            writer.u32(den.spawned);
+            writer.duration(den.until_next);
+        }
+        writer.0
+    }
+
+    /// Reads a save from its binary form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = ByteReader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SaveError::BadMagic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let config = MatchConfig {
+            seed: reader.u64()?,
+            level: reader.string()?,
+            handicaps: Handicaps::from_bytes(reader.array()?)
+                .ok_or(SaveError::Invalid("handicaps"))?,
+            mode: MatchMode::from_byte(reader.u8()?).ok_or(SaveError::Invalid("mode"))?,
+            wave: reader.u32()?,
+        };
+        let tick = reader.u64()?;
+        let hit_stop_frames = reader.u32()?;
+        let rng = GameRng::from_state(reader.u64()?);
+        let snake_timer_elapsed = reader.duration()?;
+        let snake_timer_duration = reader.duration()?;
+        if snake_timer_duration.is_zero() {
+            return Err(SaveError::Invalid("snake timer"));
+        }
+        let last_tail = if reader.bool()? {
+            Some(reader.position()?)
+        } else {
+            None
+        };
+
+        // Each snake takes at least 10 bytes, and each segment 17
+        let snake_count = reader.count(10)?;
+        let mut snakes = Vec::with_capacity(snake_count);
+        for _ in 0..snake_count {
+            let player = reader.bool()?;
+            let ai = match reader.u8()? {
+                0 => None,
+                byte => {
+                    Some(AiProfile::from_byte(byte - 1).ok_or(SaveError::Invalid("AI profile"))?)
+                }
+            };
+            let from_den = reader.bool()?;
+            let direction = reader.direction()?;
+            let last_moved = reader.direction()?;
+            let abilities = reader.abilities()?;
+            let len = reader.count(17)?;
+            if len == 0 {
+                return Err(SaveError::Invalid("snake"));
+            }
+            let mut body = Vec::with_capacity(len);
+            let mut previous = Vec::with_capacity(len);
+            let mut directions = Vec::with_capacity(len);
+            for _ in 0..len {
+                body.push(reader.position()?);
+                previous.push(reader.position()?);
+                directions.push(reader.direction()?);
+            }
+            snakes.push(SavedSnake {
+                snake: Snake::resume(body, direction, last_moved),
+                player,
+                ai,
+                from_den,
+                previous,
+                directions,
+                abilities,
+            });
+        }
+
+        let slayer = reader.slayer()?;
+        let second_slayer = reader.slayer()?;
+        let mut scores = SlayerScores::default();
+        for score in scores.0.iter_mut() {
+            *score = reader.u32()?;
+        }
+
+        let pickup_timer_elapsed = reader.duration()?;
+        // Each pickup takes 9 bytes
+        let count = reader.count(9)?;
+        let mut pickups = Vec::with_capacity(count);
+        for _ in 0..count {
+            pickups.push((decode_power_up(reader.u8()?)?, reader.position()?));
+        }
+
+        // Each den takes 21 bytes
+        let count = reader.count(21)?;
+        let mut dens = Vec::with_capacity(count);
+        for _ in 0..count {
+            let position = reader.position()?;
+            let den = SnakeDen {
+                health: reader.u8()?,
+                spawned: reader.u32()?,
+                until_next: reader.duration()?,
+            };
+            if den.health == 0 {
+                return Err(SaveError::Invalid("den"));
+            }
+            dens.push((position, den));
+        }
+
+        Ok(Self {
+            config,
+            tick,
+            hit_stop_frames,
+            rng,
+            snake_timer_elapsed,
+            snake_timer_duration,
+            last_tail,
+            snakes,
+            slayer,
+            second_slayer,
+            scores,
+            pickups,
+            pickup_timer_elapsed,
+            dens,
+        })
+    }
+
+    /// Writes the save to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the save stored at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Captures the match that is running in `world`.
+    pub fn capture(world: &mut World) -> Self {
+        let config = world.get_resource::<MatchConfig>().unwrap().clone();
+        let clock = world.get_resource::<SimulationClock>().unwrap();
+        let tick = clock.tick();
+        let hit_stop_frames = clock.hit_stop_frames();
+        let rng = world.get_resource::<GameRng>().unwrap().clone();
+        let snake_timer = &world.get_resource::<SnakeTimer>().unwrap().0;
+        let snake_timer_elapsed = snake_timer.elapsed();
+        let snake_timer_duration = snake_timer.duration();
+        let last_tail = world.get_resource::<LastTailPosition>().unwrap().0;
+
+        // Kept in the order the snakes move in, which decides who wins a contested cell
+        let mut heads = world.query::<(
+            &Snake,
+            &SnakeSegments,
+            Option<&PlayerSnake>,
+            Option<&SnakeAi>,
+            Option<&DenSnake>,
+            Option<&SnakeAbilities>,
+        )>();
+        let mut segments = world.query::<(&PreviousPosition, Option<&SnakeSegment>)>();
+        let snakes = heads
+            .iter(world)
+            .map(|(snake, entities, player, ai, from_den, abilities)| {
+                let (previous, directions) = entities
+                    .0
+                    .iter()
+                    .map(|entity| {
+                        let (previous, segment) = segments.get(world, *entity).unwrap();
+                        let direction =
+                            segment.map_or(snake.direction(), |segment| segment.direction);
+                        (previous.0, direction)
+                    })
+                    .unzip();
+                SavedSnake {
+                    snake: snake.clone(),
+                    player: player.is_some(),
+                    ai: ai.map(|ai| ai.profile),
+                    from_den: from_den.is_some(),
+                    previous,
+                    directions,
+                    abilities: abilities.cloned(),
+                }
+            })
+            .collect();
+
+        // Contacts from the last physics step are only counted on the next tick, so count them now
+        let mut bodies = world.query::<(&RigidBody, &Transform, &CollisionShape)>();
+        let mut transforms = world.query::<&Transform>();
+        let mut touches = Vec::new();
+        for event in &world.get_resource::<TickCollisions>().unwrap().0 {
+            let (entity_1, entity_2) = event.rigid_body_entities();
+            for (entity, other) in [(entity_1, entity_2), (entity_2, entity_1)] {
+                if let (Ok((RigidBody::Static, transform, shape)), Ok(slayer)) =
+                    (bodies.get(world, other), transforms.get(world, entity))
+                {
+                    let underfoot = is_underfoot(slayer, transform, shape);
+                    touches.push((entity, other, event.is_started(), underfoot));
+                }
+            }
+        }
+
+        let mut slayers = world.query::<(
+            Entity,
+            &SlayerPlayer,
+            &Transform,
+            &Velocity,
+            &Jumps,
+            &Facing,
+            &SwordDirection,
+            &AttackCooldown,
+            Option<&Invulnerable>,
+            &Buffs,
+            &ExtraLives,
+        )>();
+        let mut capture_slayer = |player: usize| {
+            slayers
+                .iter(world)
+                .find(|(_, slayer, ..)| slayer.0 == player)
+                .map(
+                    |(
+                        entity,
+                        _,
+                        transform,
+                        velocity,
+                        jumps,
+                        facing,
+                        sword,
+                        cooldown,
+                        invulnerable,
+                        buffs,
+                        extra_lives,
+                    )| {
+                        let mut jumps = jumps.clone();
+                        for (_, body, started, underfoot) in
+                            touches.iter().filter(|(slayer, ..)| *slayer == entity)
+                        {
+                            jumps.touch(*body, *started, *underfoot);
+                        }
+                        SavedSlayer {
+                            translation: transform.translation,
+                            velocity: velocity.linear,
+                            ground_contacts: jumps.ground_contacts,
+                            jumped_in_air: jumps.jumped_in_air,
+                            holding_up: jumps.holding_up,
+                            facing: match facing {
+                                Facing::Left => Direction::Left,
+                                Facing::Right => Direction::Right,
+                            },
+                            sword: match sword {
+                                SwordDirection::Up => Some(Direction::Up),
+                                SwordDirection::Down => Some(Direction::Down),
+                                SwordDirection::Left => Some(Direction::Left),
+                                SwordDirection::Right => Some(Direction::Right),
+                                SwordDirection::NotAttacking => None,
+                            },
+                            attack_cooldown: cooldown.0.elapsed(),
+                            invulnerable: invulnerable.is_some(),
+                            buffs: buffs.clone(),
+                            extra_lives: extra_lives.0,
+                        }
+                    },
+                )
+        };
+        let slayer = capture_slayer(0);
+        let second_slayer = capture_slayer(1);
+        let scores = *world.get_resource::<SlayerScores>().unwrap();
+
+        let mut pickups: Vec<(PowerUp, Position)> = world
+            .query::<(&Pickup, &Position)>()
+            .iter(world)
+            .map(|(pickup, position)| (pickup.0, *position))
+            .collect();
+        // Entities come back in a different order once restored
+        pickups.sort_by_key(|(_, position)| (position.x, position.y));
+        let pickup_timer_elapsed = world.get_resource::<PickupTimer>().unwrap().0.elapsed();
+        let mut dens: Vec<(Position, SnakeDen)> = world
+            .query::<(&Position, &SnakeDen)>()
+            .iter(world)
+            .map(|(position, den)| (*position, den.clone()))
+            .collect();
+        dens.sort_by_key(|(position, _)| (position.x, position.y));
+
+        Self {
+            config,
+            tick,
+            hit_stop_frames,
+            rng,
+            snake_timer_elapsed,
+            snake_timer_duration,
+            last_tail,
+            snakes,
+            slayer,
+            second_slayer,
+            scores,
+            pickups,
+            pickup_timer_elapsed,
+            dens,
+        }
+    }
+
+    /// Puts the match in `world`, which has just been set up, into the saved state.
+    pub fn restore(&self, world: &mut World) {
+        let mut clock = world.get_resource_mut::<SimulationClock>().unwrap();
+        clock.resume_at(self.tick);
+        clock.hit_stop(self.hit_stop_frames);
+        // Already counted in the saved slayers
+        world.insert_resource(TickCollisions::default());
+        world.insert_resource(self.rng.clone());
+        let mut snake_timer = world.get_resource_mut::<SnakeTimer>().unwrap();
+        snake_timer.0.set_duration(self.snake_timer_duration);
+        snake_timer.0.set_elapsed(self.snake_timer_elapsed);
+        world.get_resource_mut::<LastTailPosition>().unwrap().0 = self.last_tail;
+        world
+            .get_resource_mut::<PickupTimer>()
+            .unwrap()
+            .0
+            .set_elapsed(self.pickup_timer_elapsed);
+        *world.get_resource_mut::<SlayerScores>().unwrap() = self.scores;
+        // A resumed match cannot be replayed from its seed alone
+        world.get_resource_mut::<ReplayRecorder>().unwrap().0 = None;
+
+        // Swap the snakes of the level for the saved ones
+        let level_segments: Vec<Entity> = world
+            .query::<&SnakeSegments>()
+            .iter(world)
+            .flat_map(|segments| segments.0.clone())
+            .collect();
+        for segment in level_segments {
+            world.despawn(segment);
+        }
+        // Likewise the dens, some of which may have been destroyed
+        let level_dens: Vec<Entity> = world
+            .query_filtered::<Entity, With<SnakeDen>>()
+            .iter(world)
+            .collect();
+        for den in level_dens {
+            world.despawn(den);
+        }
+        let asset_server = world.get_resource::<AssetServer>().unwrap().clone();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        let heads: Vec<Entity> = self
+            .snakes
+            .iter()
+            .map(|saved| {
+                let head = spawn_snake_entities(&mut commands, &asset_server, saved.snake.clone());
+                if saved.player {
+                    commands.entity(head).insert(PlayerSnake);
+                }
+                if let Some(profile) = saved.ai {
+                    commands.entity(head).insert(SnakeAi::new(profile));
+                }
+                if saved.from_den {
+                    commands.entity(head).insert(DenSnake);
+                }
+                if let Some(abilities) = &saved.abilities {
+                    commands.entity(head).insert(abilities.clone());
+                }
+                head
+            })
+            .collect();
+        for (power_up, position) in &self.pickups {
+            spawn_pickup(&mut commands, *power_up, *position);
+        }
+        for (position, den) in &self.dens {
+            spawn_den(&mut commands, &asset_server, *position, den.clone());
+        }
+        queue.apply(world);
+        for (saved, head) in self.snakes.iter().zip(heads) {
+            let segments = world.get::<SnakeSegments>(head).unwrap().0.clone();
+            for ((segment, previous), direction) in
+                segments.iter().zip(&saved.previous).zip(&saved.directions)
+            {
+                world.get_mut::<PreviousPosition>(*segment).unwrap().0 = *previous;
+                if let Some(mut segment) = world.get_mut::<SnakeSegment>(*segment) {
+                    segment.direction = *direction;
+                }
+            }
+        }
+
+        let saved_slayers = [&self.slayer, &self.second_slayer];
+        let slayers: Vec<(Entity, usize)> = world
+            .query::<(Entity, &SlayerPlayer)>()
+            .iter(world)
+            .map(|(entity, player)| (entity, player.0))
+            .collect();
+        for (entity, player) in slayers {
+            let saved = saved_slayers.get(player).copied().unwrap_or(&None);
+            restore_slayer(world, entity, saved.as_ref());
+        }
+    }
+}
+
+/// Puts the slayer `entity` into the `saved` state, or despawns them if they had been chomped.
+fn restore_slayer(world: &mut World, entity: Entity, saved: Option<&SavedSlayer>) {
+    match saved {
+        Some(saved) => {
+            let mut slayer = world.entity_mut(entity);
+            slayer.get_mut::<Transform>().unwrap().translation = saved.translation;
+            *slayer.get_mut::<Velocity>().unwrap() = Velocity::from_linear(saved.velocity);
+            *slayer.get_mut::<Jumps>().unwrap() = Jumps {
+                ground_contacts: saved.ground_contacts,
+                underfoot: Vec::new(),
+                jumped_in_air: saved.jumped_in_air,
+                holding_up: saved.holding_up,
+                // The rebuilt physics world reports every one of them as new
+                restored_contacts: saved.ground_contacts,
+            };
+            *slayer.get_mut::<Facing>().unwrap() = match saved.facing {
+                Direction::Left => Facing::Left,
+                _ => Facing::Right,
+            };
+            *slayer.get_mut::<SwordDirection>().unwrap() = match saved.sword {
+                Some(Direction::Up) => SwordDirection::Up,
+                Some(Direction::Down) => SwordDirection::Down,
+                Some(Direction::Left) => SwordDirection::Left,
+                Some(Direction::Right) => SwordDirection::Right,
+                None => SwordDirection::NotAttacking,
+            };
+            slayer
+                .get_mut::<AttackCooldown>()
+                .unwrap()
+                .0
+                .set_elapsed(saved.attack_cooldown);
+            if saved.invulnerable {
+                slayer.insert(Invulnerable);
+            }
+            *slayer.get_mut::<Buffs>().unwrap() = saved.buffs.clone();
+            slayer.insert(ExtraLives(saved.extra_lives));
+        }
+        None => {
+            world.despawn(entity);
+        }
+    }
+}
+
+/// Where matches are saved; `None` turns saving off.
+pub struct SaveGamePath(pub Option<PathBuf>);
+
+impl Default for SaveGamePath {
+    fn default() -> Self {
+        Self(Some(PathBuf::from(SAVE_GAME_PATH)))
+    }
+}
+
+/// Asks for the paused match to be saved, after which the game goes back to the main menu.
+pub struct SaveMatchEvent;
+
+/// Present while a match is being set up from a save rather than from scratch.
+pub struct PendingRestore(pub SavedMatch);
+
+/// Saves matches from the pause menu and restores them when they are continued.
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveGamePath>()
+            .add_event::<SaveMatchEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::PauseMenu)
+                    .with_system(save_match.exclusive_system()),
+            )
+            .add_system_set(
+                // After the rest of the match has been set up
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(restore_match.exclusive_system().at_end()),
+            );
+    }
+}
+
+fn save_match(world: &mut World) {
+    let requested = world
+        .get_resource_mut::<Events<SaveMatchEvent>>()
+        .unwrap()
+        .drain()
+        .count()
+        > 0;
+    if !requested {
+        return;
+    }
+    let mode = world.get_resource::<MatchConfig>().unwrap().mode;
+    if !mode.can_be_saved() {
+        warn!("{:?} matches cannot be saved", mode);
+        return;
+    }
+    let saved = SavedMatch::capture(world);
+    if let Some(path) = &world.get_resource::<SaveGamePath>().unwrap().0 {
+        if let Err(error) = saved.save(path) {
+            error!("Could not save the match to {}: {}", path.display(), error);
+        }
+    }
+    let mut state = world.get_resource_mut::<State<AppState>>().unwrap();
+    if let Err(error) = state.replace(AppState::MainMenu) {
+        warn!("Could not leave the match after saving: {:?}", error);
+    }
+}
+
+fn restore_match(world: &mut World) {
+    if let Some(PendingRestore(saved)) = world.remove_resource::<PendingRestore>() {
+        saved.restore(world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_match() -> SavedMatch {
+        let mut buffs = Buffs::default();
+        buffs.grant(PowerUp::DoubleJump, Duration::from_millis(4200));
+        SavedMatch {
+            config: MatchConfig {
+                seed: 7,
+                level: "arena".to_string(),
+                handicaps: Handicaps {
+                    extra_lives: 2,
+                    ..Default::default()
+                },
+                mode: MatchMode::CoOp,
+                wave: 3,
+            },
+            tick: 1234,
+            hit_stop_frames: 4,
+            rng: GameRng::from_state(0xDEAD_BEEF),
+            snake_timer_elapsed: Duration::from_millis(150),
+            snake_timer_duration: Duration::from_millis(400),
+            last_tail: Some(Position::new(3, 4)),
+            snakes: vec![SavedSnake {
+                snake: Snake::resume(
+                    vec![Position::new(5, 5), Position::new(4, 5)],
+                    Direction::Up,
+                    Direction::Right,
+                ),
+                player: true,
+                ai: None,
+                from_den: false,
+                previous: vec![Position::new(4, 5), Position::new(3, 5)],
+                directions: vec![Direction::Up, Direction::Right],
+                abilities: Some(SnakeAbilities {
+                    cooldowns: [
+                        Duration::ZERO,
+                        Duration::from_millis(6500),
+                        Duration::from_millis(1200),
+                    ],
+                    armor: Duration::from_millis(1500),
+                    burst: Duration::ZERO,
+                    lunge_pending: true,
+                    recovering: false,
+                }),
+            }],
+            slayer: Some(SavedSlayer {
+                translation: Vec3::new(12.5, -40.25, 1.0),
+                velocity: Vec3::new(300.0, -9.8, 0.0),
+                ground_contacts: 0,
+                jumped_in_air: true,
+                holding_up: true,
+                facing: Direction::Left,
+                sword: Some(Direction::Down),
+                attack_cooldown: Duration::from_millis(250),
+                invulnerable: false,
+                buffs,
+                extra_lives: 1,
+            }),
+            second_slayer: Some(SavedSlayer {
+                translation: Vec3::new(76.5, -40.25, 1.0),
+                velocity: Vec3::ZERO,
+                ground_contacts: 2,
+                jumped_in_air: false,
+                holding_up: false,
+                facing: Direction::Right,
+                sword: None,
+                attack_cooldown: Duration::from_millis(900),
+                invulnerable: true,
+                buffs: Buffs::default(),
+                extra_lives: 2,
+            }),
+            scores: SlayerScores([30, 10]),
+            pickups: vec![(PowerUp::SlowSnake, Position::new(9, 2))],
+            pickup_timer_elapsed: Duration::from_millis(3500),
+            dens: vec![(
+                Position::new(24, 1),
+                SnakeDen {
+                    health: 3,
+                    spawned: 2,
+                    until_next: Duration::from_millis(8250),
+                },
+            )],
+        }
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let saved = saved_match();
+        assert_eq!(SavedMatch::from_bytes(&saved.to_bytes()).unwrap(), saved);
+
+        let chomped = SavedMatch {
+            slayer: None,
+            second_slayer: None,
+            last_tail: None,
+            pickups: Vec::new(),
+            ..saved
+        };
+        assert_eq!(
+            SavedMatch::from_bytes(&chomped.to_bytes()).unwrap(),
+            chomped
+        );
+    }
+
+    #[test]
+    fn broken_saves_are_rejected() {
+        let bytes = saved_match().to_bytes();
+        assert!(matches!(
+            SavedMatch::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SaveError::Truncated)
+        ));
+        assert!(matches!(
+            SavedMatch::from_bytes(b"UAR\0\x01\x00"),
+            Err(SaveError::BadMagic)
+        ));
+        let mut future = bytes;
+        future[4] = 99;
+        assert!(matches!(
+            SavedMatch::from_bytes(&future),
+            Err(SaveError::UnsupportedVersion(99))
+        ));
+    }
+}
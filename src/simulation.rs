@@ -0,0 +1,462 @@
+//! The fixed-timestep simulation core.
+//!
+//! Gameplay systems live in [`SimulationStage`], which is stepped zero or more times per
+//! frame by an accumulator so that every tick advances the game by exactly
+//! [`SimulationClock::step`]. Inputs are latched into [`TickInput`] once per tick, which
+//! means a given sequence of tick inputs always plays out the same way,
+//! no matter how long each rendered frame took.
+//!
+//! Heron's physics is part of the tick as well: [`TickedPhysicsPlugin`] takes its stage out of
+//! the frame loop and steps it once at the end of every tick, so bodies only move while the
+//! simulation does.
+
+use std::time::Duration;
+
+use bevy::core::Time;
+use bevy::ecs::event::ManualEventReader;
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+use bevy::transform::transform_propagate_system::transform_propagate_system;
+use heron::{CollisionEvent, PhysicsSteps};
+
+use crate::snake_grid::Direction;
+use crate::AppState;
+
+/// How many simulation ticks make up one second of game time.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// The most ticks that will be run to catch up within a single frame.
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// The most unsimulated time that is kept around after a stall.
+///
+/// Anything beyond this is dropped so that a long hitch does not turn into a death spiral.
+const MAX_BACKLOG: Duration = Duration::from_millis(500);
+
+/// The stage that all deterministic gameplay systems run in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimulationStage;
+
+/// Ordering of the systems inside [`SimulationStage`].
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub enum SimulationSystem {
+    /// Latches the inputs for the current tick.
+    Input,
+    /// Swaps in the inputs agreed on with a network peer.
+    Netplay,
+    /// Records or overrides the latched inputs.
+    Replay,
+    /// Everything that advances the game state.
+    Gameplay,
+    /// Gets the bodies ready for the physics step that ends the tick.
+    Physics,
+}
+
+/// How the simulation clock is advanced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// Ticks follow the wall clock, catching up after slow frames.
+    RealTime,
+    /// Exactly one tick is run per app update, regardless of frame time.
+    ///
+    /// Used to drive the game from tests and tools.
+    StepPerUpdate,
+}
+
+/// Accumulates frame time and hands it out in fixed-size ticks.
+pub struct SimulationClock {
+    step: Duration,
+    accumulator: Duration,
+    tick: u64,
+    mode: ClockMode,
+    time_scale: f64,
+    paused: bool,
+    queued_steps: u32,
+    hit_stop_frames: u32,
+    ticks_this_frame: u32,
+    looping: bool,
+    tick_limit: Option<u64>,
+    finished: bool,
+}
+
+impl SimulationClock {
+    /// A clock that ticks [`TICKS_PER_SECOND`] times per second of wall time.
+    pub fn new(mode: ClockMode) -> Self {
+        Self {
+            step: Duration::from_secs(1) / TICKS_PER_SECOND,
+            accumulator: Duration::ZERO,
+            tick: 0,
+            mode,
+            time_scale: 1.0,
+            paused: false,
+            queued_steps: 0,
+            hit_stop_frames: 0,
+            ticks_this_frame: 0,
+            looping: false,
+            tick_limit: None,
+            finished: false,
+        }
+    }
+
+    /// The amount of game time that passes in one tick.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// The number of ticks simulated so far.
+    ///
+    /// While a tick is being simulated this is the number of that tick, starting at 1.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Wall time that has passed since the last tick but has not been simulated yet.
+    ///
+    /// Useful for drawing things part of the way towards where the next tick will put them.
+    pub fn overstep(&self) -> Duration {
+        self.accumulator
+    }
+
+    /// How the clock is currently being advanced.
+    pub fn mode(&self) -> ClockMode {
+        self.mode
+    }
+
+    /// Changes how the clock is advanced, discarding any time that was not simulated yet.
+    pub fn set_mode(&mut self, mode: ClockMode) {
+        self.mode = mode;
+        self.accumulator = Duration::ZERO;
+    }
+
+    /// How fast game time passes relative to wall time in [`ClockMode::RealTime`].
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// Speeds up or slows down the simulation; `1.0` is normal speed.
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    /// Is the simulation frozen?
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Freezes or unfreezes the simulation.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.accumulator = Duration::ZERO;
+    }
+
+    /// Runs a single tick on the next update, even while paused.
+    pub fn step_once(&mut self) {
+        self.queued_steps += 1;
+    }
+
+    /// Holds the simulation still for the next `frames` frames, to let a hit sink in.
+    ///
+    /// Time that passes during a hit-stop is dropped rather than caught up on afterwards.
+    /// Only [`ClockMode::RealTime`] is affected, since stepped clocks have no frame pacing to stretch.
+    pub fn hit_stop(&mut self, frames: u32) {
+        self.hit_stop_frames = self.hit_stop_frames.max(frames);
+    }
+
+    /// How many more frames the current hit-stop holds the simulation still for.
+    pub fn hit_stop_frames(&self) -> u32 {
+        self.hit_stop_frames
+    }
+
+    /// Holds the simulation at tick `limit` until it is raised, while waiting on something
+    /// outside the game such as a network peer's inputs; `None` lifts the limit.
+    ///
+    /// Unlike pausing, time keeps accumulating, so the simulation catches up once it may carry on.
+    pub fn set_tick_limit(&mut self, limit: Option<u64>) {
+        self.tick_limit = limit;
+    }
+
+    /// Stops the simulation for good once the match has been decided, so nothing moves or is
+    /// recorded behind the game over screen. Only [`reset`](Self::reset) starts it again.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Has the match been decided?
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Carries on counting from `tick`, for a match restored from a save.
+    pub fn resume_at(&mut self, tick: u64) {
+        self.tick = tick;
+        self.accumulator = Duration::ZERO;
+    }
+
+    /// Restarts the tick count, at the beginning of a match.
+    pub fn reset(&mut self) {
+        self.tick = 0;
+        self.accumulator = Duration::ZERO;
+        self.time_scale = 1.0;
+        self.paused = false;
+        self.queued_steps = 0;
+        self.hit_stop_frames = 0;
+        self.finished = false;
+    }
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        Self::new(ClockMode::RealTime)
+    }
+}
+
+/// The keys a slayer is steered with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlayerKeys {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub attack: KeyCode,
+}
+
+/// The keys of each slayer, in player order.
+///
+/// The second slayer only plays in co-op matches, and their keys stay clear of the snake
+/// player's.
+pub const SLAYER_KEYS: [SlayerKeys; 2] = [
+    SlayerKeys {
+        left: KeyCode::A,
+        right: KeyCode::D,
+        up: KeyCode::W,
+        down: KeyCode::S,
+        attack: KeyCode::B,
+    },
+    SlayerKeys {
+        left: KeyCode::J,
+        right: KeyCode::L,
+        up: KeyCode::I,
+        down: KeyCode::K,
+        attack: KeyCode::U,
+    },
+];
+
+/// What a slayer asked for during a tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SlayerInput {
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    /// The attack button was pressed since the previous tick.
+    pub attack: bool,
+}
+
+/// What the snake asked for during a tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeInput {
+    pub turn: Option<Direction>,
+    /// The lunge button was pressed since the previous tick.
+    pub lunge: bool,
+    /// The armor button was pressed since the previous tick.
+    pub armor: bool,
+    /// The speed burst button was pressed since the previous tick.
+    pub burst: bool,
+}
+
+/// The inputs of both sides of a match for the current tick.
+///
+/// Simulation systems must read their inputs from here rather than from `Input<KeyCode>`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TickInput {
+    pub slayer: SlayerInput,
+    pub snake: SnakeInput,
+    /// The second slayer, who only plays in co-op matches.
+    pub second_slayer: SlayerInput,
+}
+
+impl TickInput {
+    /// The inputs of the slayer steered by `player`, counting from 0.
+    pub fn slayer_input(&self, player: usize) -> SlayerInput {
+        match player {
+            0 => self.slayer,
+            _ => self.second_slayer,
+        }
+    }
+
+    fn slayer_input_mut(&mut self, player: usize) -> &mut SlayerInput {
+        match player {
+            0 => &mut self.slayer,
+            _ => &mut self.second_slayer,
+        }
+    }
+}
+
+/// Inputs gathered from the keyboard since the last tick.
+#[derive(Default)]
+pub struct InputBuffer(pub TickInput);
+
+/// Sets up the simulation clock, stage and input latching.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationClock>()
+            .init_resource::<InputBuffer>()
+            .init_resource::<TickInput>()
+            .add_stage_after(
+                CoreStage::Update,
+                SimulationStage,
+                SystemStage::parallel().with_run_criteria(run_simulation_tick),
+            )
+            .add_system(buffer_keyboard_input)
+            .add_system_to_stage(
+                SimulationStage,
+                latch_tick_input.label(SimulationSystem::Input),
+            );
+    }
+}
+
+/// Heron's physics stage, run once per tick by [`step_physics`] instead of once per frame,
+/// and where it is up to in heron's collision events.
+struct PhysicsSchedule(Schedule, ManualEventReader<CollisionEvent>);
+
+/// The collisions heron reported in the physics step that ended the previous tick.
+///
+/// Tick systems read collisions from here rather than from the events themselves, which are
+/// dropped after two frames whether or not a tick ran in them. These stay until the next step.
+#[derive(Default)]
+pub struct TickCollisions(pub Vec<CollisionEvent>);
+
+/// Where transforms set during a tick are brought up to date before heron reads them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+struct PropagateTransforms;
+
+/// Steps heron's physics once at the end of every tick, by the length of a tick.
+///
+/// Must be added after heron's `PhysicsPlugin`, whose stage it takes over. Systems that need to
+/// set up the bodies for the step go in [`SimulationSystem::Physics`].
+pub struct TickedPhysicsPlugin;
+
+impl Plugin for TickedPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        // Leaves an empty stage behind in the frame loop
+        let heron = app
+            .schedule
+            .get_stage_mut::<Schedule>(&heron::stage::ROOT)
+            .map(std::mem::take)
+            .expect("heron's PhysicsPlugin must be added before TickedPhysicsPlugin");
+        let physics = Schedule::default()
+            .with_stage(
+                PropagateTransforms,
+                SystemStage::single_threaded().with_system(transform_propagate_system),
+            )
+            .with_stage(heron::stage::ROOT, heron);
+        app.insert_resource(PhysicsSchedule(physics, ManualEventReader::default()))
+            .init_resource::<TickCollisions>()
+            .insert_resource(PhysicsSteps::every_frame(
+                Duration::from_secs(1) / TICKS_PER_SECOND,
+            ))
+            .add_system_to_stage(SimulationStage, step_physics.exclusive_system().at_end());
+    }
+}
+
+fn step_physics(world: &mut World) {
+    world.resource_scope(|world, mut physics: Mut<PhysicsSchedule>| {
+        physics.0.run(world);
+        let events = world.get_resource::<Events<CollisionEvent>>().unwrap();
+        let collisions = physics.1.iter(events).cloned().collect();
+        world.insert_resource(TickCollisions(collisions));
+    });
+}
+
+/// Run criteria for systems in [`SimulationStage`] that should only tick while `state` is active.
+pub fn in_state(state: AppState) -> impl Fn(Res<State<AppState>>) -> ShouldRun {
+    move |current: Res<State<AppState>>| {
+        if *current.current() == state {
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+}
+
+fn run_simulation_tick(time: Res<Time>, mut clock: ResMut<SimulationClock>) -> ShouldRun {
+    let clock = &mut *clock;
+    if !clock.looping {
+        let elapsed = if clock.paused {
+            Duration::ZERO
+        } else {
+            match clock.mode {
+                ClockMode::RealTime if clock.hit_stop_frames > 0 => {
+                    clock.hit_stop_frames -= 1;
+                    Duration::ZERO
+                }
+                ClockMode::RealTime => time.delta().mul_f64(clock.time_scale),
+                ClockMode::StepPerUpdate => clock.step,
+            }
+        };
+        clock.accumulator += elapsed;
+        if clock.accumulator > MAX_BACKLOG {
+            warn!(
+                "Simulation fell {:?} behind, dropping the excess",
+                clock.accumulator - MAX_BACKLOG
+            );
+            clock.accumulator = MAX_BACKLOG;
+        }
+        clock.ticks_this_frame = 0;
+        if clock.queued_steps > 0 {
+            clock.queued_steps -= 1;
+            clock.accumulator += clock.step;
+        }
+    }
+
+    let held = clock.finished || clock.tick_limit.map_or(false, |limit| clock.tick >= limit);
+    if clock.accumulator >= clock.step && clock.ticks_this_frame < MAX_TICKS_PER_FRAME && !held {
+        clock.accumulator -= clock.step;
+        clock.tick += 1;
+        clock.ticks_this_frame += 1;
+        clock.looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        clock.looping = false;
+        ShouldRun::No
+    }
+}
+
+fn buffer_keyboard_input(keyboard_input: Res<Input<KeyCode>>, mut buffer: ResMut<InputBuffer>) {
+    for (player, keys) in SLAYER_KEYS.iter().enumerate() {
+        let slayer = buffer.0.slayer_input_mut(player);
+        slayer.left = keyboard_input.pressed(keys.left);
+        slayer.right = keyboard_input.pressed(keys.right);
+        slayer.up = keyboard_input.pressed(keys.up);
+        slayer.down = keyboard_input.pressed(keys.down);
+        slayer.attack |= keyboard_input.just_pressed(keys.attack);
+    }
+
+    let snake = &mut buffer.0.snake;
+    snake.turn = if keyboard_input.pressed(KeyCode::Left) {
+        Some(Direction::Left)
+    } else if keyboard_input.pressed(KeyCode::Down) {
+        Some(Direction::Down)
+    } else if keyboard_input.pressed(KeyCode::Up) {
+        Some(Direction::Up)
+    } else if keyboard_input.pressed(KeyCode::Right) {
+        Some(Direction::Right)
+    } else {
+        None
+    };
+    snake.lunge |= keyboard_input.just_pressed(KeyCode::RShift);
+    snake.armor |= keyboard_input.just_pressed(KeyCode::RControl);
+    snake.burst |= keyboard_input.just_pressed(KeyCode::Slash);
+}
+
+fn latch_tick_input(mut buffer: ResMut<InputBuffer>, mut tick_input: ResMut<TickInput>) {
+    *tick_input = buffer.0;
+    // One-shot presses belong to the tick that consumed them
+    buffer.0.slayer.attack = false;
+    buffer.0.second_slayer.attack = false;
+    buffer.0.snake.lunge = false;
+    buffer.0.snake.armor = false;
+    buffer.0.snake.burst = false;
+}
@@ -0,0 +1,220 @@
+//! Active abilities for the snake player, each on its own cooldown.
+//!
+//! - A lunge moves the snake two cells on its next move, after which it skips a move to
+//!   catch its breath.
+//! - Armor makes every segment shrug off sword hits for a while.
+//! - A speed burst makes the snake move twice as often for a moment, while every other
+//!   snake keeps to the [`SnakeTimer`](crate::SnakeTimer).
+//!
+//! Each ability is telegraphed by tinting the snake, so the slayer can see it coming.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::simulation::{in_state, SimulationClock, SimulationStage, SimulationSystem, TickInput};
+use crate::{AppState, PlayerSnake, SnakeAction, SnakeHead, SnakeSegments, SnakeSpeed};
+
+const LUNGE_COOLDOWN: f32 = 4.0;
+const ARMOR_COOLDOWN: f32 = 10.0;
+const BURST_COOLDOWN: f32 = 8.0;
+/// How long armor lasts, in seconds.
+const ARMOR_DURATION: f32 = 3.0;
+/// How long a speed burst lasts, in seconds.
+const BURST_DURATION: f32 = 2.0;
+/// How many times faster the snake moves during a speed burst.
+///
+/// A whole number, so that the snake still moves whenever the [`SnakeTimer`](crate::SnakeTimer)
+/// fires.
+const BURST_FACTOR: u32 = 2;
+
+/// The head tint while a lunge is about to happen.
+const LUNGE_COLOR: Color = Color::rgb(1.0, 0.35, 0.35);
+/// The head tint during a speed burst.
+const BURST_COLOR: Color = Color::rgb(1.0, 0.9, 0.3);
+/// The tint of every segment while the snake is armored.
+const ARMOR_COLOR: Color = Color::rgb(0.55, 0.65, 0.9);
+
+/// Something the snake player can do besides turning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnakeAbility {
+    Lunge,
+    Armor,
+    Burst,
+}
+
+impl SnakeAbility {
+    pub const ALL: [SnakeAbility; 3] = [
+        SnakeAbility::Lunge,
+        SnakeAbility::Armor,
+        SnakeAbility::Burst,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// How long the snake has to wait before using the ability again.
+    pub fn cooldown(self) -> Duration {
+        Duration::from_secs_f32(match self {
+            SnakeAbility::Lunge => LUNGE_COOLDOWN,
+            SnakeAbility::Armor => ARMOR_COOLDOWN,
+            SnakeAbility::Burst => BURST_COOLDOWN,
+        })
+    }
+}
+
+/// The state of the abilities of the snake steered by the snake player.
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnakeAbilities {
+    /// How long until each ability can be used again, in the order of [`SnakeAbility::ALL`].
+    pub cooldowns: [Duration; SnakeAbility::ALL.len()],
+    /// How long the armor has left.
+    pub armor: Duration,
+    /// How long the speed burst has left.
+    pub burst: Duration,
+    /// A lunge was asked for, and happens on the snake's next move.
+    pub lunge_pending: bool,
+    /// The snake lunged, and skips its next move.
+    pub recovering: bool,
+}
+
+impl SnakeAbilities {
+    pub fn is_ready(&self, ability: SnakeAbility) -> bool {
+        self.cooldowns[ability.index()].is_zero()
+    }
+
+    pub fn is_armored(&self) -> bool {
+        !self.armor.is_zero()
+    }
+
+    /// How many cells the snake moves the next time it is due to move.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::snake_abilities::SnakeAbilities;
+    /// let mut abilities = SnakeAbilities {
+    ///     lunge_pending: true,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(abilities.take_moves(), 2);
+    /// assert_eq!(abilities.take_moves(), 0);
+    /// assert_eq!(abilities.take_moves(), 1);
+    /// ```
+    pub fn take_moves(&mut self) -> u32 {
+        if self.recovering {
+            self.recovering = false;
+            0
+        } else if self.lunge_pending {
+            self.lunge_pending = false;
+            self.recovering = true;
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// Lets the snake player use their abilities, and shows them on the snake.
+pub struct SnakeAbilityPlugin;
+
+impl Plugin for SnakeAbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::InOnePlayerGame).with_system(telegraph_snake_abilities),
+        )
+        .add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+                .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                .label(SimulationSystem::Gameplay)
+                .after(SimulationSystem::Input)
+                .with_system(
+                    use_snake_abilities
+                        .after(SnakeAction::Input)
+                        .before(SnakeAction::Movement),
+                ),
+        );
+    }
+}
+
+fn use_snake_abilities(
+    tick_input: Res<TickInput>,
+    clock: Res<SimulationClock>,
+    mut snakes: Query<(&mut SnakeAbilities, &mut SnakeSpeed), With<PlayerSnake>>,
+) {
+    let input = tick_input.snake;
+    let step = clock.step();
+    for (mut abilities, mut speed) in snakes.iter_mut() {
+        for cooldown in abilities.cooldowns.iter_mut() {
+            *cooldown = cooldown.saturating_sub(step);
+        }
+        abilities.armor = abilities.armor.saturating_sub(step);
+        abilities.burst = abilities.burst.saturating_sub(step);
+
+        let wanted = [
+            (SnakeAbility::Lunge, input.lunge),
+            (SnakeAbility::Armor, input.armor),
+            (SnakeAbility::Burst, input.burst),
+        ];
+        for (ability, pressed) in wanted {
+            if !pressed || !abilities.is_ready(ability) {
+                continue;
+            }
+            abilities.cooldowns[ability.index()] = ability.cooldown();
+            match ability {
+                SnakeAbility::Lunge => abilities.lunge_pending = true,
+                SnakeAbility::Armor => abilities.armor = Duration::from_secs_f32(ARMOR_DURATION),
+                SnakeAbility::Burst => abilities.burst = Duration::from_secs_f32(BURST_DURATION),
+            }
+        }
+        *speed = if abilities.burst.is_zero() {
+            SnakeSpeed::default()
+        } else {
+            SnakeSpeed(BURST_FACTOR)
+        };
+    }
+}
+
+/// Tints snakes to show which of their abilities are in play.
+fn telegraph_snake_abilities(
+    heads: Query<(&SnakeSegments, Option<&SnakeAbilities>), With<SnakeHead>>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    for (segments, abilities) in heads.iter() {
+        let abilities = abilities.cloned().unwrap_or_default();
+        let body_color = if abilities.is_armored() {
+            ARMOR_COLOR
+        } else {
+            Color::WHITE
+        };
+        let head_color = if abilities.lunge_pending {
+            LUNGE_COLOR
+        } else if !abilities.burst.is_zero() {
+            BURST_COLOR
+        } else {
+            body_color
+        };
+        for (index, segment) in segments.0.iter().enumerate() {
+            if let Ok(mut sprite) = sprites.get_mut(*segment) {
+                sprite.color = if index == 0 { head_color } else { body_color };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn abilities_start_ready() {
+        let abilities = SnakeAbilities::default();
+        for ability in SnakeAbility::ALL {
+            assert!(abilities.is_ready(ability));
+            assert!(!ability.cooldown().is_zero());
+            assert_eq!(SnakeAbility::ALL[ability.index()], ability);
+        }
+        assert!(!abilities.is_armored());
+    }
+}
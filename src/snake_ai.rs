@@ -0,0 +1,126 @@
+//! Snakes that steer themselves, for when there is no snake player.
+//!
+//! How an AI snake picks its way depends on its [`AiProfile`]. A hunter looks for the
+//! shortest way around everything in the arena to the nearest slayer each tick and turns
+//! onto it; an ambusher does the same, but only once a slayer comes close; a wanderer never
+//! goes after anyone. Any snake without a way to a slayer keeps going if it can and
+//! otherwise turns into whatever cell is free.
+//!
+//! Only the occupancy grid and the slayers' positions go into the decision, so an AI snake
+//! moves the same way in a replay as it did in the match.
+
+use bevy::prelude::*;
+
+use crate::simulation::{in_state, SimulationStage, SimulationSystem};
+use crate::snake_grid::{Direction, Occupancy, Snake};
+use crate::{world_to_cell, AppState, Slayer, SnakeAction};
+
+/// How many moves away a slayer has to be for an ambusher to go after them.
+const AMBUSH_RANGE: usize = 6;
+
+/// How an AI snake goes about the slayers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AiProfile {
+    /// Always heads for the nearest slayer.
+    Hunter,
+    /// Lies in wait until a slayer comes close, then heads for them.
+    Ambusher,
+    /// Goes its own way, turning only when something is in the way.
+    Wanderer,
+}
+
+impl AiProfile {
+    pub const ALL: [AiProfile; 3] = [AiProfile::Hunter, AiProfile::Ambusher, AiProfile::Wanderer];
+
+    /// The furthest a slayer can be, in moves, for the snake to go after them.
+    fn range(self) -> Option<usize> {
+        match self {
+            AiProfile::Hunter => Some(usize::MAX),
+            AiProfile::Ambusher => Some(AMBUSH_RANGE),
+            AiProfile::Wanderer => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(byte as usize).copied()
+    }
+}
+
+impl Default for AiProfile {
+    fn default() -> Self {
+        AiProfile::Hunter
+    }
+}
+
+/// Marks a snake head as steered by [`SnakeAiPlugin`].
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnakeAi {
+    pub profile: AiProfile,
+}
+
+impl SnakeAi {
+    pub fn new(profile: AiProfile) -> Self {
+        Self { profile }
+    }
+}
+
+/// Steers every [`SnakeAi`] snake towards the slayers.
+pub struct SnakeAiPlugin;
+
+impl Plugin for SnakeAiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+                .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                .label(SimulationSystem::Gameplay)
+                .after(SimulationSystem::Input)
+                .with_system(
+                    steer_ai_snakes
+                        .after(SnakeAction::Input)
+                        .before(SnakeAction::Movement),
+                ),
+        );
+    }
+}
+
+fn steer_ai_snakes(
+    occupancy: Res<Occupancy<Entity>>,
+    slayers: Query<&Transform, With<Slayer>>,
+    mut snakes: Query<(&mut Snake, &SnakeAi)>,
+) {
+    for (mut snake, ai) in snakes.iter_mut() {
+        let head = snake.head();
+        let chase = ai.profile.range().and_then(|range| {
+            slayers
+                .iter()
+                .filter_map(|transform| {
+                    occupancy.find_path(head, world_to_cell(transform.translation.truncate()))
+                })
+                .filter(|path| !path.is_empty() && path.len() <= range)
+                .min_by_key(|path| path.len())
+                .map(|path| path[0])
+        });
+        let direction = chase.unwrap_or_else(|| wander(&occupancy, &snake));
+        snake.turn(direction);
+    }
+}
+
+/// Where to go with no slayer in reach: straight on if the way is free, otherwise into any
+/// free neighbouring cell.
+fn wander(occupancy: &Occupancy<Entity>, snake: &Snake) -> Direction {
+    let arena = occupancy.arena();
+    let is_free = |direction| occupancy.is_free(arena.neighbour(snake.head(), direction));
+    if is_free(snake.direction()) {
+        return snake.direction();
+    }
+    Direction::ALL
+        .into_iter()
+        .filter(|direction| *direction != snake.last_moved().opposite())
+        .find(|direction| is_free(*direction))
+        .unwrap_or_else(|| snake.direction())
+}
@@ -0,0 +1,284 @@
+//! Snake dens: arena objects that keep letting out new snakes until they are destroyed.
+//!
+//! Each den in the [`Level`] lets out a short snake every so often, which hunts the slayers
+//! like a [`SnakeAi`] snake. The wait between snakes gets shorter with every snake let out,
+//! down to a limit, so a den left alone soon fills the arena. The slayer destroys a den by
+//! hitting it with their sword [`DEN_HEALTH`] times; it turns redder with each hit.
+//!
+//! Which way a new snake heads out is drawn from the match's [`GameRng`], so dens behave the
+//! same in a replay.
+
+use std::iter;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::level::Level;
+use crate::power_ups::Buffs;
+use crate::rng::GameRng;
+use crate::simulation::{in_state, SimulationClock, SimulationStage, SimulationSystem};
+use crate::snake_ai::SnakeAi;
+use crate::snake_grid::{Direction, Occupancy, Position, Snake};
+use crate::{
+    cell_to_world, spawn_snake_entities, sword_cells, AppState, MatchSetup, Slayer, SlayerAction,
+    SnakeAction, SwordSwingEvent, TILE_SIZE,
+};
+
+/// How many sword hits it takes to destroy a den.
+pub const DEN_HEALTH: u8 = 5;
+/// The image dens are drawn with.
+pub(crate) const DEN_TEXTURE: &str = "snake_den.png";
+/// How wide and tall a den is drawn, in world units.
+pub(crate) const DEN_SIZE: f32 = 1.5 * TILE_SIZE;
+
+/// How long a den waits before letting out its first snake, in seconds.
+const FIRST_SNAKE_DELAY: f32 = 15.0;
+/// How much sooner each snake comes than the one before it, in seconds.
+const DELAY_STEP: f32 = 2.0;
+/// The shortest wait between snakes, in seconds.
+const MIN_DELAY: f32 = 4.0;
+/// How long the snakes let out by dens are.
+const DEN_SNAKE_LENGTH: usize = 3;
+/// Dens stop letting out snakes while this many of theirs are in the arena.
+const MAX_DEN_SNAKES: usize = 6;
+
+/// A den that lets out snakes, in the cell given by its [`Position`].
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct SnakeDen {
+    /// How many more sword hits the den can take.
+    pub health: u8,
+    /// How many snakes the den has let out so far.
+    pub spawned: u32,
+    /// How long until the den lets out its next snake.
+    pub until_next: Duration,
+}
+
+impl Default for SnakeDen {
+    fn default() -> Self {
+        Self {
+            health: DEN_HEALTH,
+            spawned: 0,
+            until_next: spawn_delay(0),
+        }
+    }
+}
+
+impl SnakeDen {
+    /// Counts down to the next snake by `step`, returning whether it is due.
+    ///
+    /// A snake stays due until [`SnakeDen::let_out`] says it has been let out.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use unfair_advantage_lib::snake_den::SnakeDen;
+    /// let mut den = SnakeDen::default();
+    /// assert!(!den.tick(Duration::from_secs(14)));
+    /// assert!(den.tick(Duration::from_secs(1)));
+    /// assert!(den.tick(Duration::from_secs(1)));
+    /// assert_eq!(den.spawned, 0);
+    /// den.let_out();
+    /// assert_eq!(den.spawned, 1);
+    /// assert_eq!(den.until_next, Duration::from_secs(13));
+    /// ```
+    pub fn tick(&mut self, step: Duration) -> bool {
+        self.until_next = self.until_next.saturating_sub(step);
+        self.until_next.is_zero()
+    }
+
+    /// Counts a snake that was let out, and starts the wait for the next one.
+    pub fn let_out(&mut self) {
+        self.spawned += 1;
+        self.until_next = spawn_delay(self.spawned);
+    }
+
+    /// Takes a sword hit, returning whether that destroyed the den.
+    pub fn hit(&mut self) -> bool {
+        self.health = self.health.saturating_sub(1);
+        self.health == 0
+    }
+}
+
+/// How long a den that has let out `spawned` snakes waits for the next one.
+pub fn spawn_delay(spawned: u32) -> Duration {
+    Duration::from_secs_f32((FIRST_SNAKE_DELAY - spawned as f32 * DELAY_STEP).max(MIN_DELAY))
+}
+
+/// Marks a snake that was let out by a den.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DenSnake;
+
+/// The den in `position` was destroyed by the slayer.
+pub struct DenDestroyedEvent {
+    pub position: Position,
+}
+
+/// Puts the level's dens in the arena and runs them.
+pub struct SnakeDenPlugin;
+
+impl Plugin for SnakeDenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DenDestroyedEvent>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame)
+                    .with_system(setup_dens.after(MatchSetup::LoadLevel)),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(show_den_damage),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(hit_dens.after(SlayerAction::Controls))
+                    .with_system(let_out_snakes.after(SnakeAction::Movement)),
+            );
+    }
+}
+
+/// Spawns a den in `cell`.
+pub(crate) fn spawn_den(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    cell: Position,
+    den: SnakeDen,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(DEN_SIZE)),
+                ..Default::default()
+            },
+            texture: asset_server.load(DEN_TEXTURE),
+            transform: Transform::from_translation(
+                cell_to_world(cell.x as f32, cell.y as f32).extend(0.6),
+            ),
+            ..Default::default()
+        })
+        .insert(cell)
+        .insert(den);
+}
+
+fn setup_dens(mut commands: Commands, asset_server: Res<AssetServer>, level: Res<Level>) {
+    for cell in &level.dens {
+        spawn_den(&mut commands, &asset_server, *cell, SnakeDen::default());
+    }
+}
+
+fn hit_dens(
+    mut commands: Commands,
+    mut swings: EventReader<SwordSwingEvent>,
+    mut destroyed: EventWriter<DenDestroyedEvent>,
+    slayers: Query<(&Transform, &Buffs), With<Slayer>>,
+    mut dens: Query<(Entity, &Position, &mut SnakeDen)>,
+) {
+    for swing in swings.iter() {
+        let (transform, buffs) = match slayers.get(swing.slayer) {
+            Ok(slayer) => slayer,
+            Err(_) => continue,
+        };
+        let cells = sword_cells(transform, buffs, swing.direction);
+        for (entity, position, mut den) in dens.iter_mut() {
+            if den.health == 0 || !cells.contains(position) {
+                continue;
+            }
+            if den.hit() {
+                commands.entity(entity).despawn();
+                destroyed.send(DenDestroyedEvent {
+                    position: *position,
+                });
+            }
+        }
+    }
+}
+
+fn let_out_snakes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    (clock, occupancy): (Res<SimulationClock>, Res<Occupancy<Entity>>),
+    mut rng: ResMut<GameRng>,
+    mut dens: Query<(&Position, &mut SnakeDen)>,
+    den_snakes: Query<(), With<DenSnake>>,
+) {
+    let mut count = den_snakes.iter().count();
+    // New snakes only take up their cells once they are spawned, so keep track of them here
+    let mut taken = Vec::new();
+    for (position, mut den) in dens.iter_mut() {
+        if !den.tick(clock.step()) || count >= MAX_DEN_SNAKES {
+            continue;
+        }
+        if let Some(snake) = place_den_snake(&occupancy, &taken, *position, &mut rng) {
+            taken.extend(snake.body().iter().copied());
+            let head = spawn_snake_entities(&mut commands, &asset_server, snake);
+            commands
+                .entity(head)
+                .insert(SnakeAi::default())
+                .insert(DenSnake);
+            den.let_out();
+            count += 1;
+        }
+    }
+}
+
+/// A snake heading straight out of the den in `den` without touching the `taken` cells, or
+/// `None` if the den is boxed in.
+fn place_den_snake(
+    occupancy: &Occupancy<Entity>,
+    taken: &[Position],
+    den: Position,
+    rng: &mut GameRng,
+) -> Option<Snake> {
+    let arena = occupancy.arena();
+    let first = rng.below(Direction::ALL.len() as u32) as usize;
+    (0..Direction::ALL.len())
+        .map(|turn| Direction::ALL[(first + turn) % Direction::ALL.len()])
+        .find_map(|direction| {
+            let cells: Vec<Position> =
+                iter::successors(Some(den), |cell| Some(cell.step(direction)))
+                    .skip(1)
+                    .take(DEN_SNAKE_LENGTH)
+                    .collect();
+            let clear = cells.iter().all(|cell| {
+                arena.contains(*cell) && occupancy.is_free(*cell) && !taken.contains(cell)
+            });
+            clear.then(|| {
+                Snake::straight(
+                    cells[DEN_SNAKE_LENGTH - 1],
+                    direction,
+                    DEN_SNAKE_LENGTH,
+                    &arena,
+                )
+            })
+        })
+}
+
+fn show_den_damage(mut dens: Query<(&SnakeDen, &mut Sprite), Changed<SnakeDen>>) {
+    for (den, mut sprite) in dens.iter_mut() {
+        let health = den.health as f32 / DEN_HEALTH as f32;
+        sprite.color = Color::rgb(1.0, 0.4 + 0.6 * health, 0.4 + 0.6 * health);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snake_grid::Arena;
+    use crate::{ARENA_HEIGHT, ARENA_WIDTH};
+
+    #[test]
+    fn den_snakes_keep_clear_of_cells_taken_in_the_same_tick() {
+        let occupancy = Occupancy::new(Arena::new(ARENA_WIDTH, ARENA_HEIGHT));
+        let mut rng = GameRng::new(7);
+        let den = Position::new(10, 7);
+        let mut taken = Vec::new();
+        // One snake can head out each way
+        for _ in 0..Direction::ALL.len() {
+            let snake = place_den_snake(&occupancy, &taken, den, &mut rng).unwrap();
+            assert!(snake.body().iter().all(|cell| !taken.contains(cell)));
+            taken.extend(snake.body().iter().copied());
+        }
+        assert!(place_den_snake(&occupancy, &taken, den, &mut rng).is_none());
+    }
+}
@@ -0,0 +1,762 @@
+//! The rules of the snake grid, free of any Bevy types.
+//!
+//! Snakes live on a wrapping grid of cells. Every step, the head moves one cell in
+//! the snake's direction and each segment moves into the cell of the segment in front of it.
+//! The ECS systems in the crate root are thin adapters around the types in this module,
+//! so the rules can be exercised without building an `App`.
+//!
+//! Collisions are answered by an [`Occupancy`] grid, which knows what is in every cell
+//! without walking the bodies of the snakes, and which AI snakes search for their paths.
+//!
+//! # Examples
+//! ```
+//! # use unfair_advantage_lib::snake_grid::{Arena, Direction, Position, Snake};
+//! let arena = Arena::new(4, 4);
+//! let mut snake = Snake::new(
+//!     vec![Position::new(3, 0), Position::new(2, 0)],
+//!     Direction::Right,
+//! );
+//! snake.advance(&arena);
+//! // The head wraps around to the other side of the arena
+//! assert_eq!(snake.body(), &[Position::new(0, 0), Position::new(3, 0)]);
+//! ```
+
+use std::collections::VecDeque;
+
+/// A cell of the arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The neighbouring cell in `direction`, ignoring the arena bounds.
+    pub fn step(self, direction: Direction) -> Self {
+        let (dx, dy) = direction.offset();
+        Self::new(self.x + dx, self.y + dy)
+    }
+}
+
+/// One of the four directions a snake can move in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+
+impl Direction {
+    /// All directions, in clockwise order starting from `Left`.
+    pub const ALL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+    ];
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+        }
+    }
+
+    /// The change in cell coordinates of a single step in this direction.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Self::Left => (-1, 0),
+            Self::Up => (0, 1),
+            Self::Right => (1, 0),
+            Self::Down => (0, -1),
+        }
+    }
+}
+
+/// The bounds of the grid snakes move on.
+///
+/// Cells run from `(0, 0)` to `(width - 1, height - 1)`, and leaving one edge
+/// brings you back in on the opposite edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arena {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Arena {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    /// Is `position` inside the arena?
+    pub fn contains(&self, position: Position) -> bool {
+        position.x >= 0
+            && position.y >= 0
+            && (position.x as u32) < self.width
+            && (position.y as u32) < self.height
+    }
+
+    /// Brings a position that has left the arena back in on the opposite edge.
+    pub fn wrap(&self, position: Position) -> Position {
+        Position::new(
+            position.x.rem_euclid(self.width as i32),
+            position.y.rem_euclid(self.height as i32),
+        )
+    }
+
+    /// The cell one step away from `position` in `direction`, wrapping around the edges.
+    pub fn neighbour(&self, position: Position, direction: Direction) -> Position {
+        self.wrap(position.step(direction))
+    }
+
+    /// Where something sliding from `from` to the adjacent cell `to` is once it has
+    /// covered `t` of the way, in fractional cell coordinates.
+    ///
+    /// Moves across an edge take the short way round: the first half of the slide
+    /// leaves through one edge and the second half comes in through the opposite one.
+    pub fn interpolate(&self, from: Position, to: Position, t: f32) -> (f32, f32) {
+        fn axis(from: i32, to: i32, size: u32, t: f32) -> f32 {
+            let size = size as i32;
+            let mut delta = (to - from).rem_euclid(size);
+            if delta * 2 > size {
+                delta -= size;
+            }
+            let position = from as f32 + delta as f32 * t.clamp(0.0, 1.0);
+            (position + 0.5).rem_euclid(size as f32) - 0.5
+        }
+        (
+            axis(from.x, to.x, self.width, t),
+            axis(from.y, to.y, self.height, t),
+        )
+    }
+
+    /// The direction that leads from `from` to the adjacent cell `to`, if they are adjacent.
+    pub fn direction_between(&self, from: Position, to: Position) -> Option<Direction> {
+        Direction::ALL
+            .iter()
+            .copied()
+            .find(|direction| self.neighbour(from, *direction) == to)
+    }
+}
+
+/// What happened when a snake advanced one cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// The cell the head moved into.
+    pub head: Position,
+    /// The cell the tail moved out of.
+    pub vacated: Position,
+}
+
+/// A snake on the grid: its cells from head to tail and where it is going.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snake {
+    body: VecDeque<Position>,
+    direction: Direction,
+    last_moved: Direction,
+}
+
+impl Snake {
+    /// A snake occupying `body`, listed from head to tail.
+    ///
+    /// # Panics
+    /// Panics if `body` is empty.
+    pub fn new(body: Vec<Position>, direction: Direction) -> Self {
+        assert!(!body.is_empty(), "a snake needs at least a head");
+        Self {
+            body: body.into(),
+            direction,
+            last_moved: direction,
+        }
+    }
+
+    /// A snake as it was captured mid-move, which can only turn the ways that snake could.
+    ///
+    /// # Panics
+    /// Panics if `body` is empty.
+    pub fn resume(body: Vec<Position>, direction: Direction, last_moved: Direction) -> Self {
+        Self {
+            last_moved,
+            ..Self::new(body, direction)
+        }
+    }
+
+    /// A straight snake of `len` cells with its head at `head`, trailing behind it.
+    pub fn straight(head: Position, direction: Direction, len: usize, arena: &Arena) -> Self {
+        let mut body = Vec::with_capacity(len.max(1));
+        let mut cell = head;
+        for _ in 0..len.max(1) {
+            body.push(cell);
+            cell = arena.neighbour(cell, direction.opposite());
+        }
+        Self::new(body, direction)
+    }
+
+    /// The cells of the snake, from head to tail.
+    pub fn body(&self) -> &VecDeque<Position> {
+        &self.body
+    }
+
+    pub fn head(&self) -> Position {
+        self.body[0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.body.len()
+    }
+
+    /// Snakes always have a head, so this is always `false`.
+    pub fn is_empty(&self) -> bool {
+        self.body.is_empty()
+    }
+
+    /// The direction the snake will move in on its next step.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The direction the snake moved in on its last step; it cannot turn back against this.
+    pub fn last_moved(&self) -> Direction {
+        self.last_moved
+    }
+
+    /// Steers the snake for its next step.
+    ///
+    /// Turning back onto the neck is ignored, and `false` is returned.
+    pub fn turn(&mut self, direction: Direction) -> bool {
+        if self.len() > 1 && direction == self.last_moved.opposite() {
+            return false;
+        }
+        self.direction = direction;
+        true
+    }
+
+    /// Moves the snake one cell forward.
+    ///
+    /// This does not look for collisions; use [`Occupancy::advance_snake`] for that.
+    pub fn advance(&mut self, arena: &Arena) -> Step {
+        let head = arena.neighbour(self.head(), self.direction);
+        let vacated = self.body.pop_back().expect("a snake always has a head");
+        self.body.push_front(head);
+        self.last_moved = self.direction;
+        Step { head, vacated }
+    }
+
+    /// The index of the segment at `position`, if any.
+    ///
+    /// This walks the body, so prefer asking an [`Occupancy`] grid whether
+    /// the cell is taken at all first.
+    pub fn segment_at(&self, position: Position) -> Option<usize> {
+        self.body.iter().position(|cell| *cell == position)
+    }
+
+    /// Cuts the snake in two in front of the segment at `index`.
+    ///
+    /// The segments from `index` onwards are returned as a new snake, headed by the segment
+    /// at `index` and moving the way that segment was going. Returns `None` if `index`
+    /// is the head or out of bounds, since there would be nothing to cut off.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::snake_grid::{Arena, Direction, Position, Snake};
+    /// let arena = Arena::new(10, 10);
+    /// let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 4, &arena);
+    /// let tail = snake.split_off(3, &arena).unwrap();
+    /// assert_eq!(snake.len(), 3);
+    /// assert_eq!(tail.body(), &[Position::new(2, 5)]);
+    /// assert_eq!(tail.direction(), Direction::Right);
+    /// ```
+    pub fn split_off(&mut self, index: usize, arena: &Arena) -> Option<Snake> {
+        if index == 0 || index >= self.body.len() {
+            return None;
+        }
+        let direction = arena
+            .direction_between(self.body[index], self.body[index - 1])
+            .unwrap_or(self.last_moved);
+        let tail = self.body.split_off(index);
+        Some(Snake {
+            body: tail,
+            direction,
+            last_moved: direction,
+        })
+    }
+}
+
+/// What a snake ran into when it moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collision<T> {
+    /// The head landed on the snake's own segment at this index.
+    Itself(usize),
+    /// The head landed on a cell held by something else.
+    Other(T),
+}
+
+/// What occupies each cell of an arena.
+///
+/// Answers "what is at (x, y)" in constant time. When things overlap, a cell holds all of
+/// them and reports the last one to move in; each occupant only frees its own claim, so
+/// whatever it was covering shows again once it moves on. Cells can also be blocked by
+/// obstacles, which snakes neither move into nor find paths through.
+///
+/// # Examples
+/// ```
+/// # use unfair_advantage_lib::snake_grid::{Arena, Collision, Direction, Occupancy, Position, Snake};
+/// let arena = Arena::new(10, 10);
+/// let mut occupancy = Occupancy::new(arena);
+/// let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 3, &arena);
+/// occupancy.occupy_snake(&snake, 'a');
+/// occupancy.insert(Position::new(6, 5), 'b');
+///
+/// let (step, collision) = occupancy.advance_snake(&mut snake, 'a').unwrap();
+/// assert_eq!(collision, Some(Collision::Other('b')));
+/// assert_eq!(occupancy.get(step.head), Some('a'));
+/// assert_eq!(occupancy.get(step.vacated), None);
+///
+/// occupancy.block(Position::new(8, 5));
+/// assert!(occupancy.advance_snake(&mut snake, 'a').is_none());
+/// assert_eq!(snake.head(), Position::new(7, 5));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occupancy<T> {
+    arena: Arena,
+    cells: Vec<Vec<T>>,
+    blocked: Vec<bool>,
+}
+
+impl<T: Copy + PartialEq> Occupancy<T> {
+    /// An empty grid covering `arena`.
+    pub fn new(arena: Arena) -> Self {
+        Self {
+            arena,
+            cells: vec![Vec::new(); (arena.width * arena.height) as usize],
+            blocked: vec![false; (arena.width * arena.height) as usize],
+        }
+    }
+
+    pub fn arena(&self) -> Arena {
+        self.arena
+    }
+
+    fn index(&self, position: Position) -> Option<usize> {
+        if self.arena.contains(position) {
+            Some(position.y as usize * self.arena.width as usize + position.x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// What moved into `position` last; cells outside the arena are always empty.
+    pub fn get(&self, position: Position) -> Option<T> {
+        self.index(position)
+            .and_then(|index| self.cells[index].last().copied())
+    }
+
+    /// Is there nothing at all at `position`, neither an occupant nor an obstacle?
+    pub fn is_free(&self, position: Position) -> bool {
+        self.get(position).is_none() && !self.is_blocked(position)
+    }
+
+    /// Fills the cell at `position` with an obstacle.
+    pub fn block(&mut self, position: Position) {
+        if let Some(index) = self.index(position) {
+            self.blocked[index] = true;
+        }
+    }
+
+    pub fn is_blocked(&self, position: Position) -> bool {
+        self.index(position)
+            .map_or(false, |index| self.blocked[index])
+    }
+
+    /// Puts `occupant` in the cell at `position`, on top of whatever is there, returning
+    /// what was reported there before.
+    pub fn insert(&mut self, position: Position, occupant: T) -> Option<T> {
+        let index = self.index(position)?;
+        let previous = self.cells[index].last().copied();
+        self.cells[index].push(occupant);
+        previous
+    }
+
+    /// Empties the cell at `position`, returning what was reported there.
+    pub fn remove(&mut self, position: Position) -> Option<T> {
+        let index = self.index(position)?;
+        let previous = self.cells[index].last().copied();
+        self.cells[index].clear();
+        previous
+    }
+
+    /// Takes back one claim `occupant` has on the cell at `position`, if it has any.
+    pub fn release(&mut self, position: Position, occupant: T) {
+        if let Some(index) = self.index(position) {
+            let cell = &mut self.cells[index];
+            if let Some(claim) = cell.iter().rposition(|held| *held == occupant) {
+                cell.remove(claim);
+            }
+        }
+    }
+
+    /// Empties every cell, obstacles included.
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(Vec::clear);
+        self.blocked.iter_mut().for_each(|blocked| *blocked = false);
+    }
+
+    /// Marks every cell of `snake` as held by `occupant`.
+    pub fn occupy_snake(&mut self, snake: &Snake, occupant: T) {
+        for cell in snake.body() {
+            self.insert(*cell, occupant);
+        }
+    }
+
+    /// Frees the cells of `snake` that `occupant` still holds.
+    pub fn release_snake(&mut self, snake: &Snake, occupant: T) {
+        for cell in snake.body() {
+            self.release(*cell, occupant);
+        }
+    }
+
+    /// Moves `snake`, held by `occupant`, one cell forward and keeps the grid in sync.
+    ///
+    /// Returns the step along with whatever was already in the cell the head moved into.
+    /// Moving into the cell the tail just left is not a collision, and moving into its own
+    /// body is a bite even where another snake lies on top of it. If an obstacle is in the
+    /// way the snake stays where it is, and `None` is returned.
+    pub fn advance_snake(
+        &mut self,
+        snake: &mut Snake,
+        occupant: T,
+    ) -> Option<(Step, Option<Collision<T>>)> {
+        if self.is_blocked(self.arena.neighbour(snake.head(), snake.direction())) {
+            return None;
+        }
+        let step = snake.advance(&self.arena);
+        self.release(step.vacated, occupant);
+        let bites_itself = self
+            .index(step.head)
+            .map_or(false, |index| self.cells[index].contains(&occupant));
+        let previous = self.insert(step.head, occupant);
+        let collision = if bites_itself {
+            snake
+                .body()
+                .iter()
+                .skip(1)
+                .position(|cell| *cell == step.head)
+                .map(|index| Collision::Itself(index + 1))
+        } else {
+            previous.map(Collision::Other)
+        };
+        Some((step, collision))
+    }
+
+    /// The shortest way from `from` to `to` through free cells, as a list of moves.
+    ///
+    /// `to` itself may be occupied, so this can be used to chase whatever is in that cell.
+    /// Returns `None` if `to` cannot be reached, and an empty path if `from == to`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::snake_grid::{Arena, Direction, Occupancy, Position};
+    /// let mut occupancy = Occupancy::new(Arena::new(7, 3));
+    /// occupancy.insert(Position::new(1, 1), ());
+    /// let path = occupancy.find_path(Position::new(0, 1), Position::new(2, 1)).unwrap();
+    /// assert_eq!(path.len(), 4);
+    /// ```
+    pub fn find_path(&self, from: Position, to: Position) -> Option<Vec<Direction>> {
+        let start = self.index(from)?;
+        let goal = self.index(to)?;
+        let mut came_from: Vec<Option<Direction>> = vec![None; self.cells.len()];
+        let mut visited = vec![false; self.cells.len()];
+        visited[start] = true;
+        let mut frontier = VecDeque::from([from]);
+        while let Some(cell) = frontier.pop_front() {
+            if cell == to {
+                let mut path = Vec::new();
+                let mut cell = to;
+                while let Some(direction) = came_from[self.index(cell)?] {
+                    path.push(direction);
+                    cell = self.arena.neighbour(cell, direction.opposite());
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for direction in Direction::ALL {
+                let next = self.arena.neighbour(cell, direction);
+                let index = self.index(next)?;
+                let open = index == goal || self.cells[index].is_empty();
+                if !visited[index] && !self.blocked[index] && open {
+                    visited[index] = true;
+                    came_from[index] = Some(direction);
+                    frontier.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_follows_the_head() {
+        let arena = Arena::new(10, 10);
+        let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 3, &arena);
+        assert!(snake.turn(Direction::Up));
+        let step = snake.advance(&arena);
+        assert_eq!(step.head, Position::new(5, 6));
+        assert_eq!(step.vacated, Position::new(3, 5));
+        assert_eq!(
+            snake.body(),
+            &[
+                Position::new(5, 6),
+                Position::new(5, 5),
+                Position::new(4, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn wraps_on_every_edge() {
+        let arena = Arena::new(3, 2);
+        assert_eq!(arena.wrap(Position::new(-1, 0)), Position::new(2, 0));
+        assert_eq!(arena.wrap(Position::new(3, 0)), Position::new(0, 0));
+        assert_eq!(arena.wrap(Position::new(0, -1)), Position::new(0, 1));
+        assert_eq!(arena.wrap(Position::new(0, 2)), Position::new(0, 0));
+    }
+
+    #[test]
+    fn cannot_reverse_onto_its_neck() {
+        let arena = Arena::new(10, 10);
+        let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 3, &arena);
+        // Turning twice between steps must not sneak a reversal past the check
+        assert!(snake.turn(Direction::Up));
+        assert!(!snake.turn(Direction::Left));
+        assert_eq!(snake.direction(), Direction::Up);
+    }
+
+    #[test]
+    fn resumed_snakes_remember_their_last_move() {
+        let arena = Arena::new(10, 10);
+        let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 3, &arena);
+        snake.turn(Direction::Up);
+        let mut resumed = Snake::resume(
+            snake.body().iter().copied().collect(),
+            snake.direction(),
+            snake.last_moved(),
+        );
+        assert_eq!(resumed, snake);
+        assert!(!resumed.turn(Direction::Left));
+    }
+
+    #[test]
+    fn biting_itself_reports_the_segment() {
+        let arena = Arena::new(10, 10);
+        let mut occupancy = Occupancy::new(arena);
+        let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 5, &arena);
+        occupancy.occupy_snake(&snake, 0);
+        for direction in [Direction::Up, Direction::Left] {
+            snake.turn(direction);
+            assert_eq!(occupancy.advance_snake(&mut snake, 0).unwrap().1, None);
+        }
+        // Coming back down lands on the cell the fourth segment just moved into
+        snake.turn(Direction::Down);
+        let (step, collision) = occupancy.advance_snake(&mut snake, 0).unwrap();
+        assert_eq!(step.head, Position::new(4, 5));
+        assert_eq!(collision, Some(Collision::Itself(4)));
+    }
+
+    #[test]
+    fn biting_itself_under_another_snake_is_still_a_bite() {
+        let arena = Arena::new(10, 10);
+        let mut occupancy = Occupancy::new(arena);
+        let mut snake = Snake::straight(Position::new(5, 5), Direction::Right, 5, &arena);
+        occupancy.occupy_snake(&snake, 0);
+        for direction in [Direction::Up, Direction::Left] {
+            snake.turn(direction);
+            occupancy.advance_snake(&mut snake, 0);
+        }
+        // Another snake lies across the segment about to be bitten
+        occupancy.insert(Position::new(4, 5), 1);
+        snake.turn(Direction::Down);
+        let (_, collision) = occupancy.advance_snake(&mut snake, 0).unwrap();
+        assert_eq!(collision, Some(Collision::Itself(4)));
+        assert_eq!(occupancy.get(Position::new(4, 5)), Some(0));
+    }
+
+    #[test]
+    fn following_the_tail_is_not_a_bite() {
+        let arena = Arena::new(10, 10);
+        let mut snake = Snake::new(
+            vec![
+                Position::new(1, 1),
+                Position::new(1, 0),
+                Position::new(0, 0),
+                Position::new(0, 1),
+            ],
+            Direction::Left,
+        );
+        let mut occupancy = Occupancy::new(arena);
+        occupancy.occupy_snake(&snake, 0);
+        assert_eq!(occupancy.advance_snake(&mut snake, 0).unwrap().1, None);
+        assert_eq!(occupancy.get(Position::new(0, 1)), Some(0));
+    }
+
+    #[test]
+    fn split_tail_moves_the_way_it_was_going() {
+        let arena = Arena::new(10, 10);
+        let mut snake = Snake::new(
+            vec![
+                Position::new(5, 6),
+                Position::new(5, 5),
+                Position::new(4, 5),
+                Position::new(3, 5),
+            ],
+            Direction::Up,
+        );
+        let tail = snake.split_off(2, &arena).unwrap();
+        assert_eq!(snake.body(), &[Position::new(5, 6), Position::new(5, 5)]);
+        assert_eq!(tail.head(), Position::new(4, 5));
+        assert_eq!(tail.direction(), Direction::Right);
+        assert!(snake.split_off(0, &arena).is_none());
+        assert!(snake.split_off(2, &arena).is_none());
+    }
+
+    #[test]
+    fn occupancy_follows_a_moving_snake() {
+        let arena = Arena::new(10, 10);
+        let mut occupancy = Occupancy::new(arena);
+        let mut snake = Snake::straight(Position::new(1, 0), Direction::Left, 4, &arena);
+        occupancy.occupy_snake(&snake, 7);
+        for _ in 0..25 {
+            occupancy.advance_snake(&mut snake, 7);
+            let occupied = (0..10)
+                .flat_map(|x| (0..10).map(move |y| Position::new(x, y)))
+                .filter(|cell| !occupancy.is_free(*cell))
+                .count();
+            assert_eq!(occupied, snake.len());
+            assert!(snake
+                .body()
+                .iter()
+                .all(|cell| occupancy.get(*cell) == Some(7)));
+        }
+        occupancy.release_snake(&snake, 7);
+        assert!(snake.body().iter().all(|cell| occupancy.is_free(*cell)));
+        assert_eq!(occupancy.get(Position::new(-1, 0)), None);
+    }
+
+    #[test]
+    fn only_the_holder_frees_a_cell() {
+        let mut occupancy = Occupancy::new(Arena::new(3, 3));
+        let cell = Position::new(1, 2);
+        assert_eq!(occupancy.insert(cell, 'a'), None);
+        assert_eq!(occupancy.insert(cell, 'b'), Some('a'));
+        occupancy.release(cell, 'a');
+        assert_eq!(occupancy.get(cell), Some('b'));
+        occupancy.release(cell, 'b');
+        assert!(occupancy.is_free(cell));
+    }
+
+    #[test]
+    fn a_crossing_snake_leaves_the_one_below_in_place() {
+        let arena = Arena::new(10, 10);
+        let mut occupancy = Occupancy::new(arena);
+        let lying = Snake::straight(Position::new(5, 5), Direction::Right, 3, &arena);
+        occupancy.occupy_snake(&lying, 'a');
+        let mut crossing = Snake::straight(Position::new(4, 4), Direction::Up, 2, &arena);
+        occupancy.occupy_snake(&crossing, 'b');
+
+        let (_, collision) = occupancy.advance_snake(&mut crossing, 'b').unwrap();
+        assert_eq!(collision, Some(Collision::Other('a')));
+        assert_eq!(occupancy.get(Position::new(4, 5)), Some('b'));
+        for _ in 0..2 {
+            occupancy.advance_snake(&mut crossing, 'b');
+        }
+        assert!(lying
+            .body()
+            .iter()
+            .all(|cell| occupancy.get(*cell) == Some('a')));
+    }
+
+    #[test]
+    fn paths_go_around_snakes_and_across_edges() {
+        let arena = Arena::new(8, 8);
+        let mut occupancy = Occupancy::new(arena);
+        // A wall down the middle, with a gap at the top
+        for y in 0..7 {
+            occupancy.insert(Position::new(4, y), ());
+        }
+        let from = Position::new(3, 0);
+        let to = Position::new(5, 0);
+        let path = occupancy.find_path(from, to).unwrap();
+        // Crossing the bottom edge is quicker than walking up to the gap
+        assert_eq!(
+            path,
+            vec![
+                Direction::Down,
+                Direction::Right,
+                Direction::Right,
+                Direction::Up
+            ]
+        );
+        let end = path
+            .iter()
+            .fold(from, |cell, direction| arena.neighbour(cell, *direction));
+        assert_eq!(end, to);
+        assert_eq!(occupancy.find_path(from, from), Some(vec![]));
+    }
+
+    #[test]
+    fn walled_off_cells_have_no_path() {
+        let mut occupancy = Occupancy::new(Arena::new(8, 8));
+        for direction in Direction::ALL {
+            occupancy.insert(Position::new(4, 4).step(direction), ());
+        }
+        assert_eq!(
+            occupancy.find_path(Position::new(0, 0), Position::new(4, 4)),
+            None
+        );
+        assert_eq!(
+            occupancy.find_path(Position::new(0, 0), Position::new(9, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn paths_never_lead_through_obstacles() {
+        let mut occupancy: Occupancy<()> = Occupancy::new(Arena::new(3, 1));
+        let obstacle = Position::new(1, 0);
+        occupancy.block(obstacle);
+        assert!(!occupancy.is_free(obstacle));
+        assert_eq!(occupancy.get(obstacle), None);
+        // Around the edge is the only way left
+        assert_eq!(
+            occupancy.find_path(Position::new(0, 0), Position::new(2, 0)),
+            Some(vec![Direction::Left])
+        );
+        assert_eq!(occupancy.find_path(Position::new(0, 0), obstacle), None);
+
+        occupancy.clear();
+        assert!(occupancy.is_free(obstacle));
+    }
+
+    #[test]
+    fn sliding_across_an_edge_takes_the_short_way() {
+        let arena = Arena::new(28, 14);
+        let inside = arena.interpolate(Position::new(5, 3), Position::new(5, 4), 0.25);
+        assert_eq!(inside, (5.0, 3.25));
+        let from = Position::new(27, 0);
+        let to = Position::new(0, 0);
+        assert_eq!(arena.interpolate(from, to, 0.25), (27.25, 0.0));
+        assert_eq!(arena.interpolate(from, to, 0.75), (-0.25, 0.0));
+        assert_eq!(arena.interpolate(to, from, 0.75), (27.25, 0.0));
+        assert_eq!(arena.interpolate(from, to, 2.0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,528 @@
+//! The survival campaign: the slayer against numbered waves of snakes that steer themselves.
+//!
+//! Each [`Wave`] sets how many snakes come, how long they are, how they hunt and how fast
+//! they move, and which arena they come in. A snake cut down to its head is finished off,
+//! and a wave is cleared once every snake and every den in the arena is gone. An
+//! intermission screen then counts down to the next wave.
+//!
+//! The wave to carry on from and the best wave cleared so far are kept in a
+//! [`SurvivalProgress`] file, so a campaign can be picked up again from the main menu.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::level::Level;
+use crate::replay::ReplayPlayback;
+use crate::rng::GameRng;
+use crate::snake_ai::{AiProfile, SnakeAi};
+use crate::snake_grid::{Arena, Direction, Position, Snake};
+use crate::{
+    spawn_snake_entities, world_to_cell, AppState, ArenaClearedEvent, MatchConfig, MatchMode,
+    MatchSetup,
+};
+
+/// Where survival progress is kept unless [`SurvivalProgressPath`] says otherwise.
+pub const SURVIVAL_PROGRESS_PATH: &str = "saves/survival.txt";
+/// How many lives the slayer gets on top of their first in each wave.
+pub const SURVIVAL_EXTRA_LIVES: u8 = 2;
+
+/// The waves that were laid out by hand, as their arena, the milliseconds between snake moves
+/// and the length and profile of each snake. Later waves build on the last of these.
+const WAVES: &[(&str, u64, &[(usize, AiProfile)])] = &[
+    (
+        "open",
+        450,
+        &[(4, AiProfile::Wanderer), (4, AiProfile::Wanderer)],
+    ),
+    (
+        "open",
+        420,
+        &[(5, AiProfile::Hunter), (4, AiProfile::Wanderer)],
+    ),
+    (
+        "pillars",
+        400,
+        &[
+            (5, AiProfile::Hunter),
+            (5, AiProfile::Ambusher),
+            (4, AiProfile::Wanderer),
+        ],
+    ),
+    (
+        "pillars",
+        370,
+        &[
+            (6, AiProfile::Hunter),
+            (6, AiProfile::Hunter),
+            (5, AiProfile::Ambusher),
+        ],
+    ),
+    (
+        "nest",
+        350,
+        &[(6, AiProfile::Hunter), (5, AiProfile::Ambusher)],
+    ),
+];
+/// How much quicker the snakes get with each wave after the hand-made ones, in milliseconds.
+const INTERVAL_STEP: u64 = 15;
+/// The shortest wait between snake moves in any wave, in milliseconds.
+const MIN_INTERVAL: u64 = 200;
+/// How long the hunters added in later waves are.
+const EXTRA_HUNTER_LENGTH: usize = 6;
+/// Later waves stop adding snakes once they have this many.
+const MAX_WAVE_SNAKES: usize = 8;
+/// How close to the slayer's spawn, in moves, no wave snake may start.
+const SPAWN_CLEARANCE: i32 = 5;
+/// How many cells are tried for each wave snake before it is left out.
+const PLACEMENT_ATTEMPTS: usize = 200;
+/// How long the intermission between waves lasts, in seconds.
+const INTERMISSION_SECONDS: f32 = 5.0;
+
+/// One snake of a [`Wave`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaveSnake {
+    pub length: usize,
+    pub profile: AiProfile,
+}
+
+/// Everything that sets one wave of the campaign apart from the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wave {
+    pub number: u32,
+    /// The name of the [`Level`] the wave is played in.
+    pub arena: &'static str,
+    pub snakes: Vec<WaveSnake>,
+    /// How long the snakes wait between moves.
+    pub snake_interval: Duration,
+}
+
+/// Wave `number` of the campaign, counting from 1.
+///
+/// # Examples
+/// ```
+/// # use unfair_advantage_lib::survival::wave;
+/// let first = wave(1);
+/// let later = wave(9);
+/// assert!(later.snakes.len() > first.snakes.len());
+/// assert!(later.snake_interval < first.snake_interval);
+/// ```
+pub fn wave(number: u32) -> Wave {
+    let number = number.max(1);
+    let last = WAVES.len() as u32;
+    let (arena, interval, snakes) = WAVES[(number.min(last) - 1) as usize];
+    let beyond = number.saturating_sub(last);
+    let mut snakes: Vec<WaveSnake> = snakes
+        .iter()
+        .map(|&(length, profile)| WaveSnake { length, profile })
+        .collect();
+    // One more hunter for each wave past the hand-made ones
+    let count = (snakes.len() + beyond as usize).min(MAX_WAVE_SNAKES);
+    snakes.resize(
+        count.max(snakes.len()),
+        WaveSnake {
+            length: EXTRA_HUNTER_LENGTH,
+            profile: AiProfile::Hunter,
+        },
+    );
+    let interval = interval
+        .saturating_sub(beyond as u64 * INTERVAL_STEP)
+        .max(MIN_INTERVAL);
+    Wave {
+        number,
+        arena,
+        snakes,
+        snake_interval: Duration::from_millis(interval),
+    }
+}
+
+/// How far the player has got in the survival campaign.
+///
+/// On disk this is a text file with a `wave` line for the wave to carry on from and a
+/// `best` line for the best wave cleared, like `wave 4` and `best 6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurvivalProgress {
+    /// The wave the campaign carries on from.
+    pub wave: u32,
+    /// The highest wave ever cleared, or 0 if none has been.
+    pub best_wave: u32,
+}
+
+impl Default for SurvivalProgress {
+    fn default() -> Self {
+        Self {
+            wave: 1,
+            best_wave: 0,
+        }
+    }
+}
+
+impl SurvivalProgress {
+    /// Reads the progress stored at `path`, starting afresh if there is none or it cannot be
+    /// read.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => text.parse().unwrap(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(error) => {
+                warn!(
+                    "Could not read survival progress from {}: {}",
+                    path.display(),
+                    error
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// Writes the progress to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    /// Moves the campaign on past `wave`, which has just been cleared.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::survival::SurvivalProgress;
+    /// let mut progress = SurvivalProgress { wave: 2, best_wave: 5 };
+    /// progress.clear(2);
+    /// assert_eq!(progress, SurvivalProgress { wave: 3, best_wave: 5 });
+    /// ```
+    pub fn clear(&mut self, wave: u32) {
+        self.wave = wave + 1;
+        self.best_wave = self.best_wave.max(wave);
+    }
+}
+
+impl fmt::Display for SurvivalProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "wave {}", self.wave)?;
+        writeln!(f, "best {}", self.best_wave)
+    }
+}
+
+impl std::str::FromStr for SurvivalProgress {
+    type Err = std::convert::Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut progress = Self::default();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let mut words = line.split_whitespace();
+            let field = words.next();
+            let value = words.next().and_then(|value| value.parse::<u32>().ok());
+            match (field, value, words.next()) {
+                (Some("wave"), Some(wave), None) if wave > 0 => progress.wave = wave,
+                (Some("best"), Some(best), None) => progress.best_wave = best,
+                _ => warn!("Skipping unreadable survival progress line {:?}", line),
+            }
+        }
+        Ok(progress)
+    }
+}
+
+/// Where survival progress is kept; `None` stops it being recorded.
+pub struct SurvivalProgressPath(pub Option<PathBuf>);
+
+impl Default for SurvivalProgressPath {
+    fn default() -> Self {
+        Self(Some(PathBuf::from(SURVIVAL_PROGRESS_PATH)))
+    }
+}
+
+/// Counts down to the next wave on the intermission screen.
+struct Intermission(Timer);
+
+#[derive(Component)]
+struct OnIntermissionScreen;
+
+/// The intermission line that counts down to the next wave.
+#[derive(Component)]
+struct CountdownLabel;
+
+/// Runs survival matches and the intermissions between their waves.
+pub struct SurvivalPlugin;
+
+impl Plugin for SurvivalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SurvivalProgressPath>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame).with_system(
+                    spawn_wave_snakes
+                        .after(MatchSetup::SeedRng)
+                        .after(MatchSetup::LoadLevel),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(start_intermission),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::WaveIntermission)
+                    .with_system(setup_intermission_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::WaveIntermission).with_system(run_intermission),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::WaveIntermission)
+                    .with_system(cleanup_intermission_screen),
+            );
+    }
+}
+
+fn spawn_wave_snakes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    (level, config, arena): (Res<Level>, Res<MatchConfig>, Res<Arena>),
+    mut rng: ResMut<GameRng>,
+) {
+    if config.mode != MatchMode::Survival {
+        return;
+    }
+    let spawn = world_to_cell(level.slayer_spawn);
+    let mut taken: HashSet<Position> = level.obstacles.iter().chain(&level.dens).copied().collect();
+    for wave_snake in wave(config.wave).snakes {
+        let snake = match place_wave_snake(&arena, spawn, &taken, wave_snake.length, &mut rng) {
+            Some(snake) => snake,
+            None => {
+                warn!("No room for a snake of length {}", wave_snake.length);
+                continue;
+            }
+        };
+        taken.extend(snake.body().iter().copied());
+        let head = spawn_snake_entities(&mut commands, &asset_server, snake);
+        commands
+            .entity(head)
+            .insert(SnakeAi::new(wave_snake.profile));
+    }
+}
+
+/// A straight snake of `length` in free cells well away from the slayer's `spawn`, or `None`
+/// if no such place turned up.
+fn place_wave_snake(
+    arena: &Arena,
+    spawn: Position,
+    taken: &HashSet<Position>,
+    length: usize,
+    rng: &mut GameRng,
+) -> Option<Snake> {
+    (0..PLACEMENT_ATTEMPTS).find_map(|_| {
+        let head = Position::new(
+            rng.below(arena.width) as i32,
+            rng.below(arena.height) as i32,
+        );
+        let direction = *rng.pick(&Direction::ALL)?;
+        let cells: Vec<Position> =
+            iter::successors(Some(head), |cell| Some(cell.step(direction.opposite())))
+                .take(length)
+                .collect();
+        let fits = cells.iter().all(|cell| {
+            arena.contains(*cell)
+                && !taken.contains(cell)
+                && (cell.x - spawn.x).abs() + (cell.y - spawn.y).abs() > SPAWN_CLEARANCE
+        });
+        fits.then(|| Snake::straight(head, direction, length, arena))
+    })
+}
+
+fn start_intermission(
+    mut cleared: EventReader<ArenaClearedEvent>,
+    mut state: ResMut<State<AppState>>,
+    config: Res<MatchConfig>,
+    progress_path: Res<SurvivalProgressPath>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if cleared.iter().count() == 0 || config.mode != MatchMode::Survival {
+        return;
+    }
+    // A replay ends with the wave it recorded
+    if playback.is_some() {
+        return;
+    }
+    let wave = config.wave;
+    if let Some(path) = &progress_path.0 {
+        let mut progress = SurvivalProgress::load(path);
+        progress.clear(wave);
+        if let Err(error) = progress.save(path) {
+            error!(
+                "Could not save survival progress to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+    state.set(AppState::WaveIntermission).unwrap();
+}
+
+fn setup_intermission_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<MatchConfig>,
+    progress_path: Res<SurvivalProgressPath>,
+) {
+    let best_wave = match &progress_path.0 {
+        Some(path) => SurvivalProgress::load(path).best_wave,
+        None => 0,
+    }
+    .max(config.wave);
+    commands.insert_resource(Intermission(Timer::from_seconds(
+        INTERMISSION_SECONDS,
+        false,
+    )));
+    let style = TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size: 40.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OnIntermissionScreen);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                margin: Rect::all(Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(30.0)),
+                ..Default::default()
+            },
+            color: Color::TEAL.into(),
+            ..Default::default()
+        })
+        .insert(OnIntermissionScreen)
+        .with_children(|parent| {
+            for line in [
+                format!("Wave {} cleared!", config.wave),
+                format!("Best wave: {}", best_wave),
+            ] {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(line, style.clone(), Default::default()),
+                    ..Default::default()
+                });
+            }
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(CountdownLabel);
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Press Enter to start now",
+                    style.clone(),
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn run_intermission(
+    time: Res<Time>,
+    mut input: ResMut<Input<KeyCode>>,
+    mut intermission: ResMut<Intermission>,
+    mut state: ResMut<State<AppState>>,
+    mut config: ResMut<MatchConfig>,
+    mut labels: Query<&mut Text, With<CountdownLabel>>,
+) {
+    intermission.0.tick(time.delta());
+    let next = config.wave + 1;
+    let remaining = intermission
+        .0
+        .duration()
+        .saturating_sub(intermission.0.elapsed());
+    for mut text in labels.iter_mut() {
+        text.sections[0].value = format!(
+            "Wave {} starts in {}",
+            next,
+            remaining.as_secs_f32().ceil() as u32
+        );
+    }
+    let skip = input.just_pressed(KeyCode::Return);
+    if !skip && !intermission.0.finished() {
+        return;
+    }
+    // The next wave would otherwise see the same press
+    input.reset(KeyCode::Return);
+    *config = MatchConfig::survival(config.seed, next);
+    state.set(AppState::InOnePlayerGame).unwrap();
+}
+
+fn cleanup_intermission_screen(
+    mut commands: Commands,
+    screen: Query<Entity, With<OnIntermissionScreen>>,
+) {
+    commands.remove_resource::<Intermission>();
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_get_harder() {
+        for number in 1..20 {
+            let this = wave(number);
+            let next = wave(number + 1);
+            assert!(next.snakes.len() >= this.snakes.len() || number < WAVES.len() as u32);
+            assert!(next.snake_interval <= this.snake_interval);
+            assert!(Level::built_in(this.arena).is_some());
+        }
+        assert_eq!(wave(0), wave(1));
+        assert_eq!(wave(100).snakes.len(), MAX_WAVE_SNAKES);
+        assert_eq!(
+            wave(100).snake_interval,
+            Duration::from_millis(MIN_INTERVAL)
+        );
+    }
+
+    #[test]
+    fn progress_round_trips_through_text() {
+        let progress = SurvivalProgress {
+            wave: 4,
+            best_wave: 6,
+        };
+        let text = progress.to_string();
+        assert_eq!(text, "wave 4\nbest 6\n");
+        assert_eq!(text.parse::<SurvivalProgress>().unwrap(), progress);
+
+        let damaged: SurvivalProgress = "wave zero\n\nbest 3\nwave 0\n".parse().unwrap();
+        assert_eq!(
+            damaged,
+            SurvivalProgress {
+                wave: 1,
+                best_wave: 3
+            }
+        );
+    }
+
+    #[test]
+    fn wave_snakes_stay_clear_of_the_slayer() {
+        let arena = Arena::new(28, 14);
+        let spawn = Position::new(14, 7);
+        let mut rng = GameRng::new(7);
+        let mut taken = HashSet::new();
+        for _ in 0..MAX_WAVE_SNAKES {
+            let snake = place_wave_snake(&arena, spawn, &taken, 6, &mut rng).unwrap();
+            for cell in snake.body() {
+                assert!(arena.contains(*cell));
+                assert!(taken.insert(*cell));
+                assert!((cell.x - spawn.x).abs() + (cell.y - spawn.y).abs() > SPAWN_CLEARANCE);
+            }
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! Time attack: the slayer against the clock, to clear the level's snake as fast as they can.
+//!
+//! The timer counts simulation ticks, so a run takes exactly as long in its replay as it did
+//! when it was played. Each snake finished off adds a split time, shown against the same split
+//! of the personal best. The personal best of each level is kept on disk along with where the
+//! slayer was on every tick of it, which later runs draw as a see-through ghost.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::handicap::MatchOverEvent;
+use crate::netplay::Side;
+use crate::replay::ReplayPlayback;
+use crate::simulation::{
+    in_state, SimulationClock, SimulationStage, SimulationSystem, TICKS_PER_SECOND,
+};
+use crate::{
+    AppState, ArenaClearedEvent, MatchConfig, MatchMode, Slayer, SnakeAction, SnakeKilledEvent,
+};
+
+/// How many lives the slayer gets on top of their first in a time attack.
+pub const TIME_ATTACK_EXTRA_LIVES: u8 = 2;
+/// Where personal bests are kept unless [`TimeAttackDirectory`] says otherwise.
+pub const TIME_ATTACK_DIRECTORY: &str = "saves/time_attack";
+
+const HEADER: &str = "unfair-advantage-best";
+const FORMAT_VERSION: u16 = 1;
+/// How see-through the ghost of the personal best is drawn.
+const GHOST_ALPHA: f32 = 0.4;
+/// How many of the latest split times the HUD shows.
+const SHOWN_SPLITS: usize = 5;
+
+/// A number of ticks as a time with milliseconds, like `12.345` or `1:02.345`.
+///
+/// # Examples
+/// ```
+/// # use unfair_advantage_lib::time_attack::format_ticks;
+/// assert_eq!(format_ticks(90), "1.500");
+/// assert_eq!(format_ticks(3725), "1:02.083");
+/// ```
+pub fn format_ticks(ticks: u64) -> String {
+    let millis = ticks * 1000 / TICKS_PER_SECOND as u64;
+    let (minutes, seconds, millis) = (millis / 60_000, millis / 1000 % 60, millis % 1000);
+    if minutes > 0 {
+        format!("{}:{:02}.{:03}", minutes, seconds, millis)
+    } else {
+        format!("{}.{:03}", seconds, millis)
+    }
+}
+
+/// The fastest clear of a level.
+///
+/// On disk this is a text file with the time and the splits in ticks, followed by the
+/// slayer's position on every tick as `x,y` pairs:
+///
+/// ```text
+/// unfair-advantage-best 1
+/// time 754
+/// splits 300 620 754
+/// ghost 0,0 0.5,-1.25 1,-2.5
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PersonalBest {
+    /// How many ticks the clear took.
+    pub ticks: u64,
+    /// The tick on which each snake was finished off.
+    pub splits: Vec<u64>,
+    /// Where the slayer was on each tick, starting with the first.
+    pub ghost: Vec<Vec2>,
+}
+
+impl PersonalBest {
+    /// The file the personal best for `level` is kept in.
+    pub fn path(directory: &Path, level: &str) -> PathBuf {
+        directory.join(format!("{}.best", level))
+    }
+
+    /// Reads the personal best stored at `path`, which is `None` if there is none yet.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_text(&text)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a personal best")),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the personal best to `path`, creating its directory if needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    /// Writes the personal best out in its text form.
+    ///
+    /// # Examples
+    /// ```
+    /// # use bevy::math::Vec2;
+    /// # use unfair_advantage_lib::time_attack::PersonalBest;
+    /// let best = PersonalBest {
+    ///     ticks: 3,
+    ///     splits: vec![2, 3],
+    ///     ghost: vec![Vec2::ZERO, Vec2::new(0.5, -1.0), Vec2::new(1.0, -2.0)],
+    /// };
+    /// assert_eq!(PersonalBest::from_text(&best.to_text()), Some(best));
+    /// ```
+    pub fn to_text(&self) -> String {
+        let splits: Vec<String> = self.splits.iter().map(u64::to_string).collect();
+        let ghost: Vec<String> = self
+            .ghost
+            .iter()
+            .map(|point| format!("{},{}", point.x, point.y))
+            .collect();
+        format!(
+            "{} {}\ntime {}\nsplits {}\nghost {}\n",
+            HEADER,
+            FORMAT_VERSION,
+            self.ticks,
+            splits.join(" "),
+            ghost.join(" ")
+        )
+    }
+
+    /// Reads a personal best from its text form, or `None` if it cannot be understood.
+    pub fn from_text(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        let mut header = lines.next()?.split_whitespace();
+        let version: u16 = match (header.next(), header.next()) {
+            (Some(HEADER), Some(version)) => version.parse().ok()?,
+            _ => return None,
+        };
+        if version > FORMAT_VERSION {
+            return None;
+        }
+        let mut best = Self::default();
+        let mut timed = false;
+        for line in lines {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("time") => {
+                    best.ticks = words.next()?.parse().ok()?;
+                    timed = true;
+                }
+                Some("splits") => {
+                    best.splits = words.map(|word| word.parse().ok()).collect::<Option<_>>()?;
+                }
+                Some("ghost") => {
+                    best.ghost = words.map(parse_point).collect::<Option<_>>()?;
+                }
+                None => {}
+                Some(_) => return None,
+            }
+        }
+        timed.then(|| best)
+    }
+}
+
+fn parse_point(word: &str) -> Option<Vec2> {
+    let (x, y) = word.split_once(',')?;
+    Some(Vec2::new(x.parse().ok()?, y.parse().ok()?))
+}
+
+/// Where personal bests are kept; `None` stops them being recorded.
+pub struct TimeAttackDirectory(pub Option<PathBuf>);
+
+impl Default for TimeAttackDirectory {
+    fn default() -> Self {
+        Self(Some(PathBuf::from(TIME_ATTACK_DIRECTORY)))
+    }
+}
+
+/// How the current time attack run is going.
+#[derive(Debug, Default)]
+pub struct TimeAttackRun {
+    /// The tick on which each snake was finished off so far.
+    pub splits: Vec<u64>,
+    /// The tick the arena was cleared on, once it has been.
+    pub finished: Option<u64>,
+    /// The personal best for the level, as it was when the run started.
+    pub best: Option<PersonalBest>,
+    /// Did this run set a new personal best?
+    pub new_best: bool,
+    ghost: Vec<Vec2>,
+}
+
+#[derive(Component)]
+struct OnTimeAttackHud;
+
+#[derive(Component)]
+struct TimerLabel;
+
+#[derive(Component)]
+struct SplitsLabel;
+
+/// The see-through slayer that runs the personal best again.
+#[derive(Component)]
+struct Ghost;
+
+/// Times time attack runs, shows them on a HUD and keeps the personal best of each level.
+pub struct TimeAttackPlugin;
+
+impl Plugin for TimeAttackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeAttackDirectory>()
+            .init_resource::<TimeAttackRun>()
+            .add_system_set(SystemSet::on_enter(AppState::InOnePlayerGame).with_system(start_run))
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame)
+                    .with_system(update_time_attack_hud)
+                    .with_system(move_ghost)
+                    .with_system(finish_run),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_time_attack_hud),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(time_run.after(SnakeAction::Split).after(SnakeAction::Death)),
+            );
+    }
+}
+
+fn start_run(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    (config, directory): (Res<MatchConfig>, Res<TimeAttackDirectory>),
+    mut run: ResMut<TimeAttackRun>,
+) {
+    *run = TimeAttackRun::default();
+    if config.mode != MatchMode::TimeAttack {
+        return;
+    }
+    if let Some(directory) = &directory.0 {
+        let path = PersonalBest::path(directory, &config.level);
+        run.best = PersonalBest::load(&path).unwrap_or_else(|error| {
+            warn!(
+                "Could not read the personal best from {}: {}",
+                path.display(),
+                error
+            );
+            None
+        });
+    }
+    if let Some(start) = run.best.as_ref().and_then(|best| best.ghost.first()) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 1.0, 1.0, GHOST_ALPHA),
+                    ..Default::default()
+                },
+                texture: asset_server.load("slayer.png"),
+                transform: Transform::from_translation(start.extend(0.9)),
+                ..Default::default()
+            })
+            .insert(Ghost);
+    }
+
+    let style = TextStyle {
+        font: asset_server.load("fonts/GoMono-Bold.ttf"),
+        font_size: 30.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexStart,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(OnTimeAttackHud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(TimerLabel);
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section("", style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(SplitsLabel);
+        });
+}
+
+fn time_run(
+    clock: Res<SimulationClock>,
+    config: Res<MatchConfig>,
+    mut run: ResMut<TimeAttackRun>,
+    mut killed: EventReader<SnakeKilledEvent>,
+    mut cleared: EventReader<ArenaClearedEvent>,
+    slayers: Query<&Transform, With<Slayer>>,
+) {
+    if config.mode != MatchMode::TimeAttack || run.finished.is_some() {
+        return;
+    }
+    // Once out of lives the slayer stays where they fell
+    let position = slayers
+        .iter()
+        .next()
+        .map(|transform| transform.translation.truncate())
+        .or_else(|| run.ghost.last().copied())
+        .unwrap_or_default();
+    run.ghost.push(position);
+    for _ in killed.iter() {
+        run.splits.push(clock.tick());
+    }
+    if cleared.iter().next().is_some() {
+        run.finished = Some(clock.tick());
+    }
+}
+
+fn finish_run(
+    mut cleared: EventReader<ArenaClearedEvent>,
+    mut match_over: EventWriter<MatchOverEvent>,
+    (config, directory): (Res<MatchConfig>, Res<TimeAttackDirectory>),
+    mut run: ResMut<TimeAttackRun>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if cleared.iter().count() == 0 || config.mode != MatchMode::TimeAttack {
+        return;
+    }
+    let ticks = match run.finished {
+        Some(ticks) => ticks,
+        None => return,
+    };
+    match_over.send(MatchOverEvent {
+        winner: Side::Slayer,
+    });
+    // A run resumed from a save is missing the start of its ghost
+    let complete = run.ghost.len() as u64 == ticks;
+    let faster = run.best.as_ref().map_or(true, |best| ticks < best.ticks);
+    if !complete || !faster || playback.is_some() {
+        return;
+    }
+    run.new_best = true;
+    let best = PersonalBest {
+        ticks,
+        splits: run.splits.clone(),
+        ghost: std::mem::take(&mut run.ghost),
+    };
+    if let Some(directory) = &directory.0 {
+        let path = PersonalBest::path(directory, &config.level);
+        if let Err(error) = best.save(&path) {
+            error!(
+                "Could not save the personal best to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+fn update_time_attack_hud(
+    clock: Res<SimulationClock>,
+    run: Res<TimeAttackRun>,
+    mut timers: Query<&mut Text, (With<TimerLabel>, Without<SplitsLabel>)>,
+    mut splits: Query<&mut Text, (With<SplitsLabel>, Without<TimerLabel>)>,
+) {
+    let ticks = run.finished.unwrap_or_else(|| clock.tick());
+    for mut text in timers.iter_mut() {
+        text.sections[0].value = format!("Time {}", format_ticks(ticks));
+    }
+    let best_splits = run.best.as_ref().map_or(&[][..], |best| &best.splits[..]);
+    let lines: Vec<String> = run
+        .splits
+        .iter()
+        .enumerate()
+        .skip(run.splits.len().saturating_sub(SHOWN_SPLITS))
+        .map(|(index, split)| {
+            let mut line = format!("Snake {} {}", index + 1, format_ticks(*split));
+            if let Some(best) = best_splits.get(index) {
+                let (sign, difference) = if split < best {
+                    ('-', best - split)
+                } else {
+                    ('+', split - best)
+                };
+                line.push_str(&format!(" {}{}", sign, format_ticks(difference)));
+            }
+            line
+        })
+        .collect();
+    for mut text in splits.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+fn move_ghost(
+    clock: Res<SimulationClock>,
+    run: Res<TimeAttackRun>,
+    mut ghosts: Query<&mut Transform, With<Ghost>>,
+) {
+    let ghost = match &run.best {
+        Some(best) if !best.ghost.is_empty() => &best.ghost,
+        _ => return,
+    };
+    // The ghost stops where the personal best ended
+    let index = (clock.tick().saturating_sub(1) as usize).min(ghost.len() - 1);
+    for mut transform in ghosts.iter_mut() {
+        transform.translation = ghost[index].extend(transform.translation.z);
+    }
+}
+
+fn cleanup_time_attack_hud(
+    mut commands: Commands,
+    hud: Query<Entity, Or<(With<OnTimeAttackHud>, With<Ghost>)>>,
+) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_personal_bests_are_rejected() {
+        assert_eq!(PersonalBest::from_text(""), None);
+        assert_eq!(PersonalBest::from_text("unfair-advantage-best 1\n"), None);
+        assert_eq!(
+            PersonalBest::from_text("unfair-advantage-best 2\ntime 5\n"),
+            None
+        );
+        assert_eq!(
+            PersonalBest::from_text("unfair-advantage-best 1\ntime 5\nghost 1;2\n"),
+            None
+        );
+        assert_eq!(
+            PersonalBest::from_text("unfair-advantage-best 1\ntime 5\nsplits\nghost\n"),
+            Some(PersonalBest {
+                ticks: 5,
+                ..Default::default()
+            })
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! The tutorial: a scripted match that teaches new players how the slayer is steered.
+//!
+//! It walks through running, jumping, swinging the sword, aiming it with the direction keys
+//! and cutting a snake in two, one [`TutorialStep`] at a time. A prompt at the top of the
+//! screen says what to do, and moves on to the next step once the player has done it. The
+//! snake holds still until it is time to cut it and then only crawls, and snake heads pass
+//! harmlessly through the slayer, so nothing can go wrong while the controls are learnt.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::handicap::{MatchOutcome, MatchOverEvent};
+use crate::netplay::Side;
+use crate::simulation::{in_state, SimulationStage, SimulationSystem, TickInput, SLAYER_KEYS};
+use crate::snake_grid::Direction;
+use crate::{
+    AppState, MatchConfig, MatchMode, SlayerAction, SnakeAction, SnakeSeveredEvent, SnakeTimer,
+    SwordSwingEvent,
+};
+
+/// The level the tutorial is played in, which has no dens to let out more snakes.
+pub const TUTORIAL_LEVEL: &str = "open";
+/// How long the tutorial's snake waits between moves once it starts crawling.
+pub const TUTORIAL_SNAKE_INTERVAL: Duration = Duration::from_millis(900);
+
+/// What the tutorial is teaching, in the order it is taught.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TutorialStep {
+    /// Run both ways.
+    Run,
+    Jump,
+    /// Swing the sword the way the slayer faces.
+    Attack,
+    /// Swing the sword in every direction, aimed with the direction keys.
+    Aim,
+    /// Cut a segment off the snake.
+    Sever,
+    Complete,
+}
+
+impl TutorialStep {
+    /// The step after this one.
+    pub fn next(self) -> Self {
+        match self {
+            TutorialStep::Run => TutorialStep::Jump,
+            TutorialStep::Jump => TutorialStep::Attack,
+            TutorialStep::Attack => TutorialStep::Aim,
+            TutorialStep::Aim => TutorialStep::Sever,
+            TutorialStep::Sever | TutorialStep::Complete => TutorialStep::Complete,
+        }
+    }
+}
+
+/// How far the player has got through the tutorial.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tutorial {
+    pub step: TutorialStep,
+    /// The ways the slayer has run so far in the [`TutorialStep::Run`] step.
+    ran: Vec<Direction>,
+    /// The directions the sword has been swung in so far in the [`TutorialStep::Aim`] step.
+    aimed: Vec<Direction>,
+}
+
+impl Default for Tutorial {
+    fn default() -> Self {
+        Self {
+            step: TutorialStep::Run,
+            ran: Vec::new(),
+            aimed: Vec::new(),
+        }
+    }
+}
+
+impl Tutorial {
+    /// What the prompt for the current step says, naming the first slayer's keys.
+    ///
+    /// # Examples
+    /// ```
+    /// # use unfair_advantage_lib::tutorial::Tutorial;
+    /// assert_eq!(Tutorial::default().prompt(), "Press A and D to run left and right");
+    /// ```
+    pub fn prompt(&self) -> String {
+        let keys = SLAYER_KEYS[0];
+        match self.step {
+            TutorialStep::Run => format!(
+                "Press {:?} and {:?} to run left and right",
+                keys.left, keys.right
+            ),
+            TutorialStep::Jump => format!("Press {:?} to jump", keys.up),
+            TutorialStep::Attack => format!("Press {:?} to swing your sword", keys.attack),
+            TutorialStep::Aim => {
+                let left: Vec<&str> = Direction::ALL
+                    .iter()
+                    .filter(|direction| !self.aimed.contains(direction))
+                    .map(|direction| direction_name(*direction))
+                    .collect();
+                format!(
+                    "Hold {:?}, {:?}, {:?} or {:?} as you press {:?} to aim the sword\nStill to swing: {}",
+                    keys.up,
+                    keys.left,
+                    keys.down,
+                    keys.right,
+                    keys.attack,
+                    left.join(", ")
+                )
+            }
+            TutorialStep::Sever => "Hit the snake behind its head to cut it in two".to_string(),
+            TutorialStep::Complete => "Well done!".to_string(),
+        }
+    }
+
+    /// Moves on to the next step.
+    fn advance(&mut self) {
+        self.step = self.step.next();
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+#[derive(Component)]
+struct OnTutorialHud;
+
+#[derive(Component)]
+struct PromptLabel;
+
+/// Runs the tutorial's steps and shows their prompts.
+pub struct TutorialPlugin;
+
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tutorial>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::InOnePlayerGame).with_system(start_tutorial),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InOnePlayerGame).with_system(update_prompt),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InOnePlayerGame).with_system(cleanup_tutorial_hud),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(in_state(AppState::InOnePlayerGame))
+                    .label(SimulationSystem::Gameplay)
+                    .after(SimulationSystem::Input)
+                    .with_system(hold_snake.before(SnakeAction::Movement))
+                    .with_system(
+                        follow_steps
+                            .after(SlayerAction::Controls)
+                            .after(SnakeAction::Split)
+                            .after(SnakeAction::Death),
+                    ),
+            );
+    }
+}
+
+fn start_tutorial(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<MatchConfig>,
+    mut tutorial: ResMut<Tutorial>,
+) {
+    *tutorial = Tutorial::default();
+    if config.mode != MatchMode::Tutorial {
+        return;
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .insert(OnTutorialHud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        tutorial.prompt(),
+                        TextStyle {
+                            font: asset_server.load("fonts/GoMono-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(PromptLabel);
+        });
+}
+
+/// Keeps the snake where it is until the player has learnt to swing the sword.
+fn hold_snake(
+    config: Res<MatchConfig>,
+    tutorial: Res<Tutorial>,
+    mut snake_timer: ResMut<SnakeTimer>,
+) {
+    if config.mode != MatchMode::Tutorial {
+        return;
+    }
+    if tutorial.step < TutorialStep::Sever {
+        snake_timer.0.pause();
+    } else {
+        snake_timer.0.unpause();
+    }
+}
+
+/// Moves on to the next step once the player has done what the current one asks.
+fn follow_steps(
+    (config, tick_input): (Res<MatchConfig>, Res<TickInput>),
+    mut tutorial: ResMut<Tutorial>,
+    mut outcome: ResMut<MatchOutcome>,
+    mut swings: EventReader<SwordSwingEvent>,
+    mut severed: EventReader<SnakeSeveredEvent>,
+    mut match_over: EventWriter<MatchOverEvent>,
+) {
+    if config.mode != MatchMode::Tutorial {
+        return;
+    }
+    let input = tick_input.slayer_input(0);
+    let swung: Vec<Direction> = swings.iter().map(|swing| swing.direction).collect();
+    let cut = severed.iter().next().is_some();
+    match tutorial.step {
+        TutorialStep::Run => {
+            for (pressed, direction) in [
+                (input.left, Direction::Left),
+                (input.right, Direction::Right),
+            ] {
+                if pressed && !tutorial.ran.contains(&direction) {
+                    tutorial.ran.push(direction);
+                }
+            }
+            if tutorial.ran.len() == 2 {
+                tutorial.advance();
+            }
+        }
+        TutorialStep::Jump => {
+            if input.up {
+                tutorial.advance();
+            }
+        }
+        TutorialStep::Attack => {
+            if !swung.is_empty() {
+                tutorial.advance();
+            }
+        }
+        TutorialStep::Aim => {
+            for direction in swung {
+                if !tutorial.aimed.contains(&direction) {
+                    tutorial.aimed.push(direction);
+                }
+            }
+            if tutorial.aimed.len() == Direction::ALL.len() {
+                tutorial.advance();
+            }
+        }
+        TutorialStep::Sever => {
+            if cut && outcome.0.is_none() {
+                tutorial.advance();
+                outcome.0 = Some(Side::Slayer);
+                match_over.send(MatchOverEvent {
+                    winner: Side::Slayer,
+                });
+            }
+        }
+        TutorialStep::Complete => {}
+    }
+}
+
+fn update_prompt(tutorial: Res<Tutorial>, mut prompts: Query<&mut Text, With<PromptLabel>>) {
+    if !tutorial.is_changed() {
+        return;
+    }
+    for mut text in prompts.iter_mut() {
+        text.sections[0].value = tutorial.prompt();
+    }
+}
+
+fn cleanup_tutorial_hud(mut commands: Commands, hud: Query<Entity, With<OnTutorialHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_aim_prompt_lists_the_directions_still_to_swing() {
+        let tutorial = Tutorial {
+            step: TutorialStep::Aim,
+            ran: Vec::new(),
+            aimed: vec![Direction::Up, Direction::Left],
+        };
+        let prompt = tutorial.prompt();
+        assert!(
+            prompt.ends_with("Still to swing: right, down"),
+            "{}",
+            prompt
+        );
+    }
+
+    #[test]
+    fn the_steps_end_with_the_tutorial_complete() {
+        let mut step = TutorialStep::Run;
+        let mut steps = vec![step];
+        while step != TutorialStep::Complete {
+            step = step.next();
+            steps.push(step);
+        }
+        assert_eq!(steps.len(), 6);
+        assert_eq!(TutorialStep::Complete.next(), TutorialStep::Complete);
+    }
+}
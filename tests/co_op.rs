@@ -0,0 +1,58 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::co_op::SlayerScores;
+use unfair_advantage_lib::snake_abilities::SnakeAbilities;
+use unfair_advantage_lib::snake_ai::SnakeAi;
+use unfair_advantage_lib::{MatchConfig, MatchMode, SlayerPlayer};
+
+fn slayer_x(game: &mut TestApp, player: usize) -> f32 {
+    let world = &mut game.app.world;
+    world
+        .query::<(&SlayerPlayer, &Transform)>()
+        .iter(world)
+        .find(|(slayer, _)| slayer.0 == player)
+        .map(|(_, transform)| transform.translation.x)
+        .unwrap()
+}
+
+#[test]
+fn co_op_has_two_slayers_and_an_ai_snake() {
+    let mut game = TestApp::new();
+    game.click("Start Co-op Game");
+    assert_eq!(
+        game.app.world.get_resource::<MatchConfig>().unwrap().mode,
+        MatchMode::CoOp
+    );
+    assert_eq!(game.count::<SlayerPlayer>(), 2);
+    assert_eq!(game.count::<SnakeAi>(), 1);
+    assert_eq!(game.count::<SnakeAbilities>(), 0);
+    assert_eq!(
+        game.app.world.get_resource::<SlayerScores>(),
+        Some(&SlayerScores::default())
+    );
+}
+
+#[test]
+fn each_slayer_has_their_own_keys() {
+    let mut game = TestApp::new();
+    game.click("Start Co-op Game");
+    game.run_ticks(10);
+    let first = slayer_x(&mut game, 0);
+    let second = slayer_x(&mut game, 1);
+
+    game.press(KeyCode::L);
+    game.run_ticks(10);
+    game.release(KeyCode::L);
+    assert_eq!(slayer_x(&mut game, 0), first);
+    assert!(slayer_x(&mut game, 1) > second);
+}
+
+#[test]
+fn versus_keeps_a_single_slayer() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    assert_eq!(game.count::<SlayerPlayer>(), 1);
+    assert_eq!(game.count::<SnakeAi>(), 0);
+}
@@ -0,0 +1,162 @@
+//! A headless harness for driving the whole game from integration tests.
+//!
+//! The game runs with `MinimalPlugins`, without a window, audio or rendering,
+//! and every call to [`TestApp::update`] advances the simulation by exactly one tick.
+
+// Not every test file uses every helper
+#![allow(dead_code)]
+
+use bevy::asset::AssetPlugin;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::ElementState;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use unfair_advantage_lib::handicap::WinHistoryPath;
+use unfair_advantage_lib::replay::ReplaySavePath;
+use unfair_advantage_lib::save_game::SaveGamePath;
+use unfair_advantage_lib::simulation::{ClockMode, SimulationClock};
+use unfair_advantage_lib::snake_grid::Snake;
+use unfair_advantage_lib::survival::SurvivalProgressPath;
+use unfair_advantage_lib::time_attack::TimeAttackDirectory;
+use unfair_advantage_lib::{AppState, SnakeSplitEvent, UnfairAdvantagePlugin};
+
+pub struct TestApp {
+    pub app: App,
+}
+
+impl TestApp {
+    /// Builds the game and runs its first update, which opens the main menu.
+    pub fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(InputPlugin)
+            .add_plugin(AssetPlugin)
+            .add_plugin(UnfairAdvantagePlugin::headless())
+            .insert_resource(ReplaySavePath(None))
+            .insert_resource(SaveGamePath(None))
+            .insert_resource(WinHistoryPath(None))
+            .insert_resource(SurvivalProgressPath(None))
+            .insert_resource(TimeAttackDirectory(None));
+        app.world
+            .get_resource_mut::<SimulationClock>()
+            .unwrap()
+            .set_mode(ClockMode::StepPerUpdate);
+
+        let mut test_app = Self { app };
+        test_app.update();
+        test_app
+    }
+
+    /// Runs one frame, which is also one simulation tick.
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// Runs `ticks` frames.
+    pub fn run_ticks(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    pub fn state(&self) -> AppState {
+        self.app
+            .world
+            .get_resource::<State<AppState>>()
+            .unwrap()
+            .current()
+            .clone()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.app
+            .world
+            .get_resource::<SimulationClock>()
+            .unwrap()
+            .tick()
+    }
+
+    /// Starts holding `key`, as of the next update.
+    pub fn press(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Pressed);
+    }
+
+    /// Lets go of `key`, as of the next update.
+    pub fn release(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Released);
+    }
+
+    /// Presses `key` for a single update.
+    pub fn tap(&mut self, key: KeyCode) {
+        self.press(key);
+        self.update();
+        self.release(key);
+    }
+
+    fn send_key(&mut self, key: KeyCode, state: ElementState) {
+        self.app
+            .world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key),
+                state,
+            });
+    }
+
+    /// Clicks the menu button labelled `label` and runs an update to react to it.
+    ///
+    /// # Panics
+    /// Panics if no such button is on screen.
+    pub fn click(&mut self, label: &str) {
+        let world = &mut self.app.world;
+        let buttons: Vec<(Entity, Vec<Entity>)> = world
+            .query_filtered::<(Entity, &Children), With<Button>>()
+            .iter(world)
+            .map(|(button, children)| (button, children.iter().copied().collect()))
+            .collect();
+        let button = buttons
+            .into_iter()
+            .find(|(_, children)| {
+                children.iter().any(|child| {
+                    world
+                        .get::<Text>(*child)
+                        .map_or(false, |text| text.sections[0].value == label)
+                })
+            })
+            .map(|(button, _)| button)
+            .unwrap_or_else(|| panic!("no button labelled {:?}", label));
+        *world.get_mut::<Interaction>(button).unwrap() = Interaction::Clicked;
+        self.update();
+    }
+
+    /// How many entities have a `T`.
+    pub fn count<T: Component>(&mut self) -> usize {
+        let world = &mut self.app.world;
+        world.query_filtered::<(), With<T>>().iter(world).count()
+    }
+
+    /// Cuts the head off every snake longer than that, which leaves a shorter snake behind.
+    pub fn cut_every_snake(&mut self) {
+        let world = &mut self.app.world;
+        let heads: Vec<Entity> = world
+            .query::<(Entity, &Snake)>()
+            .iter(world)
+            .filter(|(_, snake)| snake.len() > 1)
+            .map(|(head, _)| head)
+            .collect();
+        let mut events = world.get_resource_mut::<Events<SnakeSplitEvent>>().unwrap();
+        for snake in heads {
+            events.send(SnakeSplitEvent { snake, index: 1 });
+        }
+        self.update();
+    }
+
+    /// Every snake in the arena.
+    pub fn snakes(&mut self) -> Vec<Snake> {
+        let world = &mut self.app.world;
+        world.query::<&Snake>().iter(world).cloned().collect()
+    }
+}
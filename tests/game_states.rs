@@ -0,0 +1,298 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use heron::prelude::*;
+use unfair_advantage_lib::handicap::MatchOutcome;
+use unfair_advantage_lib::netplay::Side;
+use unfair_advantage_lib::rng::GameRng;
+use unfair_advantage_lib::simulation::{SimulationClock, SimulationStage, SimulationSystem};
+use unfair_advantage_lib::snake_grid::{Direction, Occupancy, Position, Snake};
+use unfair_advantage_lib::{cell_to_world, AppState, MatchConfig, SlayerPlayer};
+
+fn start_one_player_game() -> TestApp {
+    let mut game = TestApp::new();
+    assert_eq!(game.state(), AppState::MainMenu);
+    game.click("Start 1 Player Game");
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+    game
+}
+
+#[test]
+fn menus_lead_into_and_out_of_a_game() {
+    let mut game = start_one_player_game();
+    assert_eq!(game.count::<Snake>(), 1);
+
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::PauseMenu);
+
+    game.click("Resume");
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+
+    game.tap(KeyCode::P);
+    assert_eq!(game.state(), AppState::PauseMenu);
+
+    game.click("Quit Game");
+    assert_eq!(game.state(), AppState::MainMenu);
+}
+
+#[test]
+fn quitting_a_game_cleans_up_its_entities() {
+    let mut game = start_one_player_game();
+    assert!(game.count::<Position>() > 0);
+
+    game.tap(KeyCode::Escape);
+    game.click("Quit Game");
+
+    assert_eq!(game.count::<Snake>(), 0);
+    assert_eq!(game.count::<Position>(), 0);
+    assert_eq!(game.count::<Sprite>(), 0);
+    assert_eq!(game.count::<TextureAtlasSprite>(), 0);
+}
+
+#[test]
+fn matches_are_seeded_from_the_seed_picked_on_the_menu() {
+    let mut game = TestApp::new();
+    game.click("Seed: 0");
+    game.update();
+    let seed = game.app.world.get_resource::<MatchConfig>().unwrap().seed;
+    assert_ne!(seed, 0);
+
+    game.click("Start 1 Player Game");
+    assert_eq!(
+        game.app.world.get_resource::<MatchConfig>().unwrap().seed,
+        seed
+    );
+    assert_eq!(
+        game.app.world.get_resource::<GameRng>().unwrap(),
+        &GameRng::new(seed)
+    );
+}
+
+#[test]
+fn the_simulation_stops_once_the_match_is_decided() {
+    let mut game = start_one_player_game();
+    game.run_ticks(10);
+    // Cutting the snake down to its head wins a versus match for the slayer
+    game.cut_every_snake();
+    game.update();
+    assert_eq!(
+        game.app.world.get_resource::<MatchOutcome>().unwrap().0,
+        Some(Side::Slayer)
+    );
+    let snakes = game.snakes();
+    let tick = game.tick();
+    game.run_ticks(60);
+    assert_eq!(game.tick(), tick);
+    assert_eq!(game.snakes(), snakes);
+
+    // The next match starts from the beginning again
+    game.tap(KeyCode::Return);
+    assert_eq!(game.state(), AppState::MainMenu);
+    game.click("Start 1 Player Game");
+    game.run_ticks(10);
+    assert!(game.tick() > 0);
+}
+
+#[test]
+fn pausing_stops_the_simulation() {
+    let mut game = start_one_player_game();
+    game.tap(KeyCode::Escape);
+    let snakes = game.snakes();
+    let tick = game.tick();
+    game.run_ticks(60);
+    assert_eq!(game.snakes(), snakes);
+    assert_eq!(game.tick(), tick);
+
+    game.click("Resume");
+    game.run_ticks(10);
+    assert!(game.tick() > tick);
+}
+
+fn clock(game: &mut TestApp) -> Mut<SimulationClock> {
+    game.app
+        .world
+        .get_resource_mut::<SimulationClock>()
+        .unwrap()
+}
+
+fn slayer_transform(game: &mut TestApp) -> Transform {
+    let world = &mut game.app.world;
+    *world
+        .query_filtered::<&Transform, With<SlayerPlayer>>()
+        .iter(world)
+        .next()
+        .unwrap()
+}
+
+#[test]
+fn the_slayer_only_moves_while_the_clock_runs() {
+    let mut game = start_one_player_game();
+    // The slayer starts out in mid-air
+    game.run_ticks(10);
+    let falling = slayer_transform(&mut game);
+    game.run_ticks(1);
+    assert_ne!(slayer_transform(&mut game), falling);
+
+    clock(&mut game).set_paused(true);
+    let paused = slayer_transform(&mut game);
+    game.run_ticks(60);
+    assert_eq!(slayer_transform(&mut game), paused);
+}
+
+#[test]
+fn landing_is_not_missed_between_ticks() {
+    let mut game = start_one_player_game();
+    clock(&mut game).set_paused(true);
+    // Run one tick every few frames, as during hit-stop, until the slayer has landed
+    for _ in 0..200 {
+        clock(&mut game).step_once();
+        game.update();
+        game.run_ticks(3);
+    }
+    clock(&mut game).set_paused(false);
+
+    let landed = slayer_transform(&mut game);
+    game.press(KeyCode::W);
+    game.run_ticks(5);
+    assert!(slayer_transform(&mut game).translation.y > landed.translation.y);
+}
+
+#[test]
+fn leaning_on_a_wall_does_not_let_the_slayer_climb_it() {
+    let mut game = start_one_player_game();
+    game.run_ticks(120);
+    let landed = slayer_transform(&mut game).translation;
+    // A tall wall right next to the slayer
+    game.app
+        .world
+        .spawn()
+        .insert(Transform::from_xyz(landed.x + 48.0, landed.y + 500.0, 1.0))
+        .insert(GlobalTransform::default())
+        .insert(RigidBody::Static)
+        .insert(CollisionShape::Cuboid {
+            half_extends: Vec3::new(16.0, 500.0, 0.0),
+            border_radius: None,
+        });
+
+    game.press(KeyCode::D);
+    let mut highest = landed.y;
+    for _ in 0..20 {
+        game.tap(KeyCode::W);
+        game.run_ticks(10);
+        highest = highest.max(slayer_transform(&mut game).translation.y);
+    }
+    // A single jump from the ground rises a little under 270 units
+    assert!(highest > landed.y + 100.0);
+    assert!(highest < landed.y + 300.0);
+}
+
+#[test]
+fn snake_moves_one_cell_per_snake_timer() {
+    let mut game = start_one_player_game();
+    let start = game.tick();
+    // The snake timer fires every 0.4 seconds, which is just over 24 ticks
+    game.run_ticks(100 - start);
+
+    let snakes = game.snakes();
+    assert_eq!(snakes.len(), 1);
+    assert_eq!(snakes[0].head(), Position::new(12, 9));
+    assert_eq!(snakes[0].body()[1], Position::new(11, 9));
+    assert_eq!(snakes[0].len(), 8);
+}
+
+/// Where the grid had things, and where they were placed, as each physics step began.
+#[derive(Default)]
+struct PhysicsPlacements(Vec<(Position, Vec3)>);
+
+fn record_physics_placements(
+    mut placements: ResMut<PhysicsPlacements>,
+    placed: Query<(&Position, &Transform)>,
+) {
+    for (position, transform) in placed.iter() {
+        placements.0.push((*position, transform.translation));
+    }
+}
+
+#[test]
+fn physics_finds_snakes_on_their_cells_while_they_are_drawn_sliding() {
+    let mut game = start_one_player_game();
+    game.app
+        .init_resource::<PhysicsPlacements>()
+        .add_system_to_stage(
+            SimulationStage,
+            record_physics_placements.after(SimulationSystem::Physics),
+        );
+    let mut sliding = false;
+    for _ in 0..60 {
+        game.run_ticks(1);
+        let world = &mut game.app.world;
+        sliding |=
+            world
+                .query::<(&Position, &Transform)>()
+                .iter(world)
+                .any(|(position, transform)| {
+                    transform.translation.truncate()
+                        != cell_to_world(position.x as f32, position.y as f32)
+                });
+    }
+    assert!(sliding);
+
+    let placements = &game
+        .app
+        .world
+        .get_resource::<PhysicsPlacements>()
+        .unwrap()
+        .0;
+    assert!(!placements.is_empty());
+    for (position, translation) in placements {
+        assert_eq!(
+            translation.truncate(),
+            cell_to_world(position.x as f32, position.y as f32)
+        );
+    }
+}
+
+#[test]
+fn snake_follows_scripted_turns() {
+    let mut game = start_one_player_game();
+    // The first move happens on tick 25, the second on tick 49
+    game.press(KeyCode::Up);
+    game.run_ticks(30);
+    game.release(KeyCode::Up);
+    game.press(KeyCode::Right);
+    game.run_ticks(30);
+    game.release(KeyCode::Right);
+
+    let snake = game.snakes()[0].clone();
+    assert_eq!(snake.direction(), Direction::Right);
+    assert_eq!(snake.head(), Position::new(9, 10));
+    assert_eq!(snake.body()[1], Position::new(8, 10));
+    assert_eq!(snake.body()[2], Position::new(8, 9));
+}
+
+#[test]
+fn occupancy_tracks_the_moving_snake() {
+    let mut game = start_one_player_game();
+    game.press(KeyCode::Up);
+    game.run_ticks(60);
+
+    let world = &mut game.app.world;
+    let (head, snake) = world
+        .query::<(Entity, &Snake)>()
+        .iter(world)
+        .map(|(head, snake)| (head, snake.clone()))
+        .next()
+        .unwrap();
+    let occupancy = world.get_resource::<Occupancy<Entity>>().unwrap();
+    assert!(snake
+        .body()
+        .iter()
+        .all(|cell| occupancy.get(*cell) == Some(head)));
+    let arena = occupancy.arena();
+    let occupied = (0..arena.width as i32)
+        .flat_map(|x| (0..arena.height as i32).map(move |y| Position::new(x, y)))
+        .filter(|cell| !occupancy.is_free(*cell))
+        .count();
+    assert_eq!(occupied, snake.len());
+}
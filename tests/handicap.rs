@@ -0,0 +1,143 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy::window::{ReceivedCharacter, WindowId};
+use common::TestApp;
+use unfair_advantage_lib::handicap::{
+    ExtraLives, Handicaps, MatchOverEvent, Players, WinHistory, WinHistoryPath,
+};
+use unfair_advantage_lib::netplay::Side;
+use unfair_advantage_lib::{AppState, MatchConfig};
+
+fn history_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir()
+        .join(format!("unfair-advantage-history-{}", std::process::id()))
+        .join(name)
+}
+
+fn texts(game: &mut TestApp) -> Vec<String> {
+    let world = &mut game.app.world;
+    world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+/// Types `text` into whatever has the keyboard, then runs an update to take it in.
+fn type_text(game: &mut TestApp, text: &str) {
+    let mut characters = game
+        .app
+        .world
+        .get_resource_mut::<Events<ReceivedCharacter>>()
+        .unwrap();
+    for char in text.chars() {
+        characters.send(ReceivedCharacter {
+            id: WindowId::primary(),
+            char,
+        });
+    }
+    game.update();
+}
+
+#[test]
+fn picked_handicaps_carry_into_the_match() {
+    let mut game = TestApp::new();
+    game.click("Versus Handicaps");
+    assert_eq!(game.state(), AppState::HandicapMenu);
+    game.click("Slayer lives: 1");
+    game.click("Sword damage: 1");
+    game.click("Sword damage: 2");
+    game.click("Snake speed: Normal");
+    game.click("Start Match");
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+
+    let config = game.app.world.get_resource::<MatchConfig>().unwrap();
+    assert_eq!(config.handicaps.extra_lives, 1);
+    assert_eq!(config.handicaps.sword_damage, 3);
+    let world = &mut game.app.world;
+    let lives = *world.query::<&ExtraLives>().iter(world).next().unwrap();
+    assert_eq!(lives, ExtraLives(1));
+}
+
+#[test]
+fn suggestions_come_from_the_win_history() {
+    let path = history_path("suggested.txt");
+    let mut history = WinHistory::default();
+    history.record(&Players::default(), Side::Snake, Handicaps::default());
+    history.record(&Players::default(), Side::Snake, Handicaps::default());
+    history.save(&path).unwrap();
+
+    let mut game = TestApp::new();
+    game.app.insert_resource(WinHistoryPath(Some(path.clone())));
+    game.click("Versus Handicaps");
+    let screen = texts(&mut game);
+    assert!(screen.contains(&"Last 2 matches: slayer 0, snake 2".to_string()));
+    assert!(screen.contains(&"Slayer lives: 2".to_string()));
+
+    game.click("Start Match");
+    game.app
+        .world
+        .get_resource_mut::<Events<MatchOverEvent>>()
+        .unwrap()
+        .send(MatchOverEvent {
+            winner: Side::Slayer,
+        });
+    game.update();
+    assert!(texts(&mut game).contains(&"The slayer wins!".to_string()));
+    let recorded = WinHistory::load(&path).unwrap();
+    assert_eq!(recorded.results.len(), 3);
+    assert_eq!(recorded.results[2].winner, Side::Slayer);
+    assert_eq!(recorded.results[2].handicaps.extra_lives, 1);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn suggestions_follow_the_names_entered() {
+    let path = history_path("named.txt");
+    let players = Players {
+        slayer: "Ana".to_string(),
+        snake: "Bo".to_string(),
+    };
+    let mut history = WinHistory::default();
+    history.record(&players, Side::Slayer, Handicaps::default());
+    history.record(&players, Side::Slayer, Handicaps::default());
+    history.save(&path).unwrap();
+
+    let mut game = TestApp::new();
+    game.app.insert_resource(WinHistoryPath(Some(path.clone())));
+    game.click("Versus Handicaps");
+    // The history has nothing on two players who have not named themselves
+    assert!(texts(&mut game).contains(&"Slayer lives: 1".to_string()));
+    assert!(texts(&mut game).contains(&"No versus matches between these players yet".to_string()));
+
+    game.click("Slayer player: ");
+    type_text(&mut game, "Anx");
+    game.tap(KeyCode::Back);
+    type_text(&mut game, "a");
+    game.tap(KeyCode::Return);
+    game.click("Snake player: ");
+    type_text(&mut game, "Bo");
+    assert!(texts(&mut game).contains(&"Snake player: Bo_".to_string()));
+    game.click("Start Match");
+
+    let config = game.app.world.get_resource::<MatchConfig>().unwrap();
+    assert_eq!(
+        config.handicaps.snake_pace,
+        Handicaps::default().favor(Side::Snake).snake_pace
+    );
+    assert_eq!(*game.app.world.get_resource::<Players>().unwrap(), players);
+    game.app
+        .world
+        .get_resource_mut::<Events<MatchOverEvent>>()
+        .unwrap()
+        .send(MatchOverEvent {
+            winner: Side::Snake,
+        });
+    game.update();
+    let recorded = WinHistory::load(&path).unwrap();
+    assert_eq!(recorded.results[2].players, players);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::TestApp;
+use unfair_advantage_lib::launcher::{LaunchOptions, RunSummary};
+use unfair_advantage_lib::level::Level;
+use unfair_advantage_lib::snake_grid::{Direction, Position, Snake};
+use unfair_advantage_lib::{AppState, MatchConfig};
+
+fn launch(args: &[&str]) -> TestApp {
+    let options = LaunchOptions::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+    let mut game = TestApp::new();
+    options.configure(&mut game.app).unwrap();
+    game.update();
+    game
+}
+
+#[test]
+fn starts_straight_into_a_match_on_the_chosen_level() {
+    let directory =
+        std::env::temp_dir().join(format!("unfair-advantage-launcher-{}", std::process::id()));
+    let path = directory.join("corridor.level");
+    let level = Level {
+        snake: Snake::new(
+            vec![Position::new(3, 3), Position::new(2, 3)],
+            Direction::Right,
+        ),
+        ..Default::default()
+    };
+    level.save(&path).unwrap();
+
+    let mut game = launch(&[
+        "--start",
+        "one",
+        "--seed",
+        "77",
+        "--level",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+    assert_eq!(
+        game.app.world.get_resource::<MatchConfig>(),
+        Some(&MatchConfig {
+            seed: 77,
+            level: "corridor".to_string(),
+            ..Default::default()
+        })
+    );
+    assert_eq!(game.snakes(), vec![level.snake]);
+
+    game.run_ticks(50);
+    let summary = RunSummary::capture(&mut game.app.world);
+    assert_eq!(summary.state, AppState::InOnePlayerGame);
+    assert_eq!(summary.snake_lengths, vec![2]);
+    assert!(summary.slayer.is_some());
+    assert!(summary.to_string().contains("seed:   77"));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn broken_level_files_are_reported() {
+    let options =
+        LaunchOptions::parse(["--level".to_string(), "missing.level".to_string()]).unwrap();
+    let mut game = TestApp::new();
+    assert!(options.configure(&mut game.app).is_err());
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::level::{Level, LevelDirectory};
+use unfair_advantage_lib::snake_grid::{Direction, Position, Snake};
+use unfair_advantage_lib::AppState;
+
+fn level(game: &TestApp) -> Level {
+    game.app.world.get_resource::<Level>().unwrap().clone()
+}
+
+#[test]
+fn test_play_round_trips_through_the_level_file() {
+    let directory =
+        std::env::temp_dir().join(format!("unfair-advantage-levels-{}", std::process::id()));
+    let mut game = TestApp::new();
+    game.app.insert_resource(LevelDirectory(directory.clone()));
+    game.click("Level Editor");
+    assert_eq!(game.state(), AppState::LevelEditor);
+    assert_eq!(level(&game), Level::default());
+
+    // Stand in for a few strokes of the mouse
+    let edited = {
+        let mut level = game.app.world.get_resource_mut::<Level>().unwrap();
+        level.snake = Snake::new(
+            vec![
+                Position::new(5, 5),
+                Position::new(5, 4),
+                Position::new(6, 4),
+            ],
+            Direction::Up,
+        );
+        level.obstacles.push(Position::new(10, 2));
+        level.background = None;
+        level.clone()
+    };
+
+    game.tap(KeyCode::F5);
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+    assert!(directory.join("custom.level").exists());
+    assert_eq!(level(&game), edited);
+    assert_eq!(game.snakes(), vec![edited.snake.clone()]);
+
+    game.tap(KeyCode::F5);
+    assert_eq!(game.state(), AppState::LevelEditor);
+    assert_eq!(level(&game), edited);
+    assert_eq!(game.count::<Snake>(), 0);
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn the_snake_cannot_be_turned_back_into_its_neck() {
+    let mut game = TestApp::new();
+    game.click("Level Editor");
+    assert_eq!(level(&game).snake.direction(), Direction::Right);
+
+    game.tap(KeyCode::Left);
+    assert_eq!(level(&game).snake.direction(), Direction::Right);
+    game.tap(KeyCode::Up);
+    assert_eq!(level(&game).snake.direction(), Direction::Up);
+    assert_eq!(level(&game).snake.body(), Level::default().snake.body());
+}
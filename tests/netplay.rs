@@ -0,0 +1,196 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use common::TestApp;
+use heron::Velocity;
+use unfair_advantage_lib::level::{Level, LevelDirectory};
+use unfair_advantage_lib::netplay::{LinkConditions, Netplay};
+use unfair_advantage_lib::simulation::SimulationClock;
+use unfair_advantage_lib::snake_grid::{Direction, Position};
+use unfair_advantage_lib::{AppState, MatchConfig, SlayerPlayer};
+
+const TICKS: u64 = 200;
+
+fn netplay(game: &TestApp) -> Option<&Netplay> {
+    game.app.world.get_resource::<Netplay>()
+}
+
+/// Updates both peers until each has simulated exactly `TICKS` ticks of the match.
+fn play_until_done(host: &mut TestApp, joiner: &mut TestApp) {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    loop {
+        let mut done = true;
+        for game in [&mut *host, &mut *joiner] {
+            if game.state() == AppState::InOnePlayerGame {
+                // Step one tick at a time, so neither peer catches up past the last one. Once
+                // there, keep talking so the peer gets every input it needs
+                let mut clock = game
+                    .app
+                    .world
+                    .get_resource_mut::<SimulationClock>()
+                    .unwrap();
+                if !clock.is_paused() {
+                    clock.set_paused(true);
+                }
+                if clock.tick() < TICKS {
+                    done = false;
+                    if clock.overstep() == Duration::ZERO {
+                        clock.step_once();
+                    }
+                }
+            } else {
+                done = false;
+            }
+            game.update();
+        }
+        if done {
+            return;
+        }
+        assert!(Instant::now() < deadline, "the match stalled");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn slayer(game: &mut TestApp) -> (Transform, Velocity) {
+    let world = &mut game.app.world;
+    world
+        .query_filtered::<(&Transform, &Velocity), With<SlayerPlayer>>()
+        .iter(world)
+        .map(|(transform, velocity)| (*transform, *velocity))
+        .next()
+        .unwrap()
+}
+
+#[test]
+fn peers_on_a_bad_connection_play_the_same_match() {
+    let conditions = LinkConditions {
+        latency: Duration::from_millis(40),
+        packet_loss: 0.25,
+    };
+    let config = MatchConfig {
+        seed: 1234,
+        ..Default::default()
+    };
+    let mut host = TestApp::new();
+    let mut joiner = TestApp::new();
+    let listening = Netplay::host(
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        config.clone(),
+        conditions,
+    )
+    .unwrap();
+    let address = listening.local_addr().unwrap();
+    host.app.insert_resource(listening);
+    joiner
+        .app
+        .insert_resource(Netplay::join(address, conditions).unwrap());
+
+    // The host plays the slayer and the joiner the snake, so these keys are theirs to press
+    host.press(KeyCode::D);
+    joiner.press(KeyCode::Up);
+    // Keys that belong to the other side are ignored
+    host.press(KeyCode::Down);
+    joiner.press(KeyCode::A);
+    play_until_done(&mut host, &mut joiner);
+
+    assert_eq!(host.app.world.get_resource::<MatchConfig>(), Some(&config));
+    assert_eq!(
+        joiner.app.world.get_resource::<MatchConfig>(),
+        Some(&config)
+    );
+    assert_eq!(host.tick(), TICKS);
+    assert_eq!(joiner.tick(), TICKS);
+    assert_eq!(host.snakes(), joiner.snakes());
+    assert_eq!(host.snakes()[0].direction(), Direction::Up);
+    assert_eq!(slayer(&mut host), slayer(&mut joiner));
+    assert!(netplay(&host).unwrap().lockstep().is_some());
+}
+
+#[test]
+fn leaving_the_match_sends_the_peer_back_to_the_menu() {
+    let mut host = TestApp::new();
+    let mut joiner = TestApp::new();
+    let listening = Netplay::host(
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        MatchConfig::default(),
+        LinkConditions::default(),
+    )
+    .unwrap();
+    let address = listening.local_addr().unwrap();
+    host.app.insert_resource(listening);
+    joiner
+        .app
+        .insert_resource(Netplay::join(address, LinkConditions::default()).unwrap());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while host.state() != AppState::InOnePlayerGame || joiner.state() != AppState::InOnePlayerGame {
+        assert!(Instant::now() < deadline, "the peers never met");
+        host.update();
+        joiner.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    joiner.tap(KeyCode::Escape);
+    joiner.click("Quit Game");
+    assert!(netplay(&joiner).is_none());
+    while host.state() != AppState::MainMenu {
+        assert!(Instant::now() < deadline, "the host never noticed");
+        host.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(netplay(&host).is_none());
+}
+
+#[test]
+fn joiners_with_a_different_copy_of_the_level_turn_the_match_down() {
+    let root =
+        std::env::temp_dir().join(format!("unfair-advantage-netplay-{}", std::process::id()));
+    let mut level = Level::default();
+    level.obstacles.push(Position::new(20, 2));
+    level.save(root.join("host").join("custom.level")).unwrap();
+    level.obstacles.push(Position::new(21, 2));
+    level
+        .save(root.join("joiner").join("custom.level"))
+        .unwrap();
+
+    let mut host = TestApp::new();
+    let mut joiner = TestApp::new();
+    host.app.insert_resource(LevelDirectory(root.join("host")));
+    joiner
+        .app
+        .insert_resource(LevelDirectory(root.join("joiner")));
+    let config = MatchConfig {
+        level: "custom".to_string(),
+        ..Default::default()
+    };
+    let listening = Netplay::host(
+        SocketAddr::from(([127, 0, 0, 1], 0)),
+        config,
+        LinkConditions::default(),
+    )
+    .unwrap();
+    let address = listening.local_addr().unwrap();
+    host.app.insert_resource(listening);
+    joiner
+        .app
+        .insert_resource(Netplay::join(address, LinkConditions::default()).unwrap());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while netplay(&joiner).is_some() {
+        assert!(Instant::now() < deadline, "the joiner never heard back");
+        host.update();
+        joiner.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(joiner.state(), AppState::MainMenu);
+    while host.state() != AppState::MainMenu || netplay(&host).is_some() {
+        assert!(Instant::now() < deadline, "the host never noticed");
+        host.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::TestApp;
+use heron::{CollisionLayers, Velocity};
+use unfair_advantage_lib::power_ups::{Buffs, Pickup, PowerUp, PowerUpCollectedEvent};
+use unfair_advantage_lib::simulation::TICKS_PER_SECOND;
+use unfair_advantage_lib::snake_grid::Position;
+use unfair_advantage_lib::{cell_to_world, SlayerPlayer, SnakeSlowdown, SnakeTimer};
+
+fn snake_timer_duration(game: &TestApp) -> Duration {
+    game.app
+        .world
+        .get_resource::<SnakeTimer>()
+        .unwrap()
+        .0
+        .duration()
+}
+
+fn slowdown(game: &TestApp) -> SnakeSlowdown {
+    *game.app.world.get_resource::<SnakeSlowdown>().unwrap()
+}
+
+fn slayers(game: &mut TestApp) -> Vec<Entity> {
+    let world = &mut game.app.world;
+    world
+        .query_filtered::<Entity, With<Buffs>>()
+        .iter(world)
+        .collect()
+}
+
+fn grant(game: &mut TestApp, slayer: Entity, power_up: PowerUp) {
+    game.app
+        .world
+        .get_resource_mut::<Events<PowerUpCollectedEvent>>()
+        .unwrap()
+        .send(PowerUpCollectedEvent { slayer, power_up });
+}
+
+fn buffs(game: &mut TestApp) -> Buffs {
+    let world = &mut game.app.world;
+    world.query::<&Buffs>().iter(world).next().unwrap().clone()
+}
+
+#[test]
+fn pickups_turn_up_over_time() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    assert_eq!(game.count::<Pickup>(), 0);
+    // Keep the slayer from touching anything until the pickup has been placed
+    let slayer = slayers(&mut game)[0];
+    let layers = *game.app.world.get::<CollisionLayers>(slayer).unwrap();
+    game.app
+        .world
+        .entity_mut(slayer)
+        .insert(CollisionLayers::none());
+    game.run_ticks(9 * TICKS_PER_SECOND as u64);
+    assert_eq!(game.count::<Pickup>(), 1);
+    assert_eq!(buffs(&mut game), Buffs::default());
+
+    // Then put the slayer right on top of it
+    let world = &mut game.app.world;
+    let (pickup, position) = world
+        .query::<(&Pickup, &Position)>()
+        .iter(world)
+        .map(|(pickup, position)| (*pickup, *position))
+        .next()
+        .unwrap();
+    let mut slayer = world.entity_mut(slayer);
+    // It has been falling through the floor all this time
+    slayer.insert(layers).insert(Velocity::default());
+    slayer.get_mut::<Transform>().unwrap().translation =
+        cell_to_world(position.x as f32, position.y as f32).extend(1.0);
+    game.run_ticks(3);
+    assert_eq!(game.count::<Pickup>(), 0);
+    assert!(buffs(&mut game).is_active(pickup.0));
+}
+
+#[test]
+fn slow_snake_wears_off() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    let normal = snake_timer_duration(&game);
+
+    let slayer = slayers(&mut game)[0];
+    grant(&mut game, slayer, PowerUp::SlowSnake);
+    game.run_ticks(2);
+    assert!(buffs(&mut game).is_active(PowerUp::SlowSnake));
+    assert!(slowdown(&game).0 > 1);
+    // The slow is applied as the timer runs, so its interval is left alone
+    assert_eq!(snake_timer_duration(&game), normal);
+    let world = &mut game.app.world;
+    let hud: Vec<String> = world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect();
+    assert!(hud.iter().any(|line| line.starts_with("Slow snake")));
+
+    let ticks = PowerUp::SlowSnake.duration().as_secs_f32() * TICKS_PER_SECOND as f32;
+    game.run_ticks(ticks as u64 + 1);
+    assert!(!buffs(&mut game).is_active(PowerUp::SlowSnake));
+    assert_eq!(slowdown(&game), SnakeSlowdown::default());
+    assert_eq!(snake_timer_duration(&game), normal);
+}
+
+#[test]
+fn slow_snake_does_not_stack_or_outlast_its_slayer() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    let slayer = slayers(&mut game)[0];
+    grant(&mut game, slayer, PowerUp::SlowSnake);
+    game.run_ticks(2);
+    let once = slowdown(&game);
+
+    let mut game = TestApp::new();
+    game.click("Start Co-op Game");
+    let both = slayers(&mut game);
+    assert_eq!(both.len(), 2);
+    for slayer in both.iter() {
+        grant(&mut game, *slayer, PowerUp::SlowSnake);
+    }
+    game.run_ticks(2);
+    assert_eq!(slowdown(&game), once);
+
+    // One slayer leaving keeps the snake slowed for the other
+    game.app.world.despawn(both[0]);
+    game.run_ticks(2);
+    assert_eq!(slowdown(&game), once);
+
+    // Once the last buffed slayer is gone, so is the slow
+    game.app.world.despawn(both[1]);
+    game.run_ticks(2);
+    assert_eq!(slowdown(&game), SnakeSlowdown::default());
+}
+
+#[test]
+fn each_co_op_slayer_has_their_own_power_up_row() {
+    let mut game = TestApp::new();
+    game.click("Start Co-op Game");
+    let world = &mut game.app.world;
+    let second = world
+        .query::<(Entity, &SlayerPlayer)>()
+        .iter(world)
+        .find(|(_, player)| player.0 == 1)
+        .map(|(entity, _)| entity)
+        .unwrap();
+    grant(&mut game, second, PowerUp::QuickAttack);
+    game.run_ticks(2);
+
+    let world = &mut game.app.world;
+    let hud: Vec<String> = world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect();
+    assert!(hud.iter().any(|line| line == "P2"));
+    assert!(!hud.iter().any(|line| line == "P1"));
+    assert_eq!(
+        hud.iter()
+            .filter(|line| line.starts_with("Quick sword"))
+            .count(),
+        1
+    );
+}
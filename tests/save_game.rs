@@ -0,0 +1,155 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use heron::Velocity;
+use unfair_advantage_lib::save_game::{PendingRestore, SaveGamePath, SaveMatchEvent, SavedMatch};
+use unfair_advantage_lib::{AppState, SlayerPlayer};
+
+fn save_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir()
+        .join(format!("unfair-advantage-saves-{}", std::process::id()))
+        .join(name)
+}
+
+/// Steers the snake around for a while, so that it is part of the way between moves.
+fn play(game: &mut TestApp, turns: &[KeyCode]) {
+    for key in turns {
+        game.press(*key);
+        game.run_ticks(20);
+        game.release(*key);
+    }
+}
+
+fn slayer(game: &mut TestApp) -> (Transform, Velocity) {
+    let world = &mut game.app.world;
+    world
+        .query_filtered::<(&Transform, &Velocity), With<SlayerPlayer>>()
+        .iter(world)
+        .map(|(transform, velocity)| (*transform, *velocity))
+        .next()
+        .unwrap()
+}
+
+fn labels(game: &mut TestApp) -> Vec<String> {
+    let world = &mut game.app.world;
+    world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+#[test]
+fn continued_matches_play_out_like_the_original() {
+    let path = save_path("continued.save");
+    let mut original = TestApp::new();
+    original.click("Start 1 Player Game");
+    play(&mut original, &[KeyCode::Up, KeyCode::Left, KeyCode::Down]);
+    let saved = SavedMatch::capture(&mut original.app.world);
+    saved.save(&path).unwrap();
+
+    play(&mut original, &[KeyCode::Left, KeyCode::Up, KeyCode::Right]);
+
+    let mut continued = TestApp::new();
+    continued
+        .app
+        .insert_resource(SaveGamePath(Some(path.clone())));
+    // The main menu has to be opened again to notice the save
+    continued.click("Level Editor");
+    continued.tap(KeyCode::Escape);
+    assert_eq!(continued.state(), AppState::MainMenu);
+    // The original held left from the first tick after the save
+    continued.press(KeyCode::Left);
+    continued.click("Continue Saved Game");
+    assert_eq!(continued.state(), AppState::InOnePlayerGame);
+    assert_eq!(continued.tick(), saved.tick + 1);
+    continued.run_ticks(19);
+    continued.release(KeyCode::Left);
+    play(&mut continued, &[KeyCode::Up, KeyCode::Right]);
+
+    assert_eq!(continued.tick(), original.tick());
+    assert_eq!(continued.snakes(), original.snakes());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn saving_from_the_pause_menu_returns_to_the_main_menu() {
+    let path = save_path("paused.save");
+    let mut game = TestApp::new();
+    game.app.insert_resource(SaveGamePath(Some(path.clone())));
+    game.click("Start 1 Player Game");
+    play(&mut game, &[KeyCode::Up]);
+    let snakes = game.snakes();
+
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::PauseMenu);
+    game.click("Save and Quit");
+    // The save is written on the frame after the click
+    game.update();
+    assert_eq!(game.state(), AppState::MainMenu);
+
+    let saved = SavedMatch::load(&path).unwrap();
+    assert_eq!(
+        saved
+            .snakes
+            .iter()
+            .map(|saved| saved.snake.clone())
+            .collect::<Vec<_>>(),
+        snakes
+    );
+    assert!(saved.slayer.is_some());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn restored_slayers_carry_on_falling_like_the_original() {
+    let mut original = TestApp::new();
+    original.click("Start 1 Player Game");
+    original.run_ticks(30);
+    let saved = SavedMatch::capture(&mut original.app.world);
+    let bytes = saved.to_bytes();
+    original.run_ticks(30);
+
+    let mut continued = TestApp::new();
+    let saved = SavedMatch::from_bytes(&bytes).unwrap();
+    continued.app.insert_resource(saved.config.clone());
+    continued.app.insert_resource(PendingRestore(saved));
+    continued
+        .app
+        .world
+        .get_resource_mut::<State<AppState>>()
+        .unwrap()
+        .set(AppState::InOnePlayerGame)
+        .unwrap();
+    continued.run_ticks(30);
+
+    assert_eq!(continued.tick(), original.tick());
+    assert_eq!(continued.snakes(), original.snakes());
+    let (transform, velocity) = slayer(&mut original);
+    // Still in the air, so no contacts came into it
+    assert!(velocity.linear.y < 0.0);
+    assert_eq!(slayer(&mut continued), (transform, velocity));
+}
+
+#[test]
+fn time_attack_runs_cannot_be_saved() {
+    let path = save_path("time_attack.save");
+    let mut game = TestApp::new();
+    game.app.insert_resource(SaveGamePath(Some(path.clone())));
+    game.click("Time Attack");
+    game.tap(KeyCode::Escape);
+    assert_eq!(game.state(), AppState::PauseMenu);
+    assert!(!labels(&mut game).contains(&"Save and Quit".to_string()));
+
+    game.app
+        .world
+        .get_resource_mut::<Events<SaveMatchEvent>>()
+        .unwrap()
+        .send(SaveMatchEvent);
+    game.update();
+    assert_eq!(game.state(), AppState::PauseMenu);
+    assert!(!path.exists());
+}
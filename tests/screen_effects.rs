@@ -0,0 +1,67 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::effects::{CameraShake, ScreenEffects};
+use unfair_advantage_lib::snake_grid::Position;
+use unfair_advantage_lib::{SlayerPlayer, SwordHitEvent};
+
+fn hit_the_snake(game: &mut TestApp) {
+    let world = &mut game.app.world;
+    let snake = world
+        .query_filtered::<Entity, With<unfair_advantage_lib::SnakeHead>>()
+        .iter(world)
+        .next()
+        .unwrap();
+    let slayer = world
+        .query_filtered::<Entity, With<SlayerPlayer>>()
+        .iter(world)
+        .next()
+        .unwrap();
+    world
+        .get_resource_mut::<Events<SwordHitEvent>>()
+        .unwrap()
+        .send(SwordHitEvent {
+            slayer,
+            snake,
+            index: 0,
+            position: Position::new(8, 9),
+        });
+    game.update();
+}
+
+fn trauma(game: &TestApp) -> f32 {
+    game.app
+        .world
+        .get_resource::<CameraShake>()
+        .unwrap()
+        .trauma()
+}
+
+#[test]
+fn hits_shake_the_camera() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    hit_the_snake(&mut game);
+    assert!(trauma(&game) > 0.0);
+}
+
+#[test]
+fn screen_effects_can_be_turned_off() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+
+    game.tap(KeyCode::Escape);
+    game.click("Screen Effects: Full");
+    // The label catches up with the setting on the following update
+    game.update();
+    game.click("Screen Effects: Low");
+    assert_eq!(
+        *game.app.world.get_resource::<ScreenEffects>().unwrap(),
+        ScreenEffects::Off
+    );
+    game.click("Resume");
+
+    hit_the_snake(&mut game);
+    assert_eq!(trauma(&game), 0.0);
+}
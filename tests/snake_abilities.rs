@@ -0,0 +1,72 @@
+mod common;
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::simulation::TICKS_PER_SECOND;
+use unfair_advantage_lib::snake_abilities::{SnakeAbilities, SnakeAbility};
+use unfair_advantage_lib::{SnakeSpeed, SnakeTimer};
+
+fn snake_timer_duration(game: &TestApp) -> Duration {
+    game.app
+        .world
+        .get_resource::<SnakeTimer>()
+        .unwrap()
+        .0
+        .duration()
+}
+
+fn speed(game: &mut TestApp) -> SnakeSpeed {
+    let world = &mut game.app.world;
+    *world
+        .query_filtered::<&SnakeSpeed, With<SnakeAbilities>>()
+        .iter(world)
+        .next()
+        .unwrap()
+}
+
+fn abilities(game: &mut TestApp) -> SnakeAbilities {
+    let world = &mut game.app.world;
+    world
+        .query::<&SnakeAbilities>()
+        .iter(world)
+        .next()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn speed_burst_wears_off() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    let normal = snake_timer_duration(&game);
+
+    game.tap(KeyCode::Slash);
+    game.run_ticks(1);
+    assert!(speed(&mut game).0 > 1);
+    assert!(!abilities(&mut game).is_ready(SnakeAbility::Burst));
+    // Only the snake player's snake speeds up
+    assert_eq!(snake_timer_duration(&game), normal);
+
+    game.run_ticks(3 * TICKS_PER_SECOND as u64);
+    assert_eq!(speed(&mut game), SnakeSpeed::default());
+}
+
+#[test]
+fn armor_goes_on_cooldown() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+
+    game.tap(KeyCode::RControl);
+    game.run_ticks(1);
+    let armored = abilities(&mut game);
+    assert!(armored.is_armored());
+    assert!(!armored.is_ready(SnakeAbility::Armor));
+    assert!(armored.is_ready(SnakeAbility::Lunge));
+
+    game.run_ticks(4 * TICKS_PER_SECOND as u64);
+    let worn_off = abilities(&mut game);
+    assert!(!worn_off.is_armored());
+    assert!(!worn_off.is_ready(SnakeAbility::Armor));
+}
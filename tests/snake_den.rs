@@ -0,0 +1,82 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::level::Level;
+use unfair_advantage_lib::simulation::TICKS_PER_SECOND;
+use unfair_advantage_lib::snake_den::{DenSnake, SnakeDen, DEN_HEALTH};
+use unfair_advantage_lib::snake_grid::Direction;
+use unfair_advantage_lib::{cell_to_world, AppState, MatchConfig, SlayerPlayer, SwordSwingEvent};
+
+fn den(game: &mut TestApp) -> Option<SnakeDen> {
+    let world = &mut game.app.world;
+    world.query::<&SnakeDen>().iter(world).next().cloned()
+}
+
+/// Starts a match in the "nest", the built-in level with two dens.
+fn start_in_nest() -> TestApp {
+    let mut game = TestApp::new();
+    game.app.insert_resource(MatchConfig {
+        level: "nest".to_string(),
+        ..Default::default()
+    });
+    game.app
+        .world
+        .get_resource_mut::<State<AppState>>()
+        .unwrap()
+        .set(AppState::InOnePlayerGame)
+        .unwrap();
+    game.update();
+    game
+}
+
+#[test]
+fn the_default_level_has_no_dens() {
+    let mut game = TestApp::new();
+    game.click("Start 1 Player Game");
+    assert_eq!(game.count::<SnakeDen>(), 0);
+}
+
+#[test]
+fn dens_let_out_snakes_sooner_and_sooner() {
+    let mut game = start_in_nest();
+    assert_eq!(game.count::<SnakeDen>(), 2);
+    assert_eq!(game.count::<DenSnake>(), 0);
+    let first_wait = den(&mut game).unwrap().until_next;
+
+    game.run_ticks(first_wait.as_secs() * TICKS_PER_SECOND as u64 + 1);
+    assert_eq!(game.count::<DenSnake>(), 2);
+    let den = den(&mut game).unwrap();
+    assert_eq!(den.spawned, 1);
+    assert!(den.until_next < first_wait);
+}
+
+#[test]
+fn the_slayer_can_destroy_a_den() {
+    let mut game = start_in_nest();
+    let cell = game.app.world.get_resource::<Level>().unwrap().dens[0];
+    let world = &mut game.app.world;
+    let slayer = world
+        .query_filtered::<Entity, With<SlayerPlayer>>()
+        .iter(world)
+        .next()
+        .unwrap();
+
+    for _ in 0..DEN_HEALTH {
+        assert_eq!(game.count::<SnakeDen>(), 2);
+        // Stand just left of the den and swing at it
+        let world = &mut game.app.world;
+        world.get_mut::<Transform>(slayer).unwrap().translation =
+            cell_to_world((cell.x - 1) as f32, cell.y as f32).extend(1.0);
+        world
+            .get_resource_mut::<Events<SwordSwingEvent>>()
+            .unwrap()
+            .send(SwordSwingEvent {
+                slayer,
+                direction: Direction::Right,
+            });
+        game.update();
+    }
+    game.update();
+    assert_eq!(game.count::<SnakeDen>(), 1);
+}
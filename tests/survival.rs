@@ -0,0 +1,51 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::snake_abilities::SnakeAbilities;
+use unfair_advantage_lib::snake_ai::SnakeAi;
+use unfair_advantage_lib::snake_den::SnakeDen;
+use unfair_advantage_lib::survival::wave;
+use unfair_advantage_lib::{AppState, MatchConfig, MatchMode};
+
+fn config(game: &TestApp) -> MatchConfig {
+    game.app
+        .world
+        .get_resource::<MatchConfig>()
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn the_campaign_starts_with_the_first_wave() {
+    let mut game = TestApp::new();
+    game.click("Survival Campaign");
+    let config = config(&game);
+    assert_eq!(config.mode, MatchMode::Survival);
+    assert_eq!(config.wave, 1);
+    assert_eq!(config.level, wave(1).arena);
+    assert_eq!(game.count::<SnakeAi>(), wave(1).snakes.len());
+    assert_eq!(game.count::<SnakeAbilities>(), 0);
+    assert_eq!(game.count::<SnakeDen>(), 0);
+}
+
+#[test]
+fn clearing_a_wave_leads_to_the_next() {
+    let mut game = TestApp::new();
+    game.click("Survival Campaign");
+    for _ in 0..20 {
+        if game.state() != AppState::InOnePlayerGame {
+            break;
+        }
+        game.cut_every_snake();
+    }
+    assert_eq!(game.state(), AppState::WaveIntermission);
+    assert!(game.snakes().is_empty());
+
+    game.tap(KeyCode::Return);
+    assert_eq!(game.state(), AppState::InOnePlayerGame);
+    let config = config(&game);
+    assert_eq!(config.wave, 2);
+    assert_eq!(config.level, wave(2).arena);
+    assert_eq!(game.count::<SnakeAi>(), wave(2).snakes.len());
+}
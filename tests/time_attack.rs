@@ -0,0 +1,75 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::level::Level;
+use unfair_advantage_lib::snake_ai::SnakeAi;
+use unfair_advantage_lib::snake_den::SnakeDen;
+use unfair_advantage_lib::time_attack::{PersonalBest, TimeAttackDirectory, TimeAttackRun};
+use unfair_advantage_lib::{AppState, MatchConfig, MatchMode};
+
+fn best_directory() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "unfair-advantage-time-attack-{}",
+        std::process::id()
+    ))
+}
+
+fn texts(game: &mut TestApp) -> Vec<String> {
+    let world = &mut game.app.world;
+    world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+/// Cuts up every snake; the default level has no dens to let out more.
+fn clear_arena(game: &mut TestApp) {
+    assert_eq!(game.count::<SnakeDen>(), 0);
+    for _ in 0..20 {
+        game.cut_every_snake();
+    }
+}
+
+#[test]
+fn clearing_the_level_sets_a_personal_best() {
+    let directory = best_directory();
+    let _ = std::fs::remove_dir_all(&directory);
+    let mut game = TestApp::new();
+    game.app
+        .world
+        .insert_resource(TimeAttackDirectory(Some(directory.clone())));
+    game.click("Time Attack");
+    let config = game
+        .app
+        .world
+        .get_resource::<MatchConfig>()
+        .unwrap()
+        .clone();
+    assert_eq!(config.mode, MatchMode::TimeAttack);
+    assert_eq!(game.count::<SnakeAi>(), 1);
+
+    clear_arena(&mut game);
+    assert!(game.snakes().is_empty());
+    let run = game.app.world.get_resource::<TimeAttackRun>().unwrap();
+    let ticks = run.finished.expect("the run should be over");
+    // Every cut leaves one more snake, down to single segments
+    assert_eq!(run.splits.len(), Level::default().snake.len());
+    assert!(run.new_best);
+    assert!(texts(&mut game).contains(&"New best time!".to_string()));
+
+    let best = PersonalBest::load(PersonalBest::path(&directory, &config.level))
+        .unwrap()
+        .unwrap();
+    assert_eq!(best.ticks, ticks);
+    assert_eq!(best.ghost.len() as u64, ticks);
+
+    // The next run races the ghost of this one
+    game.tap(KeyCode::Return);
+    assert_eq!(game.state(), AppState::MainMenu);
+    game.click("Time Attack");
+    let run = game.app.world.get_resource::<TimeAttackRun>().unwrap();
+    assert_eq!(run.best.as_ref(), Some(&best));
+    let _ = std::fs::remove_dir_all(&directory);
+}
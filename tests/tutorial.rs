@@ -0,0 +1,83 @@
+mod common;
+
+use bevy::prelude::*;
+use common::TestApp;
+use unfair_advantage_lib::simulation::{SLAYER_KEYS, TICKS_PER_SECOND};
+use unfair_advantage_lib::snake_ai::SnakeAi;
+use unfair_advantage_lib::snake_den::SnakeDen;
+use unfair_advantage_lib::tutorial::{Tutorial, TutorialStep};
+use unfair_advantage_lib::{AppState, Invulnerable, MatchConfig, MatchMode};
+
+fn step(game: &TestApp) -> TutorialStep {
+    game.app.world.get_resource::<Tutorial>().unwrap().step
+}
+
+fn texts(game: &mut TestApp) -> Vec<String> {
+    let world = &mut game.app.world;
+    world
+        .query::<&Text>()
+        .iter(world)
+        .map(|text| text.sections[0].value.clone())
+        .collect()
+}
+
+/// Waits out the sword's cooldown, then swings it while holding `aim`, if anything.
+fn swing(game: &mut TestApp, aim: Option<KeyCode>) {
+    let keys = SLAYER_KEYS[0];
+    game.run_ticks(TICKS_PER_SECOND as u64);
+    if let Some(aim) = aim {
+        game.press(aim);
+    }
+    game.tap(keys.attack);
+    if let Some(aim) = aim {
+        game.release(aim);
+    }
+    game.update();
+}
+
+#[test]
+fn the_tutorial_moves_on_as_each_step_is_done() {
+    let keys = SLAYER_KEYS[0];
+    let mut game = TestApp::new();
+    game.click("Tutorial");
+    let config = game.app.world.get_resource::<MatchConfig>().unwrap();
+    assert_eq!(config.mode, MatchMode::Tutorial);
+    assert_eq!(game.count::<SnakeAi>(), 1);
+    assert_eq!(game.count::<SnakeDen>(), 0);
+    assert_eq!(game.count::<Invulnerable>(), 1);
+    assert_eq!(step(&game), TutorialStep::Run);
+    let prompt = Tutorial::default().prompt();
+    assert!(texts(&mut game).contains(&prompt));
+
+    // Running one way is not enough
+    game.tap(keys.left);
+    game.update();
+    assert_eq!(step(&game), TutorialStep::Run);
+    game.tap(keys.right);
+    game.update();
+    assert_eq!(step(&game), TutorialStep::Jump);
+
+    game.tap(keys.up);
+    game.update();
+    assert_eq!(step(&game), TutorialStep::Attack);
+
+    let snakes = game.snakes();
+    swing(&mut game, None);
+    assert_eq!(step(&game), TutorialStep::Aim);
+    for aim in [keys.up, keys.down, keys.left] {
+        swing(&mut game, Some(aim));
+        assert_eq!(step(&game), TutorialStep::Aim);
+    }
+    swing(&mut game, Some(keys.right));
+    assert_eq!(step(&game), TutorialStep::Sever);
+    // The snake waits until it is time to cut it
+    assert_eq!(game.snakes(), snakes);
+
+    game.cut_every_snake();
+    game.run_ticks(2);
+    assert_eq!(step(&game), TutorialStep::Complete);
+    assert!(texts(&mut game).contains(&"Tutorial complete!".to_string()));
+
+    game.tap(KeyCode::Return);
+    assert_eq!(game.state(), AppState::MainMenu);
+}